@@ -31,15 +31,29 @@ fn main() -> std::io::Result<()> {
     if let Some(("polish", sub_m)) = matches.subcommand() {
         return polish(sub_m);
     }
+    if let Some(("diff", sub_m)) = matches.subcommand() {
+        return diff(sub_m);
+    }
+    if let Some(("inspect-chunk", sub_m)) = matches.subcommand() {
+        return inspect_chunk(sub_m);
+    }
+    if let Some(("compare_models", sub_m)) = matches.subcommand() {
+        return compare_models(sub_m);
+    }
     let mut ds = get_input_file()?;
     let ds = &mut ds;
     match matches.subcommand() {
         Some(("select_chunks", sub_m)) => select_chunks(sub_m, ds),
         Some(("mask_repeats", sub_m)) => repeat_masking(sub_m, ds),
+        Some(("trio_binning", sub_m)) => trio_binning(sub_m, ds).unwrap(),
+        Some(("hic", sub_m)) => hic(sub_m, ds).unwrap(),
+        Some(("phase", sub_m)) => phase(sub_m, ds).unwrap(),
+        Some(("locate_chunks", sub_m)) => locate_chunks(sub_m, ds).unwrap(),
         Some(("encode", sub_m)) => encode(sub_m, ds),
+        Some(("add_reads", sub_m)) => add_reads(sub_m, ds).unwrap(),
         Some(("pick_components", sub_m)) => pick_components(sub_m, ds),
         Some(("polish_encoding", sub_m)) => polish_encode(sub_m, ds),
-        Some(("partition_local", sub_m)) => local_clustering(sub_m, ds),
+        Some(("partition_local", sub_m)) => local_clustering(sub_m, ds).unwrap(),
         Some(("purge_diverged", sub_m)) => purge_diverged(sub_m, ds),
         Some(("correct_deletion", sub_m)) => correct_deletion(sub_m, ds),
         Some(("estimate_multiplicity", sub_m)) => multiplicity_estimation(sub_m, ds),
@@ -49,6 +63,8 @@ fn main() -> std::io::Result<()> {
         Some(("extract", sub_m)) => extract(sub_m, ds).unwrap(),
         Some(("stats", sub_m)) => stats(sub_m, ds).unwrap(),
         Some(("squish", sub_m)) => squish(sub_m, ds),
+        Some(("export_model", sub_m)) => export_model(sub_m, ds).unwrap(),
+        Some(("import_model", sub_m)) => import_model(sub_m, ds).unwrap(),
         _ => unreachable!(),
     };
     flush_file(ds)
@@ -59,6 +75,32 @@ fn entry(matches: &clap::ArgMatches) -> std::io::Result<DataSet> {
     debug!("START\tEntry");
     set_threads(matches);
     let file = matches.value_of("input").unwrap();
+    let seqs = parse_reads(file)?;
+    let read_type = matches.value_of("read_type").unwrap();
+    let ploidy: usize = matches
+        .value_of("ploidy")
+        .and_then(|x| x.parse().ok())
+        .unwrap();
+    if ploidy == 0 {
+        eprintln!("The ploidy should be positive.");
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    }
+    let seed: u64 = matches
+        .value_of("seed")
+        .and_then(|x| x.parse().ok())
+        .unwrap();
+    let aligner = match matches.value_of("aligner") {
+        Some("builtin") => definitions::Aligner::Builtin,
+        _ => definitions::Aligner::Minimap2,
+    };
+    let mut ds = DataSet::entry(file, seqs, read_type);
+    ds.ploidy = ploidy;
+    ds.seed = seed;
+    ds.aligner = aligner;
+    Ok(ds)
+}
+
+fn parse_reads(file: &str) -> std::io::Result<Vec<(String, Vec<u8>)>> {
     let reader = std::fs::File::open(file).map(BufReader::new)?;
     debug!("Opening {}", file);
     let seqs: Vec<(String, Vec<u8>)> = match file.chars().last() {
@@ -82,8 +124,7 @@ fn entry(matches: &clap::ArgMatches) -> std::io::Result<DataSet> {
             .collect(),
         _ => panic!("file type:{} not supported", file),
     };
-    let read_type = matches.value_of("read_type").unwrap();
-    Ok(DataSet::entry(file, seqs, read_type))
+    Ok(seqs)
 }
 
 fn extract(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
@@ -138,7 +179,24 @@ fn select_chunks(matches: &clap::ArgMatches, dataset: &mut DataSet) {
     set_threads(matches);
     use haplotyper::determine_chunks::{DetermineUnit, DetermineUnitConfig};
     let (cl, tn) = (chunk_len, take_num);
-    let config = DetermineUnitConfig::new(cl, tn, margin, thrds, filter, purge_copy_num, seed);
+    let mut config = DetermineUnitConfig::new(cl, tn, margin, thrds, filter, purge_copy_num, seed);
+    if let Some(reference) = matches.value_of("reference") {
+        config.set_reference(reference);
+    }
+    let min_chunk_len: Option<usize> = matches
+        .value_of("min_chunk_len")
+        .and_then(|e| e.parse().ok());
+    let max_chunk_len: Option<usize> = matches
+        .value_of("max_chunk_len")
+        .and_then(|e| e.parse().ok());
+    if min_chunk_len.is_some() || max_chunk_len.is_some() {
+        let min_len = min_chunk_len.unwrap_or(chunk_len);
+        let max_len = max_chunk_len.unwrap_or(chunk_len);
+        config.set_adaptive_length(min_len, max_len);
+    }
+    if let Some(prefix) = matches.value_of("report") {
+        config.set_report(prefix);
+    }
     dataset.select_chunks(&config);
 }
 
@@ -146,8 +204,8 @@ fn repeat_masking(matches: &clap::ArgMatches, dataset: &mut DataSet) {
     debug!("START\tmasking repeat.");
     set_threads(matches);
     let k: usize = matches.value_of("k").and_then(|l| l.parse().ok()).unwrap();
-    if k > 32 {
-        panic!("K should be less than 32.");
+    if k > haplotyper::kmer_count::MAX_K {
+        panic!("K should be less than 64.");
     }
     let freq: f64 = matches
         .value_of("freq")
@@ -157,8 +215,33 @@ fn repeat_masking(matches: &clap::ArgMatches, dataset: &mut DataSet) {
         .value_of("min")
         .and_then(|l| l.parse().ok())
         .unwrap();
+    let max_period: usize = matches
+        .value_of("max_period")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let min_copies: f64 = matches
+        .value_of("min_copies")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let min_entropy: f64 = matches
+        .value_of("min_entropy")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let spectrum_k: usize = matches
+        .value_of("spectrum_k")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    if spectrum_k > haplotyper::kmer_count::MAX_K {
+        panic!("K should be less than 64.");
+    }
     use haplotyper::repeat_masking::*;
-    let config = RepeatMaskConfig::new(k, freq, min);
+    let mut config = RepeatMaskConfig::new(k, freq, min);
+    config.set_tandem_repeat(max_period, min_copies);
+    config.set_low_complexity(min_entropy);
+    if let Some(path) = matches.value_of("bed") {
+        config.set_bed_output(path);
+    }
+    config.set_kmer_spectrum(spectrum_k, matches.value_of("spectrum"));
     dataset.mask_repeat(&config)
 }
 
@@ -175,7 +258,42 @@ fn encode(matches: &clap::ArgMatches, dataset: &mut DataSet) {
         Some(res) => res,
         None => rt.sim_thr(),
     };
-    dataset.encode(threads, sim_thr, rt.sd_of_error())
+    dataset.encode(threads, sim_thr, rt.sd_of_error());
+    if let Some(max_dev) = matches
+        .value_of("discard_outliers")
+        .and_then(|e| e.parse().ok())
+    {
+        use haplotyper::estimate_error_rate::discard_outlier_reads;
+        let discarded = discard_outlier_reads(dataset, max_dev);
+        debug!("ENCODE\tDiscarded\t{discarded}");
+    }
+}
+
+fn add_reads(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
+    debug!("START\tAdding reads");
+    set_threads(matches);
+    let threads: usize = matches
+        .value_of("threads")
+        .and_then(|e| e.parse::<usize>().ok())
+        .unwrap();
+    let sim_thr = match matches.value_of("sim_thr").and_then(|e| e.parse().ok()) {
+        Some(res) => res,
+        None => dataset.read_type.sim_thr(),
+    };
+    let min_change: f64 = matches
+        .value_of("min_change")
+        .and_then(|e| e.parse().ok())
+        .unwrap();
+    if !dataset.coverage.is_available() {
+        eprintln!("The dataset should be encoded and clustered first.");
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    }
+    let reads = parse_reads(matches.value_of("input").unwrap())?;
+    use haplotyper::add_reads::*;
+    let config = AddReadsConfig::new(threads, sim_thr, min_change);
+    let reclustered = dataset.add_reads(reads, &config)?;
+    debug!("ADDREADS\tReclustered\t{}", reclustered.len());
+    Ok(())
 }
 
 fn polish_encode(matches: &clap::ArgMatches, dataset: &mut DataSet) {
@@ -218,11 +336,122 @@ fn multiplicity_estimation(matches: &clap::ArgMatches, dataset: &mut DataSet) {
     }
 }
 
-fn local_clustering(matches: &clap::ArgMatches, dataset: &mut DataSet) {
+fn trio_binning(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
+    debug!("START\tTrio-binning");
+    set_threads(matches);
+    let k: usize = matches.value_of("k").and_then(|l| l.parse().ok()).unwrap();
+    if k > haplotyper::kmer_count::MAX_K {
+        eprintln!("K should be less than 64.");
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    }
+    let min_count: u32 = matches
+        .value_of("min_count")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let min_hapmers: u32 = matches
+        .value_of("min_hapmers")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let min_ratio: f64 = matches
+        .value_of("min_ratio")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let maternal: Vec<String> = matches
+        .values_of("maternal")
+        .unwrap()
+        .map(|x| x.to_string())
+        .collect();
+    let paternal: Vec<String> = matches
+        .values_of("paternal")
+        .unwrap()
+        .map(|x| x.to_string())
+        .collect();
+    use haplotyper::trio_binning::*;
+    let config = TrioBinningConfig::new(k, min_count, min_hapmers, min_ratio);
+    let kmers = ParentalKmers::from_files(&maternal, &paternal, &config)?;
+    dataset.trio_binning(&kmers, &config);
+    Ok(())
+}
+
+fn hic(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
+    debug!("START\tHi-C mapping");
+    set_threads(matches);
+    let min_mapq: u8 = matches
+        .value_of("min_mapq")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let r1 = matches.value_of("r1").unwrap();
+    let r2 = matches.value_of("r2").unwrap();
+    use haplotyper::hic::*;
+    dataset.register_hic_pairs(r1, r2)?;
+    let config = HiCConfig::new(rayon::current_num_threads(), min_mapq);
+    dataset.map_hic_pairs(&config)
+}
+
+fn phase(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
+    debug!("START\tGlobal phasing");
+    set_threads(matches);
+    let min_span_reads: usize = matches
+        .value_of("min_span_reads")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let min_consistency: f64 = matches
+        .value_of("min_consistency")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let prefix = matches.value_of("output").unwrap();
+    use haplotyper::phasing::*;
+    let config = PhasingConfig::new(min_span_reads, min_consistency);
+    let mut result = dataset.phase(&config);
+    std::fs::write(format!("{prefix}.blocks.tsv"), result.blocks_tsv())?;
+    std::fs::write(format!("{prefix}.sites.tsv"), result.sites_tsv())?;
+    let flagged = result.flagged_chunks();
+    if matches.is_present("recluster") && !flagged.is_empty() {
+        use haplotyper::local_clustering::*;
+        let mut lc_config = LocalClusteringConfig::default();
+        lc_config.set_read_seeds(result.read_seeds());
+        dataset.local_clustering_selected_with(&flagged, &lc_config);
+        result = dataset.phase(&config);
+    }
+    dataset.phased_nodes = result.phased_nodes();
+    Ok(())
+}
+
+fn locate_chunks(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
+    debug!("START\tLocating chunks");
+    set_threads(matches);
+    let threads: usize = matches
+        .value_of("threads")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let min_identity: f64 = matches
+        .value_of("min_identity")
+        .and_then(|l| l.parse().ok())
+        .unwrap();
+    let path = matches.value_of("reference").unwrap();
+    use haplotyper::locate_chunks::*;
+    let reference = load_reference(path)?;
+    let config = LocateChunksConfig::new(threads, min_identity);
+    dataset.locate_chunks(&reference, &config)?;
+    Ok(())
+}
+
+fn local_clustering(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
     debug!("START\tLocal Clustering step");
     set_threads(matches);
     use haplotyper::local_clustering::*;
-    dataset.local_clustering();
+    let strategy: ClusteringStrategy = matches
+        .value_of("clusterer")
+        .and_then(|s| s.parse().ok())
+        .unwrap();
+    let mut config = LocalClusteringConfig::new(strategy, matches.value_of("diagnostics"));
+    if let (Some(vcf), Some(reference)) = (matches.value_of("vcf"), matches.value_of("reference")) {
+        let threads = rayon::current_num_threads();
+        let prior = variant_prior::VariantPrior::from_vcf(dataset, vcf, reference, threads)?;
+        config.set_variant_prior(prior);
+    }
+    dataset.local_clustering_with(&config);
+    Ok(())
 }
 
 fn purge_diverged(matches: &clap::ArgMatches, dataset: &mut DataSet) {
@@ -317,7 +546,7 @@ fn assembly(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Resul
     let skip_polish = matches.is_present("no_polish");
     let file = matches.value_of("output").unwrap();
     use haplotyper::assemble::*;
-    let config = AssembleConfig::new(
+    let mut config = AssembleConfig::new(
         window_size,
         !skip_polish,
         true,
@@ -326,13 +555,41 @@ fn assembly(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Resul
         true,
         Some(file),
     );
+    if matches.is_present("sequential_names") {
+        config.set_contig_naming(ContigNaming::Sequential);
+    }
+    if let Some(entries) = matches.values_of("threshold") {
+        let mut overrides = thresholds::ThresholdOverrides::default();
+        for entry in entries {
+            if let Err(why) = overrides.set(entry) {
+                eprintln!("{why}");
+                return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+            }
+        }
+        config.set_threshold_overrides(overrides);
+    }
+    match matches.value_of("bandage") {
+        Some("copy_number") => config.set_bandage_export(Some(ColorScheme::CopyNumber)),
+        Some("phase_block") => config.set_bandage_export(Some(ColorScheme::PhaseBlock)),
+        _ => {}
+    }
     debug!("START\tFinal assembly");
     if !skip_polish {
         dataset.fit_models_on_both_strands();
     }
     let gfa = dataset.assemble(&config);
-    let mut file = std::fs::File::create(format!("{file}.gfa")).map(BufWriter::new)?;
-    writeln!(file, "{}", gfa)?;
+    let mut wtr = std::fs::File::create(format!("{file}.gfa")).map(BufWriter::new)?;
+    writeln!(wtr, "{}", gfa)?;
+    let mut wtr = std::fs::File::create(format!("{file}.fa")).map(BufWriter::new)?;
+    writeln!(wtr, "{}", to_fasta(&gfa))?;
+    if !dataset.phased_nodes.is_empty() {
+        let haplotypes = haplotyper::assemble::to_haplotype_fasta(&gfa, dataset.ploidy);
+        for (i, fasta) in haplotypes.iter().enumerate() {
+            let mut wtr =
+                std::fs::File::create(format!("{file}.hap{}.fa", i + 1)).map(BufWriter::new)?;
+            writeln!(wtr, "{fasta}")?;
+        }
+    }
     Ok(())
 }
 
@@ -354,6 +611,62 @@ fn polish(matches: &clap::ArgMatches) -> std::io::Result<()> {
     polish_segmnents(reads, contig, alignments, format, window_size, seed)
 }
 
+fn diff(matches: &clap::ArgMatches) -> std::io::Result<()> {
+    use haplotyper::assemble::AssemblySummary;
+    let open = |path: &str| -> std::io::Result<AssemblySummary> {
+        let rdr = std::fs::File::open(path).map(BufReader::new)?;
+        serde_json::de::from_reader(rdr).map_err(std::io::Error::from)
+    };
+    let first = open(matches.value_of("first").unwrap())?;
+    let second = open(matches.value_of("second").unwrap())?;
+    let diff = haplotyper::assembly_diff::diff(&first, &second);
+    if let Some(path) = matches.value_of("json") {
+        let wtr = std::fs::File::create(path).map(BufWriter::new)?;
+        serde_json::ser::to_writer_pretty(wtr, &diff)?;
+    }
+    let stdout = std::io::stdout();
+    let mut wtr = BufWriter::new(stdout.lock());
+    writeln!(wtr, "{diff}")
+}
+
+fn inspect_chunk(matches: &clap::ArgMatches) -> std::io::Result<()> {
+    use haplotyper::local_clustering::diagnostics::ChunkDiagnostics;
+    let path = matches.value_of("input").unwrap();
+    let rdr = std::fs::File::open(path).map(BufReader::new)?;
+    let diagnostics: ChunkDiagnostics = serde_json::de::from_reader(rdr)?;
+    let stdout = std::io::stdout();
+    let mut wtr = BufWriter::new(stdout.lock());
+    writeln!(wtr, "{diagnostics}")?;
+    writeln!(
+        wtr,
+        "{}",
+        diagnostics.format_matrix(matches.is_present("values"))
+    )
+}
+
+fn export_model(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
+    debug!("START\tExporting the model");
+    let path = matches.value_of("file").unwrap();
+    haplotyper::model_library::write_model(&dataset.model_param, path)
+}
+
+fn import_model(matches: &clap::ArgMatches, dataset: &mut DataSet) -> std::io::Result<()> {
+    use haplotyper::model_library::{read_model, ImportModel};
+    debug!("START\tImporting the model");
+    let model = read_model(matches.value_of("model").unwrap())?;
+    dataset.import_model(model, matches.is_present("fixed"));
+    Ok(())
+}
+
+fn compare_models(matches: &clap::ArgMatches) -> std::io::Result<()> {
+    use haplotyper::model_library::{compare_models, read_model};
+    let first = read_model(matches.value_of("first").unwrap())?;
+    let second = read_model(matches.value_of("second").unwrap())?;
+    let stdout = std::io::stdout();
+    let mut wtr = BufWriter::new(stdout.lock());
+    writeln!(wtr, "{}", compare_models(&first, &second))
+}
+
 fn get_input_file() -> std::io::Result<DataSet> {
     let stdin = std::io::stdin();
     let reader = BufReader::new(stdin.lock());
@@ -27,6 +27,28 @@ fn subcommand_entry() -> Command<'static> {
                 .possible_values(["CCS", "CLR", "ONT"])
                 .help("Read type. CCS, CLR, or ONT."),
         )
+        .arg(
+            Arg::new("ploidy")
+                .long("ploidy")
+                .takes_value(true)
+                .default_value("2")
+                .help("The number of the haplotypes in the genome."),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .takes_value(true)
+                .default_value("42")
+                .help("Seed value for random number generators in the later stages."),
+        )
+        .arg(
+            Arg::new("aligner")
+                .long("aligner")
+                .takes_value(true)
+                .default_value("minimap2")
+                .possible_values(["minimap2", "builtin"])
+                .help("Aligner to encode the reads. `builtin` needs neither minimap2 nor write permission."),
+        )
         .arg(
             Arg::new("threads")
                 .short('t')
@@ -144,6 +166,31 @@ fn subcommand_select_chunk() -> Command<'static> {
                 .takes_value(true)
                 .default_value("42"),
         )
+        .arg(
+            Arg::new("reference")
+                .long("reference")
+                .takes_value(true)
+                .help("Tile chunks on this reference (FASTA) instead of picking them from reads."),
+        )
+        .arg(
+            Arg::new("min_chunk_len")
+                .long("min_chunk_len")
+                .takes_value(true)
+                .help("Shorten chunks down to [min_chunk_len] in diverged regions. Default: chunk_len"),
+        )
+        .arg(
+            Arg::new("max_chunk_len")
+                .long("max_chunk_len")
+                .takes_value(true)
+                .help("Extend chunks up to [max_chunk_len] in repetitive regions. Default: chunk_len"),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_name("PREFIX")
+                .takes_value(true)
+                .help("Write the removed chunks with the reasons to [PREFIX].tsv and [PREFIX].fa."),
+        )
 }
 
 fn subcommand_mask_repeats() -> Command<'static> {
@@ -168,7 +215,7 @@ fn subcommand_mask_repeats() -> Command<'static> {
         .arg(
             Arg::new("k")
                 .short('k')
-                .help("K-mer size(<32)")
+                .help("K-mer size(<64)")
                 .takes_value(true)
                 .default_value("12"),
         )
@@ -188,6 +235,247 @@ fn subcommand_mask_repeats() -> Command<'static> {
                 .takes_value(true)
                 .default_value("10"),
         )
+        .arg(
+            Arg::new("max_period")
+                .long("max_period")
+                .help("Also mask tandem repeats with period up to [max_period]. 0 disables it.")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("min_copies")
+                .long("min_copies")
+                .help("Do not mask tandem repeats with less than [min_copies] copies.")
+                .takes_value(true)
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("min_entropy")
+                .long("min_entropy")
+                .help("Also mask windows with normalized 3-mer entropy less than [min_entropy]. 0 disables it.")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("bed")
+                .long("bed")
+                .value_name("PATH")
+                .help("Write the masked intervals of the reads to [PATH] in BED format.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("spectrum_k")
+                .long("spectrum_k")
+                .help("Estimate the coverage and the heterozygosity from the [spectrum_k]-mer spectrum(<64). 0 disables it.")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("spectrum")
+                .long("spectrum")
+                .value_name("PATH")
+                .help("Write the k-mer histogram and the fitted mixture to [PATH] in TSV format.")
+                .takes_value(true),
+        )
+}
+
+fn subcommand_trio_binning() -> Command<'static> {
+    Command::new("trio_binning")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Label reads as maternal/paternal by the parental k-mers")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .short('t')
+                .help("Number of threads")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("maternal")
+                .long("maternal")
+                .required(true)
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("FASTQ")
+                .help("Short reads of the mother (FASTA/FASTQ, uncompressed). Can be given multiple times."),
+        )
+        .arg(
+            Arg::new("paternal")
+                .long("paternal")
+                .required(true)
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("FASTQ")
+                .help("Short reads of the father (FASTA/FASTQ, uncompressed). Can be given multiple times."),
+        )
+        .arg(
+            Arg::new("k")
+                .short('k')
+                .help("K-mer size(<64)")
+                .takes_value(true)
+                .default_value("21"),
+        )
+        .arg(
+            Arg::new("min_count")
+                .long("min_count")
+                .help("K-mers occurring less than [min_count] times in a parent are discarded.")
+                .takes_value(true)
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("min_hapmers")
+                .long("min_hapmers")
+                .help("Reads with less than [min_hapmers] parent-specific k-mers are not labelled.")
+                .takes_value(true)
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("min_ratio")
+                .long("min_ratio")
+                .help("Min ratio of the normalized hap-mer counts of the major parent to the other.")
+                .takes_value(true)
+                .default_value("2.0"),
+        )
+}
+
+fn subcommand_hic() -> Command<'static> {
+    Command::new("hic")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about(
+            "Map Hi-C pairs onto the clusters. They are used to phase the contigs in the assembly.",
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .short('t')
+                .help("Number of threads")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("r1")
+                .long("r1")
+                .required(true)
+                .takes_value(true)
+                .value_name("FASTQ")
+                .help("The first mates of the Hi-C pairs (FASTA/FASTQ)."),
+        )
+        .arg(
+            Arg::new("r2")
+                .long("r2")
+                .required(true)
+                .takes_value(true)
+                .value_name("FASTQ")
+                .help("The second mates of the Hi-C pairs, in the same order as --r1."),
+        )
+        .arg(
+            Arg::new("min_mapq")
+                .long("min_mapq")
+                .takes_value(true)
+                .default_value("5")
+                .help("Mates with mapping quality less than [min_mapq] are discarded."),
+        )
+}
+
+fn subcommand_phase() -> Command<'static> {
+    Command::new("phase")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Phase the clusters of the diploid chunks by the reads, and report the phase blocks.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .short('t')
+                .help("Number of threads")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .required(true)
+                .takes_value(true)
+                .value_name("PREFIX")
+                .help("Write the phase blocks to [PREFIX].blocks.tsv and the sites to [PREFIX].sites.tsv."),
+        )
+        .arg(
+            Arg::new("min_span_reads")
+                .long("min_span_reads")
+                .takes_value(true)
+                .default_value("3")
+                .help("Two chunks are linked if at least [min_span_reads] reads span both of them."),
+        )
+        .arg(
+            Arg::new("min_consistency")
+                .long("min_consistency")
+                .takes_value(true)
+                .default_value("0.7")
+                .help("Chunks agreeing with the phasing less than [min_consistency] are flagged."),
+        )
+        .arg(
+            Arg::new("recluster")
+                .long("recluster")
+                .help("Re-cluster the flagged chunks, seeded by the phased haplotypes of the reads."),
+        )
+}
+
+fn subcommand_locate_chunks() -> Command<'static> {
+    Command::new("locate_chunks")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Align the chunks to a reference and record their positions on it.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .short('t')
+                .help("Number of threads")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("reference")
+                .long("reference")
+                .short('r')
+                .required(true)
+                .takes_value(true)
+                .value_name("FASTA")
+                .help("The reference sequence."),
+        )
+        .arg(
+            Arg::new("min_identity")
+                .long("min_identity")
+                .takes_value(true)
+                .default_value("0.9")
+                .help("Discard alignments with identity less than [min_identity]."),
+        )
 }
 
 fn subcommand_encode() -> Command<'static> {
@@ -215,6 +503,55 @@ fn subcommand_encode() -> Command<'static> {
                 .help("similarity threshold")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("discard_outliers")
+                .long("discard_outliers")
+                .help("Discard reads whose error rate is more than [discard_outliers] SD above the median.")
+                .takes_value(true),
+        )
+}
+
+fn subcommand_add_reads() -> Command<'static> {
+    Command::new("add_reads")
+        .version("0.1")
+        .author("Bansho Masutani")
+        .about("Encode new reads by the current chunks, assign them to the clusters, and re-cluster the chunks whose depth changed. Re-run the assembly afterwards.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .short('t')
+                .help("Number of threads")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("input")
+                .long("input")
+                .short('i')
+                .required(true)
+                .takes_value(true)
+                .value_name("FILE")
+                .help("New reads in FASTA/FASTQ."),
+        )
+        .arg(
+            Arg::new("sim_thr")
+                .long("sim_thr")
+                .help("similarity threshold")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("min_change")
+                .long("min_change")
+                .takes_value(true)
+                .default_value("0.2")
+                .help("Re-cluster the chunks whose depth changed more than this fraction."),
+        )
 }
 
 fn subcommand_polish_encoding() -> Command<'static> {
@@ -344,6 +681,37 @@ fn subcommand_partition_local() -> Command<'static> {
                 .default_value("1")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("clusterer")
+                .long("clusterer")
+                .takes_value(true)
+                .default_value("mcmc")
+                .possible_values(["mcmc", "exact", "kmeans", "auto"])
+                .help("Clustering strategy. `auto` chooses one for each chunk."),
+        )
+        .arg(
+            Arg::new("diagnostics")
+                .long("diagnostics")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Write per-chunk clustering diagnostics to DIR/{chunk_id}.json"),
+        )
+        .arg(
+            Arg::new("vcf")
+                .long("vcf")
+                .takes_value(true)
+                .requires("reference")
+                .value_name("VCF")
+                .help("(Phased) VCF of heterozygous SNVs used as a prior of the clustering"),
+        )
+        .arg(
+            Arg::new("reference")
+                .long("reference")
+                .takes_value(true)
+                .requires("vcf")
+                .value_name("FASTA")
+                .help("Reference the VCF is based on. The chunks are aligned to it."),
+        )
 }
 
 fn subcommand_purge_diverged() -> Command<'static> {
@@ -598,6 +966,27 @@ fn subcommand_assemble() -> Command<'static> {
                 .required(false)
                 .help("Minimum required reads to span repeats"),
         )
+        .arg(
+            Arg::new("sequential_names")
+                .long("sequential_names")
+                .help("Name contigs tig_0000, tig_0001,... instead of by their contents."),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("NAME=VALUE")
+                .help("Fix a clean-up threshold (e.g., tip_frac=0.8) instead of deriving it from the coverage."),
+        )
+        .arg(
+            Arg::new("bandage")
+                .long("bandage")
+                .takes_value(true)
+                .value_name("COLOR")
+                .possible_values(["copy_number", "phase_block"])
+                .help("Add Bandage tags coloured by COLOR, and write a companion CSV and DOT files."),
+        )
         .arg(
             Arg::new("output")
                 .short('o')
@@ -685,6 +1074,151 @@ fn subcommand_polish() -> Command<'static> {
         )
 }
 
+fn subcommand_diff() -> Command<'static> {
+    Command::new("diff")
+        .version("0.1")
+        .author("BanshoMasutani")
+        .about("Compare two assemblies of the same dataset at the chunk level.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("first")
+                .short('a')
+                .long("first")
+                .required(true)
+                .takes_value(true)
+                .value_name("SUMMARY<JSON>")
+                .help("The summary (`*.summary.json`) of the first assembly."),
+        )
+        .arg(
+            Arg::new("second")
+                .short('b')
+                .long("second")
+                .required(true)
+                .takes_value(true)
+                .value_name("SUMMARY<JSON>")
+                .help("The summary (`*.summary.json`) of the second assembly."),
+        )
+        .arg(
+            Arg::new("json")
+                .short('j')
+                .long("json")
+                .required(false)
+                .takes_value(true)
+                .value_name("PATH")
+                .help("If given, write the difference to PATH in JSON format."),
+        )
+}
+
+fn subcommand_export_model() -> Command<'static> {
+    Command::new("export_model")
+        .version("0.1")
+        .author("BanshoMasutani")
+        .about("Write the pair-HMM model of the dataset into a JSON file.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("file")
+                .long("file")
+                .short('f')
+                .required(true)
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The output JSON file."),
+        )
+}
+
+fn subcommand_import_model() -> Command<'static> {
+    Command::new("import_model")
+        .version("0.1")
+        .author("BanshoMasutani")
+        .about("Use a pair-HMM model exported by `export_model`.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("model")
+                .long("model")
+                .short('m')
+                .required(true)
+                .takes_value(true)
+                .value_name("PATH")
+                .help("A JSON file of a trained model."),
+        )
+        .arg(
+            Arg::new("fixed")
+                .long("fixed")
+                .help("Never re-train the model. Otherwise, the training starts from the model."),
+        )
+}
+
+fn subcommand_compare_models() -> Command<'static> {
+    Command::new("compare_models")
+        .version("0.1")
+        .author("BanshoMasutani")
+        .about("Compare the transition and the emission tables of two pair-HMM models.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("first")
+                .short('a')
+                .long("first")
+                .required(true)
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The first model, a JSON file written by `export_model`."),
+        )
+        .arg(
+            Arg::new("second")
+                .short('b')
+                .long("second")
+                .required(true)
+                .takes_value(true)
+                .value_name("PATH")
+                .help("The second model, a JSON file written by `export_model`."),
+        )
+}
+
+fn subcommand_inspect_chunk() -> Command<'static> {
+    Command::new("inspect-chunk")
+        .version("0.1")
+        .author("BanshoMasutani")
+        .about("Print the clustering diagnostics of a chunk as a read x variant matrix.")
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .multiple_occurrences(true)
+                .help("Debug mode"),
+        )
+        .arg(
+            Arg::new("input")
+                .required(true)
+                .takes_value(true)
+                .value_name("DIAGNOSTICS<JSON>")
+                .help("The diagnostics written by `partition_local --diagnostics`."),
+        )
+        .arg(
+            Arg::new("values")
+                .long("values")
+                .help("Print the likelihood gains instead of their signs."),
+        )
+}
+
 fn subcommand_pipeline() -> Command<'static> {
     Command::new("pipeline")
         .version("0.1")
@@ -709,6 +1243,7 @@ pub fn jtk_parser() -> clap::Command<'static> {
         .subcommand(subcommand_stats())
         .subcommand(subcommand_select_chunk())
         .subcommand(subcommand_encode())
+        .subcommand(subcommand_add_reads())
         .subcommand(subcommand_polish_encoding())
         .subcommand(subcommand_estimate_multiplicity())
         .subcommand(subcommand_partition_local())
@@ -719,7 +1254,16 @@ pub fn jtk_parser() -> clap::Command<'static> {
         .subcommand(subcommand_assemble())
         .subcommand(subcommand_pick_components())
         .subcommand(subcommand_mask_repeats())
+        .subcommand(subcommand_trio_binning())
+        .subcommand(subcommand_hic())
+        .subcommand(subcommand_phase())
+        .subcommand(subcommand_locate_chunks())
         .subcommand(subcommand_squish())
         .subcommand(subcommand_polish())
+        .subcommand(subcommand_diff())
+        .subcommand(subcommand_inspect_chunk())
+        .subcommand(subcommand_export_model())
+        .subcommand(subcommand_import_model())
+        .subcommand(subcommand_compare_models())
         .subcommand(subcommand_pipeline())
 }
@@ -31,6 +31,59 @@ pub struct PipelineConfig {
     match_ari: f64,
     mismatch_ari: f64,
     required_count: usize,
+    #[serde(default)]
+    sequential_names: bool,
+    #[serde(default)]
+    cleanup_thresholds: haplotyper::assemble::thresholds::ThresholdOverrides,
+    #[serde(default)]
+    bandage: Option<String>,
+    #[serde(default)]
+    clusterer: Option<String>,
+    #[serde(default)]
+    maternal: Vec<String>,
+    #[serde(default)]
+    paternal: Vec<String>,
+    #[serde(default)]
+    hic_r1: Option<String>,
+    #[serde(default)]
+    hic_r2: Option<String>,
+    #[serde(default)]
+    global_phasing: bool,
+    #[serde(default = "default_ploidy")]
+    ploidy: usize,
+    #[serde(default)]
+    aligner: definitions::Aligner,
+    #[serde(default)]
+    reference: Option<String>,
+    #[serde(default)]
+    max_period: usize,
+    #[serde(default)]
+    min_entropy: f64,
+    #[serde(default = "default_spectrum_k")]
+    spectrum_k: usize,
+    #[serde(default)]
+    min_chunk_len: Option<usize>,
+    #[serde(default)]
+    max_chunk_len: Option<usize>,
+    #[serde(default)]
+    discard_error_outliers: Option<f64>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    fix_model: bool,
+}
+
+// Chunks aligned to the reference with lower identity than this value are not located.
+const MIN_IDENTITY: f64 = 0.9;
+// Tandem repeats with fewer copies than this value are not masked.
+const MIN_COPIES: f64 = 3f64;
+
+fn default_ploidy() -> usize {
+    2
+}
+
+fn default_spectrum_k() -> usize {
+    21
 }
 use haplotyper::{local_clustering::LocalClustering, *};
 use std::io::{BufReader, BufWriter, Write};
@@ -63,6 +116,26 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
         match_ari,
         mismatch_ari,
         required_count,
+        sequential_names,
+        cleanup_thresholds,
+        bandage,
+        clusterer,
+        maternal,
+        paternal,
+        hic_r1,
+        hic_r2,
+        global_phasing,
+        ploidy,
+        aligner,
+        reference,
+        max_period,
+        min_entropy,
+        spectrum_k,
+        min_chunk_len,
+        max_chunk_len,
+        discard_error_outliers,
+        model,
+        fix_model,
     } = config.clone();
     let level = match verbose {
         0 => "warn",
@@ -77,7 +150,8 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
     let clustered = format!("{file_stem}.clustered.json");
     let dense_encoded = format!("{file_stem}.de.json");
     let corrected = format!("{file_stem}.json");
-    assert!(kmersize < 32);
+    assert!(kmersize <= haplotyper::kmer_count::MAX_K);
+    assert!(spectrum_k <= haplotyper::kmer_count::MAX_K);
     rayon::ThreadPoolBuilder::new()
         .num_threads(threads)
         .build_global()
@@ -93,8 +167,15 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
         }
     };
     let take_num = 3 * genome_size / chunk_len / 2;
-    let repeat_mask_config = RepeatMaskConfig::new(kmersize, top_freq, min_count);
-    let select_chunk_config = DetermineUnitConfig::new(
+    let mut repeat_mask_config = RepeatMaskConfig::new(kmersize, top_freq, min_count);
+    repeat_mask_config.set_tandem_repeat(max_period, MIN_COPIES);
+    repeat_mask_config.set_low_complexity(min_entropy);
+    if 0 < max_period || 0f64 < min_entropy {
+        repeat_mask_config.set_bed_output(&format!("{file_stem}.masked.bed"));
+    }
+    let spectrum = format!("{file_stem}.spectrum.tsv");
+    repeat_mask_config.set_kmer_spectrum(spectrum_k, Some(&spectrum));
+    let mut select_chunk_config = DetermineUnitConfig::new(
         chunk_len,
         take_num,
         margin,
@@ -103,6 +184,15 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
         purge_copy_num,
         seed,
     );
+    if let Some(reference) = reference.as_ref() {
+        select_chunk_config.set_reference(reference);
+    }
+    if min_chunk_len.is_some() || max_chunk_len.is_some() {
+        let min_len = min_chunk_len.unwrap_or(chunk_len);
+        let max_len = max_chunk_len.unwrap_or(chunk_len);
+        select_chunk_config.set_adaptive_length(min_len, max_len);
+    }
+    select_chunk_config.set_report(&format!("{file_stem}.removed_chunks"));
     let pick_component_config = ComponentPickingConfig::new(component_num);
     let draft = format!("{file_stem}.draft.gfa");
     let multp_config = MultiplicityEstimationConfig::new(seed, Some(&draft));
@@ -112,7 +202,7 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
     let correction_config = CorrectionConfig::default();
     use haplotyper::determine_chunks::STDDEV_OR_ERROR;
     let dump = Some(file_stem.as_str());
-    let assemble_config = AssembleConfig::new(
+    let mut assemble_config = AssembleConfig::new(
         polish_window_size,
         to_polish,
         true,
@@ -121,14 +211,38 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
         true,
         dump,
     );
+    if sequential_names {
+        use haplotyper::assemble::ContigNaming;
+        assemble_config.set_contig_naming(ContigNaming::Sequential);
+    }
+    assemble_config.set_threshold_overrides(cleanup_thresholds);
+    use haplotyper::assemble::ColorScheme;
+    match bandage.as_deref() {
+        Some("copy_number") => assemble_config.set_bandage_export(Some(ColorScheme::CopyNumber)),
+        Some("phase_block") => assemble_config.set_bandage_export(Some(ColorScheme::PhaseBlock)),
+        Some(other) => warn!("Unknown colour scheme for Bandage:{other}"),
+        None => {}
+    }
     let correct_deletion_config = CorrectDeletionConfig::new(false, None, Some(STDDEV_OR_ERROR));
     let correct_deletion_config_recluster =
         CorrectDeletionConfig::new(true, None, Some(STDDEV_OR_ERROR));
     let squish_config = SquishConfig::new(supress_ari, required_count, match_ari, mismatch_ari);
+    use haplotyper::local_clustering::{ClusteringStrategy, LocalClusteringConfig};
+    let clustering_strategy: ClusteringStrategy = match clusterer.as_deref().map(str::parse) {
+        None => ClusteringStrategy::default(),
+        Some(Ok(strategy)) => strategy,
+        Some(Err(why)) => {
+            error!("{why}");
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+    };
     // Pipeline.
     let mut ds = match resume && matches!(std::path::Path::new(&entry).try_exists(), Ok(true)) {
         false => {
             let mut ds = parse_input(&input_file, &read_type)?;
+            ds.ploidy = ploidy;
+            ds.seed = seed;
+            ds.aligner = aligner;
             if let Some(hap) = haploid_coverage {
                 ds.coverage = definitions::Coverage::Protected(hap);
             }
@@ -137,26 +251,56 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
         }
         true => parse_input(&input_file, &read_type)?,
     };
+    ds.ploidy = ploidy;
+    ds.seed = seed;
+    ds.aligner = aligner;
+    // The model is imported again after each resume, as the checkpoints carry their own models.
+    let model = match model.as_ref() {
+        Some(model) => Some(haplotyper::model_library::read_model(model)?),
+        None => None,
+    };
+    let import_model = |ds: &mut DataSet| {
+        use haplotyper::model_library::ImportModel;
+        if let Some(model) = model.as_ref() {
+            ds.import_model(model.clone(), fix_model);
+        }
+    };
+    import_model(&mut ds);
     if resume && matches!(std::path::Path::new(&encoded).try_exists(), Ok(true)) {
-        ds = parse_json(&encoded)?
+        ds = parse_json(&encoded)?;
+        import_model(&mut ds);
     } else {
+        if !maternal.is_empty() && !paternal.is_empty() {
+            use haplotyper::trio_binning::ParentalKmers;
+            let trio_config = TrioBinningConfig::default();
+            let kmers = ParentalKmers::from_files(&maternal, &paternal, &trio_config)?;
+            ds.trio_binning(&kmers, &trio_config);
+        }
         ds.mask_repeat(&repeat_mask_config);
         ds.select_chunks(&select_chunk_config);
         ds.pick_top_n_component(&pick_component_config);
         ds.correct_deletion(&correct_deletion_config);
+        if let Some(max_dev) = discard_error_outliers {
+            use haplotyper::estimate_error_rate::discard_outlier_reads;
+            let discarded = discard_outlier_reads(&mut ds, max_dev);
+            debug!("DISCARD\t{discarded}");
+        }
         ds.remove_erroneous_nodes();
         ds.estimate_multiplicity(&multp_config);
         ds.purge_multiplicity(purge_copy_num);
         log(&ds, &encoded)?;
     }
     if resume && matches!(std::path::Path::new(&clustered).try_exists(), Ok(true)) {
-        ds = parse_json(&clustered)?
+        ds = parse_json(&clustered)?;
+        import_model(&mut ds);
     } else {
-        ds.local_clustering();
+        let config = LocalClusteringConfig::new(clustering_strategy, None);
+        ds.local_clustering_with(&config);
         log(&ds, &clustered)?;
     }
     if resume && matches!(std::path::Path::new(&dense_encoded).try_exists(), Ok(true)) {
         ds = parse_json(&dense_encoded)?;
+        import_model(&mut ds);
     } else {
         ds.purge(&purge_config);
         ds.purge(&purge_config);
@@ -167,15 +311,57 @@ pub fn run_pipeline(config: &PipelineConfig) -> std::io::Result<()> {
     }
     if resume && matches!(std::path::Path::new(&corrected).try_exists(), Ok(true)) {
         ds = parse_json(&corrected)?;
+        import_model(&mut ds);
     } else {
         ds.squish_erroneous_clusters(&squish_config);
         ds.correct_clustering(&correction_config);
+        if global_phasing {
+            let phasing_config = PhasingConfig::default();
+            let mut phasing = ds.phase(&phasing_config);
+            std::fs::write(format!("{file_stem}.blocks.tsv"), phasing.blocks_tsv())?;
+            std::fs::write(format!("{file_stem}.sites.tsv"), phasing.sites_tsv())?;
+            let flagged = phasing.flagged_chunks();
+            if !flagged.is_empty() {
+                let mut config = LocalClusteringConfig::new(clustering_strategy, None);
+                config.set_read_seeds(phasing.read_seeds());
+                ds.local_clustering_selected_with(&flagged, &config);
+                ds.correct_clustering(&correction_config);
+                // Phase the re-clustered nodes again for the assembly.
+                phasing = ds.phase(&phasing_config);
+            }
+            ds.phased_nodes = phasing.phased_nodes();
+        }
         log(&ds, &corrected)?;
     }
+    if let (Some(r1), Some(r2)) = (hic_r1.as_ref(), hic_r2.as_ref()) {
+        use haplotyper::hic::{HiC, HiCConfig};
+        ds.register_hic_pairs(r1, r2)?;
+        ds.map_hic_pairs(&HiCConfig::new(threads, 5))?;
+    }
+    if let Some(reference) = reference.as_ref() {
+        use haplotyper::locate_chunks::*;
+        let reference = load_reference(reference)?;
+        ds.locate_chunks(&reference, &LocateChunksConfig::new(threads, MIN_IDENTITY))?;
+    }
     // Flush the result.
+    let model_path = format!("{file_stem}.model.json");
+    if let Err(why) = haplotyper::model_library::write_model(&ds.model_param, &model_path) {
+        warn!("{why:?},{model_path}");
+    }
     let gfa = ds.assemble(&assemble_config);
     let mut asm_file = std::fs::File::create(format!("{file_stem}.gfa")).map(BufWriter::new)?;
-    writeln!(asm_file, "{gfa}")
+    writeln!(asm_file, "{gfa}")?;
+    let mut fasta = std::fs::File::create(format!("{file_stem}.fa")).map(BufWriter::new)?;
+    writeln!(fasta, "{}", haplotyper::assemble::to_fasta(&gfa))?;
+    if !ds.phased_nodes.is_empty() {
+        let haplotypes = haplotyper::assemble::to_haplotype_fasta(&gfa, ds.ploidy);
+        for (i, fasta) in haplotypes.iter().enumerate() {
+            let path = format!("{file_stem}.hap{}.fa", i + 1);
+            let mut wtr = std::fs::File::create(path).map(BufWriter::new)?;
+            writeln!(wtr, "{fasta}")?;
+        }
+    }
+    Ok(())
 }
 
 fn parse_json(filename: &str) -> std::io::Result<DataSet> {
@@ -27,10 +27,46 @@ pub struct DataSet {
     pub read_type: ReadType,
     /// Estimated Hidden Markov model. On both strands.
     pub model_param: HMMParamOnStrands,
+    /// If true, `model_param` is given by the user and never re-estimated.
+    #[serde(default)]
+    pub model_fixed: bool,
     /// Estimated error rate.
     pub error_rate: ErrorRate,
     /// JTK consists of several stages. `processed stages` shows the list of stages JTK has processed so far.
     pub processed_stages: Vec<ProcessedStage>,
+    /// The parental origin of the reads, if the parental k-mers are available (trio-binning).
+    /// Reads without enough haplotype-specific k-mers are not listed.
+    #[serde(default)]
+    pub parental_labels: Vec<ParentalLabel>,
+    /// The number of the haplotypes in the genome (2 for diploid).
+    /// The coverage and the copy numbers are estimated so that a typical chunk has this copy number.
+    #[serde(default = "default_ploidy")]
+    pub ploidy: usize,
+    /// The seed of the pseudo random number generators.
+    /// Every randomized step derives its own stream from this value, so that
+    /// the result does not depend on the number of threads.
+    #[serde(default)]
+    pub seed: u64,
+    /// The aligner used to encode the reads by the chunks.
+    #[serde(default)]
+    pub aligner: Aligner,
+    /// The estimation from the k-mer spectrum of the raw reads, if available.
+    #[serde(default)]
+    pub kmer_spectrum: Option<KmerSpectrumEstimate>,
+    /// The chunks discarded during the chunk selection, in the order of the removal.
+    #[serde(default)]
+    pub removed_chunks: Vec<RemovedChunk>,
+    /// The error rates of the reads, sorted by the IDs. Reads never encoded are not listed.
+    /// They are kept across the encodings, so that the next encoding can use them.
+    #[serde(default)]
+    pub read_error_rates: Vec<ReadErrorRate>,
+    /// The haplotypes of the nodes by the global phasing, sorted by the nodes. Empty if it was not run.
+    #[serde(default)]
+    pub phased_nodes: Vec<PhasedNode>,
+}
+
+fn default_ploidy() -> usize {
+    2
 }
 
 /// The name and the argument in a stage (e.g., encoding, clustering, or assembling.).
@@ -42,6 +78,29 @@ pub struct ProcessedStage {
     pub arg: Vec<String>,
 }
 
+/// The genome characteristics estimated by fitting a diploid mixture to the k-mer spectrum of the reads.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct KmerSpectrumEstimate {
+    /// The size of the k-mers.
+    pub k: usize,
+    /// The haploid (read) coverage.
+    pub haploid_coverage: f64,
+    /// The fraction of the heterozygous sites.
+    pub heterozygosity: f64,
+    /// The fraction of the genome occurring in three or more haploid copies.
+    pub repeat_fraction: f64,
+}
+
+/// The error rate of a read, estimated from its alignments to the chunks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReadErrorRate {
+    pub id: u64,
+    /// The error rate of the read, excluding the excess of the errors specific to the chunks.
+    pub error_rate: f64,
+    /// The number of the nodes used in the estimation.
+    pub num_nodes: usize,
+}
+
 /// Haploid coverage. To access the value, the easiest way is to use [`Coverage::unwrap`].
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Coverage {
@@ -123,6 +182,42 @@ pub struct HMMParam {
     /// A->0, C->1, G->2, T->3
     pub mat_emit: [f64; 16],
     pub ins_emit: [f64; 20],
+    /// Indel rates depending on the homopolymer length. Empty if not estimated.
+    /// They replace the homopolymer indels of the transitions above, which are the same everywhere,
+    /// in the likelihoods of the variant calling and the polishing.
+    #[serde(default)]
+    pub homopolymer: HomopolymerIndel,
+}
+
+/// Homopolymer-length-dependent indel rates, used on top of the pair-HMM.
+/// `ins[l-1]` (`del[l-1]`) is the probability that a homopolymer of length l gets one base longer (shorter) in a read.
+/// Homopolymers longer than the table share the rates of the last entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HomopolymerIndel {
+    pub ins: Vec<f64>,
+    pub del: Vec<f64>,
+}
+
+impl HomopolymerIndel {
+    pub fn is_empty(&self) -> bool {
+        self.ins.is_empty() || self.del.is_empty()
+    }
+    /// The maximum length in the table.
+    pub fn len(&self) -> usize {
+        self.ins.len().min(self.del.len())
+    }
+    /// Pr{a homopolymer of length `len` gets one base longer}, if estimated.
+    pub fn ins(&self, len: usize) -> Option<f64> {
+        assert!(0 < len);
+        let len = len.min(self.ins.len());
+        self.ins.get(len.max(1) - 1).copied()
+    }
+    /// Pr{a homopolymer of length `len` gets one base shorter}, if estimated.
+    pub fn del(&self, len: usize) -> Option<f64> {
+        assert!(0 < len);
+        let len = len.min(self.del.len());
+        self.del.get(len.max(1) - 1).copied()
+    }
 }
 
 impl std::default::Default for HMMParam {
@@ -142,6 +237,7 @@ impl std::default::Default for HMMParam {
                 0.01, 0.97,
             ],
             ins_emit: [0.25; 20],
+            homopolymer: HomopolymerIndel::default(),
         }
     }
 }
@@ -153,6 +249,17 @@ pub struct MaskInfo {
     pub thr: u32,
 }
 
+/// The aligner to map the reads (or the chunks) to the chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aligner {
+    /// Invoke `minimap2` in a temporary directory under the current directory.
+    #[default]
+    Minimap2,
+    /// Align in memory by the built-in minimizer aligner.
+    Builtin,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Copy)]
 pub enum ReadType {
     CCS,
@@ -255,8 +362,17 @@ impl std::default::Default for DataSet {
             read_type: ReadType::None,
             masked_kmers: MaskInfo::default(),
             model_param: HMMParamOnStrands::default(),
+            model_fixed: false,
             error_rate: ErrorRate::default(),
             processed_stages: vec![],
+            parental_labels: vec![],
+            ploidy: default_ploidy(),
+            seed: 0,
+            aligner: Aligner::default(),
+            kmer_spectrum: None,
+            removed_chunks: vec![],
+            read_error_rates: vec![],
+            phased_nodes: vec![],
         }
     }
 }
@@ -282,10 +398,26 @@ impl DataSet {
             read_type,
             masked_kmers: MaskInfo::default(),
             model_param: HMMParamOnStrands::default(),
+            model_fixed: false,
             error_rate: ErrorRate::guess(read_type),
             processed_stages: vec![],
+            parental_labels: vec![],
+            ploidy: default_ploidy(),
+            seed: 0,
+            aligner: Aligner::default(),
+            kmer_spectrum: None,
+            removed_chunks: vec![],
+            read_error_rates: vec![],
+            phased_nodes: vec![],
         }
     }
+    /// The estimated error rate of the read, if available.
+    pub fn read_error_rate(&self, id: u64) -> Option<&ReadErrorRate> {
+        self.read_error_rates
+            .binary_search_by_key(&id, |r| r.id)
+            .ok()
+            .map(|idx| &self.read_error_rates[idx])
+    }
     /// Sanity check function. Call it to ensure that some properties indeed holds.
     /// Currently, the following properties are checked.
     /// 1: The input file exists.
@@ -412,6 +544,94 @@ pub struct Chunk {
     pub copy_num: usize,
     /// Local clustering score. If not clustered, zero.
     pub score: f64,
+    /// The region of the reference this chunk is taken from, if the chunks are seeded by a reference.
+    #[serde(default)]
+    pub reference: Option<ReferencePosition>,
+}
+
+/// A half-open interval `[start, end)` on a reference sequence.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReferencePosition {
+    /// The name of the reference sequence.
+    pub contig: String,
+    pub start: usize,
+    pub end: usize,
+    /// If false, the chunk is the reverse complement of the reference.
+    pub strand: bool,
+    /// The fraction of the matched bases in the alignment.
+    pub identity: f64,
+}
+
+impl std::fmt::Display for ReferencePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let strand = if self.strand { '+' } else { '-' };
+        write!(f, "{}:{}-{}:{strand}", self.contig, self.start, self.end)
+    }
+}
+
+/// A chunk discarded during the chunk selection, with the reason and the metric leading to the removal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemovedChunk {
+    /// The ID of the chunk at the time of the removal. Note that the IDs are re-assigned between the stages.
+    pub id: u64,
+    pub reason: RemovalReason,
+    /// The metric of the chunk compared to `threshold`. See `RemovalReason` for each metric.
+    pub value: f64,
+    pub threshold: f64,
+    pub seq: DNASeq,
+}
+
+impl RemovedChunk {
+    pub fn new(chunk: &Chunk, reason: RemovalReason, value: f64, threshold: f64) -> Self {
+        Self {
+            id: chunk.id,
+            reason,
+            value,
+            threshold,
+            seq: chunk.seq.clone(),
+        }
+    }
+    pub fn seq(&self) -> &[u8] {
+        &self.seq.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RemovalReason {
+    /// Aligned to another chunk. The metric is the identity of the best overlap.
+    OverlapOnChunks,
+    /// Occurred too many times in the reads. The metric is the number of the occurrences.
+    Frequent,
+    /// Overlapped with another chunk on the same reads. The metric is the longest overlap (bp).
+    OverlapOnReads,
+    /// Too many repetitive k-mers. The metric is the repetitiveness.
+    Repetitive,
+    /// Too few reads to take the consensus. The metric is the number of the occurrences.
+    LowCoverage,
+    /// Occurrences purged as they support a large deletion. The chunk itself is kept.
+    /// The metric is the number of the purged occurrences, and the threshold is the number of the occurrences before the purge.
+    LargeDeletion,
+}
+
+impl RemovalReason {
+    /// Return true if the chunk is kept, i.e., only some of its occurrences were removed.
+    pub fn keeps_chunk(&self) -> bool {
+        *self == RemovalReason::LargeDeletion
+    }
+}
+
+impl std::fmt::Display for RemovalReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let reason = match self {
+            RemovalReason::OverlapOnChunks => "OverlapOnChunks",
+            RemovalReason::Frequent => "Frequent",
+            RemovalReason::OverlapOnReads => "OverlapOnReads",
+            RemovalReason::Repetitive => "Repetitive",
+            RemovalReason::LowCoverage => "LowCoverage",
+            RemovalReason::LargeDeletion => "LargeDeletion",
+        };
+        write!(f, "{reason}")
+    }
 }
 
 use serde_with::DeserializeFromStr;
@@ -476,6 +696,7 @@ impl Chunk {
             copy_num,
             cluster_num: 1,
             score: 0f64,
+            reference: None,
         }
     }
     pub fn seq(&self) -> &[u8] {
@@ -876,11 +1097,54 @@ impl std::str::FromStr for Ops {
     }
 }
 
+/// A Hi-C pair with both mates uniquely mapped to (chunk, cluster)s.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HiCEdge {
     pub pair_id: u64,
+    /// The chunk the first mate is mapped to.
     pub pair1: u64,
+    /// The chunk the second mate is mapped to.
     pub pair2: u64,
+    /// The cluster the first mate is mapped to.
+    #[serde(default)]
+    pub cluster1: u64,
+    /// The cluster the second mate is mapped to.
+    #[serde(default)]
+    pub cluster2: u64,
+}
+
+/// The parent a read (or a contig) is inherited from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Parent {
+    Maternal,
+    Paternal,
+}
+
+impl std::fmt::Display for Parent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Parent::Maternal => write!(f, "maternal"),
+            Parent::Paternal => write!(f, "paternal"),
+        }
+    }
+}
+
+/// The parental origin of a read, with the number of the maternal/paternal specific k-mers in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParentalLabel {
+    pub id: u64,
+    pub parent: Parent,
+    pub maternal: u32,
+    pub paternal: u32,
+}
+
+/// A node (a cluster of a chunk) phased by the global phasing, with the haplotypes (1-origin) carrying it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhasedNode {
+    pub chunk: u64,
+    pub cluster: u64,
+    pub block: usize,
+    pub haplotypes: Vec<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,272 @@
+//! Add the reads of a top-up sequencing run to an encoded and clustered dataset.
+//!
+//! The new reads are encoded by the current chunks, and each node is assigned to the cluster
+//! whose consensus explains it best under the pair-HMM. Then, only the chunks whose depth
+//! (normalized by the haploid coverage) changed materially are clustered again.
+//! The assembly graph should be constructed again afterwards.
+use crate::local_clustering::LocalClustering;
+use crate::model_tune::ModelFit;
+use definitions::*;
+use kiley::hmm::PairHiddenMarkovModelOnStrands;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct AddReadsConfig {
+    threads: usize,
+    // Similarity threshold of the encoding.
+    sim_thr: f64,
+    // A chunk is clustered again if the normalized depth changes more than this fraction.
+    min_change: f64,
+}
+
+impl AddReadsConfig {
+    pub fn new(threads: usize, sim_thr: f64, min_change: f64) -> Self {
+        Self {
+            threads,
+            sim_thr,
+            min_change,
+        }
+    }
+}
+
+pub trait AddReads {
+    /// Encode the `(name, sequence)`s by the current chunks and add them to the dataset.
+    /// Return the IDs of the chunks clustered again.
+    fn add_reads(
+        &mut self,
+        reads: Vec<(String, Vec<u8>)>,
+        config: &AddReadsConfig,
+    ) -> std::io::Result<HashSet<u64>>;
+}
+
+impl AddReads for DataSet {
+    fn add_reads(
+        &mut self,
+        reads: Vec<(String, Vec<u8>)>,
+        config: &AddReadsConfig,
+    ) -> std::io::Result<HashSet<u64>> {
+        let prev_cov = self.coverage.unwrap();
+        let prev_counts = count_nodes(self);
+        let first_id = self.raw_reads.iter().map(|r| r.id + 1).max().unwrap_or(0);
+        let compress_thr = crate::entry::compression_threshold(self.read_type);
+        let raw_reads: Vec<_> = reads
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, seq))| RawRead {
+                name,
+                desc: String::new(),
+                seq: crate::entry::compress_homopolymer(&seq, compress_thr).into(),
+                id: first_id + i as u64,
+            })
+            .collect();
+        debug!("ADDREADS\tReadNum\t{}", raw_reads.len());
+        let mut added = DataSet::with_minimum_data(&self.input_file, raw_reads, self.read_type);
+        added.selected_chunks = self.selected_chunks.clone();
+        // Encode the new reads in the same way as the current ones.
+        added.aligner = self.aligner;
+        added.model_param = self.model_param.clone();
+        added.model_fixed = self.model_fixed;
+        added.error_rate = self.error_rate.clone();
+        added.read_error_rates = self.read_error_rates.clone();
+        crate::encode::encode_by_mm2(&mut added, config.threads, config.sim_thr)?;
+        debug!("ADDREADS\tEncoded\t{}", added.encoded_reads.len());
+        let consensi = cluster_consensus(self);
+        let hmm = self.get_model_on_both_strands();
+        let chunks: HashMap<_, _> = self.selected_chunks.iter().map(|c| (c.id, c)).collect();
+        let read_type = self.read_type;
+        added
+            .encoded_reads
+            .par_iter_mut()
+            .flat_map(|r| r.nodes.par_iter_mut())
+            .for_each(|node| {
+                let chunk = chunks[&node.chunk];
+                assign_cluster(node, chunk, &consensi, &hmm, read_type);
+            });
+        self.raw_reads.extend(added.raw_reads);
+        self.encoded_reads.extend(added.encoded_reads);
+        let counts = count_nodes(self);
+        match self.coverage.is_protected() {
+            false => crate::misc::update_coverage(self),
+            true => {
+                let prev_total: usize = prev_counts.values().sum();
+                let total: usize = counts.values().sum();
+                let cov = prev_cov * total as f64 / prev_total.max(1) as f64;
+                self.coverage = Coverage::Protected(cov);
+            }
+        }
+        let cov = self.coverage.unwrap();
+        debug!("ADDREADS\tCoverage\t{prev_cov:.2}\t{cov:.2}");
+        let changes = changed_chunks(&prev_counts, prev_cov, &counts, cov, config.min_change);
+        for chunk in self.selected_chunks.iter_mut() {
+            if let Some(&diff) = changes.get(&chunk.id) {
+                let copy_num = (chunk.copy_num as i64 + diff).max(1) as usize;
+                debug!(
+                    "ADDREADS\tChanged\t{}\t{}\t{copy_num}",
+                    chunk.id, chunk.copy_num
+                );
+                chunk.copy_num = copy_num;
+            }
+        }
+        let selection: HashSet<_> = changes.keys().copied().collect();
+        debug!("ADDREADS\tRecluster\t{}", selection.len());
+        if !selection.is_empty() {
+            self.local_clustering_selected(&selection);
+        }
+        Ok(selection)
+    }
+}
+
+fn count_nodes(ds: &DataSet) -> HashMap<u64, usize> {
+    let mut counts: HashMap<_, usize> = HashMap::new();
+    for node in ds.encoded_reads.iter().flat_map(|r| r.nodes.iter()) {
+        *counts.entry(node.chunk).or_default() += 1;
+    }
+    counts
+}
+
+// Consensus of each cluster of the chunks with two or more clusters.
+fn cluster_consensus(ds: &DataSet) -> HashMap<(u64, u64), Vec<u8>> {
+    let chunks: HashMap<_, _> = ds
+        .selected_chunks
+        .iter()
+        .filter(|c| 1 < c.cluster_num)
+        .map(|c| (c.id, c))
+        .collect();
+    let mut bucket: HashMap<_, Vec<_>> = HashMap::new();
+    for node in ds.encoded_reads.iter().flat_map(|r| r.nodes.iter()) {
+        if chunks.contains_key(&node.chunk) {
+            bucket
+                .entry((node.chunk, node.cluster))
+                .or_default()
+                .push(node.seq());
+        }
+    }
+    bucket
+        .par_iter()
+        .map(|(key, seqs)| {
+            let chunk = chunks[&key.0];
+            let band = ds.read_type.band_width(chunk.seq().len());
+            let cons = kiley::bialignment::guided::polish_until_converge(chunk.seq(), seqs, band);
+            (*key, cons)
+        })
+        .collect()
+}
+
+fn assign_cluster(
+    node: &mut Node,
+    chunk: &Chunk,
+    consensi: &HashMap<(u64, u64), Vec<u8>>,
+    hmm: &PairHiddenMarkovModelOnStrands,
+    read_type: ReadType,
+) {
+    if chunk.cluster_num <= 1 {
+        return;
+    }
+    let hmm = match node.is_forward {
+        true => hmm.forward(),
+        false => hmm.reverse(),
+    };
+    let band = read_type.band_width(chunk.seq().len());
+    let lks: Vec<_> = (0..chunk.cluster_num as u64)
+        .map(|cl| match consensi.get(&(chunk.id, cl)) {
+            Some(cons) => hmm.likelihood_antidiagonal_bootstrap(cons, node.seq(), band),
+            None => f64::NEG_INFINITY,
+        })
+        .collect();
+    if lks.iter().all(|lk| lk.is_infinite()) {
+        return;
+    }
+    let total = crate::misc::logsumexp(&lks);
+    node.posterior = lks.iter().map(|lk| lk - total).collect();
+    let (cluster, _) = lks
+        .iter()
+        .enumerate()
+        .max_by(|x, y| x.1.partial_cmp(y.1).unwrap())
+        .unwrap();
+    node.cluster = cluster as u64;
+}
+
+// Return the chunks whose normalized depth changed more than `min_change` (relative) or
+// by a whole copy, with the change of their copy numbers.
+fn changed_chunks(
+    prev_counts: &HashMap<u64, usize>,
+    prev_cov: f64,
+    counts: &HashMap<u64, usize>,
+    cov: f64,
+    min_change: f64,
+) -> HashMap<u64, i64> {
+    counts
+        .iter()
+        .filter_map(|(&chunk, &count)| {
+            let prev_count = prev_counts.get(&chunk).copied().unwrap_or(0);
+            let prev_depth = prev_count as f64 / prev_cov;
+            let depth = count as f64 / cov;
+            let diff = depth.round() as i64 - prev_depth.round() as i64;
+            let rel_change = (depth - prev_depth).abs() / prev_depth.max(1f64);
+            (diff != 0 || min_change < rel_change).then_some((chunk, diff))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn changed_chunks_test() {
+        let prev: HashMap<u64, usize> = vec![(0, 20), (1, 40), (2, 20)].into_iter().collect();
+        let now: HashMap<u64, usize> = vec![(0, 40), (1, 80), (2, 70)].into_iter().collect();
+        let changes = changed_chunks(&prev, 10f64, &now, 20f64, 0.2);
+        assert!(!changes.contains_key(&0));
+        assert!(!changes.contains_key(&1));
+        assert_eq!(changes[&2], 2);
+        let changes = changed_chunks(&prev, 10f64, &now, 20f64, 0.0);
+        assert!(!changes.contains_key(&0));
+    }
+    #[test]
+    fn add_reads_builtin_test() {
+        use crate::encode::Encode;
+        use rand::{Rng, SeedableRng};
+        let mut rng: rand_xoshiro::Xoroshiro128StarStar = SeedableRng::seed_from_u64(4234);
+        let (len, read_len) = (8_000, 5_000);
+        let template: Vec<u8> = (0..len).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect();
+        let mut reads: Vec<_> = (0..40)
+            .map(|i| {
+                let start = rng.gen_range(0..len - read_len);
+                (
+                    format!("read{i}"),
+                    template[start..start + read_len].to_vec(),
+                )
+            })
+            .collect();
+        let mut ds = DataSet::new();
+        ds.selected_chunks = template
+            .chunks_exact(2_000)
+            .enumerate()
+            .map(|(id, seq)| Chunk::new(id as u64, seq.to_vec(), 1))
+            .collect();
+        let added_reads = reads.split_off(30);
+        ds.raw_reads = reads
+            .into_iter()
+            .enumerate()
+            .map(|(id, (name, seq))| RawRead {
+                name,
+                desc: String::new(),
+                id: id as u64,
+                seq: seq.into(),
+            })
+            .collect();
+        ds.aligner = Aligner::Builtin;
+        ds.read_type = ReadType::CCS;
+        ds.coverage = Coverage::Protected(15f64);
+        let (sim_thr, sd) = (ds.read_type.sim_thr(), ds.read_type.sd_of_error());
+        ds.encode(1, sim_thr, sd);
+        let encoded = ds.encoded_reads.len();
+        // The builtin aligner needs neither minimap2 nor temporary files.
+        let config = AddReadsConfig::new(1, sim_thr, 0.2);
+        ds.add_reads(added_reads, &config).unwrap();
+        assert_eq!(ds.raw_reads.len(), 40);
+        assert!(encoded < ds.encoded_reads.len());
+        assert!(ds.encoded_reads.iter().any(|r| 30 <= r.id));
+    }
+}
@@ -646,21 +646,21 @@ impl<'b, 'a: 'b> DitchGraph<'a> {
         cov: f64,
         reads: &[&EncodedRead],
         c: &super::AssembleConfig,
+        thr: &super::thresholds::CleanupThresholds,
         _read_type: definitions::ReadType,
     ) {
         use rand::SeedableRng;
         use rand_xoshiro::Xoshiro256PlusPlus;
-        let seed = self.nodes.len() as u64 * 7329;
-        let mut rng: Xoshiro256PlusPlus = SeedableRng::seed_from_u64(seed);
+        let mut rng: Xoshiro256PlusPlus = SeedableRng::seed_from_u64(c.seed);
         debug!("CC\tBFASN\t{}", self.cc());
         if log_enabled!(log::Level::Trace) {
             dump(self, 0, c);
         }
         self.assign_copy_number(cov, &mut rng);
-        self.remove_tips(0.8, 4);
+        self.remove_tips(thr.tip_frac, thr.tip_diag);
         assert!(self.sanity_check());
         debug!("CC\tRMZERO\t{}", self.cc());
-        self.remove_tips(0.8, 4);
+        self.remove_tips(thr.tip_frac, thr.tip_diag);
         // From good Likelihood ratio focus, to weaker ones.
         let min_llr = c.span_likelihood_ratio;
         let llr_stream = ((min_llr + 0.0).floor() as usize..(10.0 + min_llr).floor() as usize)
@@ -669,9 +669,9 @@ impl<'b, 'a: 'b> DitchGraph<'a> {
             .take_while(|&x| min_llr < x);
         for (i, llr) in llr_stream.clone().enumerate() {
             self.assign_copy_number(cov, &mut rng);
-            self.remove_zero_copy_elements(0.8);
+            self.remove_zero_copy_elements(thr.zero_copy_frac);
             debug!("REPEATRESOLVE\t{}", i);
-            self.remove_zero_copy_path(0.1);
+            self.remove_zero_copy_path(thr.zero_copy_path_frac);
             self.resolve_repeats(reads, c, llr, true, false);
             debug!("CC\tSOLVEREP\t{}\t{i}", self.cc());
             self.zip_up_overclustering(2);
@@ -680,11 +680,11 @@ impl<'b, 'a: 'b> DitchGraph<'a> {
             }
         }
         self.assign_copy_number(cov, &mut rng);
-        self.remove_zero_copy_elements(0.9);
-        self.remove_zero_copy_path(0.3);
+        self.remove_zero_copy_elements(thr.zero_copy_frac_final);
+        self.remove_zero_copy_path(thr.zero_copy_path_frac_final);
         self.remove_lightweight_edges(0, true);
-        self.remove_tips(0.8, 4);
-        self.squish_small_net(3);
+        self.remove_tips(thr.tip_frac, thr.tip_diag);
+        self.squish_small_net(thr.small_net_len);
         self.assign_copy_number(cov, &mut rng);
         self.zip_up_overclustering_dev();
         for llr in llr_stream {
@@ -728,6 +728,38 @@ impl<'b, 'a: 'b> DitchGraph<'a> {
         }
         clusters.into_values().collect()
     }
+    /// Render the chunk-level graph in Graphviz DOT format.
+    /// Each node is labeled by `chunk-cluster` with its occurrence and copy number,
+    /// and each edge is labeled by its occurrence, with the positions (H/T) at the both ends.
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut lines = vec![format!("graph \"{name}\" {{")];
+        lines.push("    node [shape=box];".to_string());
+        for (idx, node) in self.nodes() {
+            let (chunk, cluster) = node.node;
+            let cp = node.copy_number.map(|x| x.to_string());
+            let cp = cp.as_deref().unwrap_or("-");
+            lines.push(format!(
+                "    n{idx} [label=\"{chunk}-{cluster}\\nocc:{} cp:{cp}\"];",
+                node.occ
+            ));
+        }
+        for (_, node) in self.nodes() {
+            for edge in node.edges.iter() {
+                // Each edge is stored in the both ends.
+                if (edge.to, edge.to_position) < (edge.from, edge.from_position) {
+                    continue;
+                }
+                let (from, to) = (edge.from, edge.to);
+                let (fpos, tpos) = (edge.from_position, edge.to_position);
+                lines.push(format!(
+                    "    n{from} -- n{to} [label=\"{}\", taillabel=\"{fpos}\", headlabel=\"{tpos}\"];",
+                    edge.occ
+                ));
+            }
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
 }
 
 fn dump(graph: &DitchGraph, i: usize, c: &AssembleConfig) {
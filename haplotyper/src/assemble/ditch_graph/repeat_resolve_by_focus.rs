@@ -301,6 +301,7 @@ impl<'b, 'a: 'b> DitchGraph<'a> {
                 if into_multi_copy || (use_branch && is_branching) {
                     let mut foci_on = self.retrieve_foci(index, pos, reads, config);
                     foci_on.retain(|focus| thr < focus.llr());
+                    foci_on.retain(|focus| !self.is_parental_conflict(focus, reads, config));
                     foci.entry(index).or_default().extend(foci_on);
                 }
             }
@@ -379,6 +380,7 @@ impl<'b, 'a: 'b> DitchGraph<'a> {
                 for tail_cand in tail_cands.iter() {
                     let bypass =
                         self.examine_bypass(head_cand, &diplo_path, tail_cand, &reads, config);
+                    let bypass = bypass.filter(|b| !self.is_parental_conflict(b, &reads, config));
                     if let Some(bypass) = bypass {
                         bypasses.push(bypass);
                         break 'outer;
@@ -706,6 +708,39 @@ impl<'b, 'a: 'b> DitchGraph<'a> {
             .collect()
     }
 
+    // The parent of the reads on the node, if the reads are labelled by the trio-binning.
+    fn node_parent(
+        &self,
+        index: NodeIndex,
+        reads: &[&EncodedRead],
+        config: &AssembleConfig,
+    ) -> Option<Parent> {
+        let node = self.node(index)?.node;
+        let parents = reads
+            .iter()
+            .filter(|r| r.contains(node))
+            .filter_map(|r| config.read_parents.get(&r.id).copied());
+        crate::trio_binning::majority(parents)
+    }
+    // True if the focus connects a maternal node and a paternal node.
+    fn is_parental_conflict(
+        &self,
+        focus: &Focus,
+        reads: &[&EncodedRead],
+        config: &AssembleConfig,
+    ) -> bool {
+        if config.read_parents.is_empty() {
+            return false;
+        }
+        let from = self.node_parent(focus.from, reads, config);
+        let to = self.node_parent(focus.to, reads, config);
+        let is_conflict = matches!((from, to), (Some(f), Some(t)) if f != t);
+        if is_conflict {
+            debug!("FOCUS\tTRIO\t{focus}");
+        }
+        is_conflict
+    }
+
     fn survey_focus(&'b mut self, focus: &Focus, affected: &mut HashSet<NodeIndex>) -> Option<()> {
         if !self.is_path_branching(focus) {
             return None;
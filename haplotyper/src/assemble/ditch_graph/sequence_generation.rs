@@ -1,15 +1,19 @@
 //! module defines how a ditch graph would generate sequence, or reduce simple paths.
 //! 2022/07/07: I changed the strategy.
 use super::*;
+use serde::{Deserialize, Serialize};
 
 /// A summary of a contig. It tells us
 /// the chunk, the cluster, and the direction
 /// it spelled.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContigSummary {
     /// The ID of the focal contig.
     pub id: String,
     pub summary: Vec<ContigElement>,
+    /// The position on the reference, if the chunks are located on it.
+    #[serde(default)]
+    pub reference: Option<definitions::ReferencePosition>,
 }
 
 impl ContigSummary {
@@ -17,6 +21,7 @@ impl ContigSummary {
         Self {
             id: id.to_string(),
             summary: summary.to_vec(),
+            reference: None,
         }
     }
 }
@@ -32,7 +37,7 @@ impl std::fmt::Display for ContigSummary {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContigElement {
     pub chunk: u64,
     pub cluster: u64,
@@ -259,6 +264,10 @@ impl<'a> super::DitchGraph<'a> {
     /// Reduce simple path of this graph and returns the edges and nodes of the reduced graph..
     /// The contig summary contains the chunks used to construct a contig in the traversing order.
     pub fn spell(&self, c: &AssembleConfig) -> TraversedContigs {
+        self.spell_with_prefix(c, "tig")
+    }
+    /// Same as [`DitchGraph::spell`], but the contigs are named `{prefix}_{:04}`.
+    pub fn spell_with_prefix(&self, c: &AssembleConfig, prefix: &str) -> TraversedContigs {
         let mut arrived = HashSet::new();
         let mut sids: HashMap<_, _> = HashMap::new();
         let (mut g_segs, mut g_edges, mut summaries) = (vec![], vec![], vec![]);
@@ -269,7 +278,7 @@ impl<'a> super::DitchGraph<'a> {
             if arrived.contains(&node) {
                 continue;
             }
-            let name = format!("tig_{:04}", g_segs.len());
+            let name = format!("{prefix}_{:04}", g_segs.len());
             let contig_info = self.traverse_from(&mut arrived, &mut sids, node, p, name, c);
             let (contig, edges, summary, chunk_positions) = contig_info;
             g_segs.push(contig);
@@ -284,7 +293,7 @@ impl<'a> super::DitchGraph<'a> {
                 continue;
             }
             let p = Position::Head;
-            let name = format!("tig_{:04}", g_segs.len());
+            let name = format!("{prefix}_{:04}", g_segs.len());
             let contig_info = self.traverse_from(&mut arrived, &mut sids, key, p, name, c);
             let (contig, edges, summary, chunk_positions) = contig_info;
             g_segs.push(contig);
@@ -311,6 +320,7 @@ impl<'a> super::DitchGraph<'a> {
         start_position: Position,
         sids: &HashMap<NodeIndex, ContigTag>,
         gfa_edge_start: gfa::Position,
+        c: &AssembleConfig,
     ) -> Vec<(gfa::Edge, Vec<gfa::SamTag>)> {
         let edges = self.edges_from(start, start_position);
         edges
@@ -343,6 +353,9 @@ impl<'a> super::DitchGraph<'a> {
                 if let Some(cp) = e.copy_number {
                     samtag.push(gfa::SamTag::new(format!("cp:i:{}", cp)));
                 }
+                if c.bandage_colors.is_some() {
+                    samtag.push(gfa::SamTag::new(format!("RC:i:{}", e.occ)));
+                }
                 Some((edge, samtag))
             })
             .collect()
@@ -358,7 +371,7 @@ impl<'a> super::DitchGraph<'a> {
         start: NodeIndex,
         start_position: Position,
         seqname: String,
-        _c: &AssembleConfig,
+        c: &AssembleConfig,
     ) -> (
         gfa::Segment,
         Vec<(gfa::Edge, Vec<gfa::SamTag>)>,
@@ -368,7 +381,7 @@ impl<'a> super::DitchGraph<'a> {
         // Find edges.
         // I impled here!
         let gfa_pos = gfa::Position::from(0, false);
-        let edges = self.enumerate_adjacent_tag(&seqname, start, start_position, sids, gfa_pos);
+        let edges = self.enumerate_adjacent_tag(&seqname, start, start_position, sids, gfa_pos, c);
         let mut position_of_chunk = ContigEncoding::new(&seqname);
         let (mut node_index, mut position) = (start, start_position);
         let mut seq = self.initial_sequence(start, start_position);
@@ -452,7 +465,8 @@ impl<'a> super::DitchGraph<'a> {
         }
         let gfa_pos = gfa::Position::from(seq.len(), true);
         let seg = gfa::Segment::from(seqname.clone(), seq.len(), Some(seq));
-        let tail_edges = self.enumerate_adjacent_tag(&seqname, node_index, position, sids, gfa_pos);
+        let tail_edges =
+            self.enumerate_adjacent_tag(&seqname, node_index, position, sids, gfa_pos, c);
         let mut edges = edges;
         edges.extend(tail_edges);
         (seg, edges, summary, position_of_chunk)
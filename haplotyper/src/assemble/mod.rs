@@ -1,9 +1,11 @@
 pub mod ditch_graph;
+pub mod thresholds;
 use definitions::*;
 use ditch_graph::*;
 use gfa::GFA;
 use serde::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use thresholds::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Graph {
@@ -64,6 +66,36 @@ impl Graph {
     }
 }
 
+/// The contigs and the links between them, written as `{dump_path}.summary.json`.
+/// It is the input of [`crate::assembly_diff`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssemblySummary {
+    pub contigs: Vec<ContigSummary>,
+    pub links: Vec<Edge>,
+}
+
+/// How to name the contigs in the final assembly.
+/// In either way, if the previous assembly is found at `{dump_path}.summary.json`,
+/// `{dump_path}.names.tsv` maps its contigs to the new ones (See [`match_names`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContigNaming {
+    /// `tig_0000`, `tig_0001`, ... in the order the contigs are enumerated.
+    /// If there are more than one connected components, the names are prefixed by the index of the component (`cc0_tig_0000`).
+    Sequential,
+    /// A name derived from the (chunk, cluster, strand) of the both ends of a contig.
+    /// It does not change unless the ends of the contig change.
+    Content,
+}
+
+/// How to colour the segments in the Bandage-friendly GFA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    /// By the (mean) copy number of the contig.
+    CopyNumber,
+    /// By the phase block, i.e., the connected component of the contig graph.
+    PhaseBlock,
+}
+
 #[derive(Debug, Clone)]
 pub struct AssembleConfig {
     to_polish: bool,
@@ -73,6 +105,13 @@ pub struct AssembleConfig {
     span_likelihood_ratio: f64,
     to_bypass_contigs: bool,
     dump_path: Option<String>,
+    contig_naming: ContigNaming,
+    threshold_overrides: ThresholdOverrides,
+    bandage_colors: Option<ColorScheme>,
+    // Read ID -> the parent of the reads in the component, if the reads are labelled by the trio-binning.
+    read_parents: HashMap<u64, Parent>,
+    // The seed of the component, derived from `DataSet::seed`.
+    seed: u64,
 }
 
 impl std::default::Default for AssembleConfig {
@@ -85,6 +124,11 @@ impl std::default::Default for AssembleConfig {
             span_likelihood_ratio: 3f64,
             to_bypass_contigs: false,
             dump_path: None,
+            contig_naming: ContigNaming::Content,
+            threshold_overrides: ThresholdOverrides::default(),
+            bandage_colors: None,
+            read_parents: HashMap::new(),
+            seed: 0,
         }
     }
 }
@@ -107,8 +151,25 @@ impl AssembleConfig {
             span_likelihood_ratio,
             to_bypass_contigs,
             dump_path,
+            contig_naming: ContigNaming::Content,
+            threshold_overrides: ThresholdOverrides::default(),
+            bandage_colors: None,
+            read_parents: HashMap::new(),
+            seed: 0,
         }
     }
+    pub fn set_contig_naming(&mut self, contig_naming: ContigNaming) {
+        self.contig_naming = contig_naming;
+    }
+    /// Fix some of the clean-up thresholds instead of deriving them from the data.
+    pub fn set_threshold_overrides(&mut self, overrides: ThresholdOverrides) {
+        self.threshold_overrides = overrides;
+    }
+    /// Add `CL`/`LN`/`RC`/`dp` tags for Bandage, and write `{dump_path}.bandage.csv`
+    /// and Graphviz DOT files of small components.
+    pub fn set_bandage_export(&mut self, colors: Option<ColorScheme>) {
+        self.bandage_colors = colors;
+    }
 }
 
 pub trait Assemble {
@@ -153,6 +214,12 @@ impl Assemble for DataSet {
                 .map(|elm| format!("{}-{}", elm.chunk, elm.cluster))
                 .collect();
             debug!("CONUNIT\t{}\t{}\t{}", summary.id, copy_num, ids.join("\t"));
+            if let Some(position) = summary.reference.as_ref() {
+                debug!(
+                    "CONREF\t{}\t{position}\t{:.3}",
+                    summary.id, position.identity
+                );
+            }
         }
         let header = gfa::Content::Header(gfa::Header::default());
         let header = gfa::Record::from_contents(header, vec![].into());
@@ -162,26 +229,89 @@ impl Assemble for DataSet {
     }
 }
 
-const LOWER_FRAC: f64 = 0.15;
 const MAX_COV: f64 = 50.0;
+// Components with more nodes than this value are not rendered in DOT.
+const MAX_DOT_NODES: usize = 500;
+// Components with fewer chunks than this value use the global haploid coverage.
+const MIN_CHUNKS_FOR_COV: usize = 20;
 /// ASSEMBLEIMPL
 pub fn assemble(ds: &DataSet, c: &AssembleConfig) -> (Vec<gfa::Record>, Vec<ContigSummary>) {
     assert!(c.to_resolve);
-    let reads: Vec<_> = ds.encoded_reads.iter().collect();
     let cov = ds.coverage.unwrap();
-    let mut graph = DitchGraph::new(&reads, &ds.selected_chunks, ds.read_type, c);
-    debug!("GRAPH\t{graph}");
-    let thr = (cov * LOWER_FRAC).round() as usize;
-    graph.remove_lightweight_edges(thr / 2 + 1, false);
-    graph.remove_lightweight_edges(thr, true);
-    graph.clean_up_graph_for_assemble(cov, &reads, c, ds.read_type);
-    let (mut segments, mut edges, _, summaries, encodings) = graph.spell(c);
+    let components = split_into_components(&ds.encoded_reads);
+    debug!("COMPONENTS\t{}", components.len());
+    let read_parents = crate::trio_binning::read_parents(ds);
+    use rayon::prelude::*;
+    let assembled: Vec<_> = components
+        .par_iter()
+        .enumerate()
+        .map(|(i, reads)| {
+            let cov = match ds.coverage.is_protected() {
+                true => cov,
+                false => estimate_component_coverage(reads, cov, ds.ploidy),
+            };
+            debug!("COMPONENT\t{i}\t{}\t{cov:.2}", reads.len());
+            let mut c = c.clone();
+            if components.len() > 1 {
+                c.dump_path = c.dump_path.as_ref().map(|path| format!("{path}_cc{i}"));
+            }
+            c.seed = crate::misc::sub_seed(ds.seed, "assemble", i as u64);
+            c.read_parents = reads
+                .iter()
+                .filter_map(|r| read_parents.get(&r.id).map(|&p| (r.id, p)))
+                .collect();
+            let prefix = match components.len() {
+                1 => "tig".to_string(),
+                _ => format!("cc{i}_tig"),
+            };
+            assemble_component(ds, reads, cov, &c, &prefix)
+        })
+        .collect();
+    let (mut segments, mut edges, mut summaries, mut encodings) = (vec![], vec![], vec![], vec![]);
+    for (segs, eds, sums, encs) in assembled {
+        segments.extend(segs);
+        edges.extend(eds);
+        summaries.extend(sums);
+        encodings.extend(encs);
+    }
     let total_base = segments.iter().map(|x| x.slen).sum::<u64>();
     debug!("{} segments({} bp in total).", segments.len(), total_base);
+    if c.contig_naming == ContigNaming::Content {
+        rename_by_content(&mut segments, &mut edges, &mut summaries, &mut encodings);
+    }
+    locate_contigs(ds, &mut summaries);
+    if let Some(path) = c.dump_path.as_ref() {
+        let links = edges
+            .iter()
+            .map(|(e, _)| Edge {
+                from: e.sid1.id.clone(),
+                from_tail: e.beg1.pos != 0,
+                to: e.sid2.id.clone(),
+                to_tail: e.beg2.pos != 0,
+            })
+            .collect();
+        let summary = AssemblySummary {
+            contigs: summaries.clone(),
+            links,
+        };
+        if let Some(previous) = read_summary(&format!("{path}.summary.json")) {
+            let names = format!("{path}.names.tsv");
+            if let Err(why) = dump_names(&names, &match_names(&previous, &summary)) {
+                warn!("{why:?},{names}");
+            }
+        }
+        let path = format!("{path}.summary.json");
+        let dumped = std::fs::File::create(&path)
+            .map(std::io::BufWriter::new)
+            .map(|wtr| serde_json::ser::to_writer(wtr, &summary));
+        if !matches!(dumped, Ok(Ok(_))) {
+            warn!("Failed to write {path}");
+        }
+    }
     if c.to_polish {
         use crate::consensus;
         use crate::consensus::Polish;
-        let seed = 394802;
+        let seed = crate::misc::sub_seed(ds.seed, "polish", 0);
         let radius = ds.read_type.band_width(c.window_size).max(20) - 10;
         let round = 3;
         let (min_cov, max_cov) = (c.min_span_reads, (cov * 2f64).min(MAX_COV) as usize);
@@ -203,7 +333,23 @@ pub fn assemble(ds: &DataSet, c: &AssembleConfig) -> (Vec<gfa::Record>, Vec<Cont
             }
         }
     }
-    let mut groups: HashMap<_, Vec<_>> = HashMap::new();
+    let phase_blocks = phase_blocks(&segments, &edges);
+    let node_parents = count_node_parents(ds, &read_parents);
+    let hic_phases = crate::hic::phase_contigs(&summaries, &ds.hic_edges);
+    let chunk_lens: HashMap<_, _> = ds
+        .selected_chunks
+        .iter()
+        .map(|c| (c.id, c.seq().len()))
+        .collect();
+    let node_haplotypes: HashMap<_, _> = ds
+        .phased_nodes
+        .iter()
+        .map(|n| ((n.chunk, n.cluster), (n.block, n.haplotypes.clone())))
+        .collect();
+    let mut bandage_rows = vec![];
+    // Ordered, so that the group records are written in the same order in every run.
+    let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    let mut parent_groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
     let nodes: Vec<_> = segments
         .into_iter()
         .map(|node| {
@@ -220,17 +366,74 @@ pub fn assemble(ds: &DataSet, c: &AssembleConfig) -> (Vec<gfa::Record>, Vec<Cont
                         .filter_map(|elm| elm.copy_number)
                         .fold((0, 0), |(cp, num), x| (cp + x, num + 1));
                     let copynum = (cp as f64 / cpnum.max(1) as f64).round() as usize;
-                    let mut tags = vec![coverage];
+                    let chunks = gfa::SamTag::new(format!("nc:i:{}", contigsummary.summary.len()));
+                    let total_len: usize = contigsummary
+                        .summary
+                        .iter()
+                        .filter_map(|n| chunk_lens.get(&n.chunk))
+                        .sum();
+                    let chunk_len = total_len / contigsummary.summary.len().max(1);
+                    let chunk_len = gfa::SamTag::new(format!("cl:i:{chunk_len}"));
+                    let mut tags = vec![coverage, chunks, chunk_len];
                     if cpnum != 0 {
                         tags.push(gfa::SamTag::new(format!("cp:i:{copynum}")));
                     }
                     groups.entry(copynum).or_default().push(node.sid.clone());
+                    if let Some(position) = contigsummary.reference.as_ref() {
+                        tags.push(gfa::SamTag::new(format!("rf:Z:{position}")));
+                        let identity = position.identity;
+                        tags.push(gfa::SamTag::new(format!("ri:f:{identity:.3}")));
+                    }
+                    if let Some(phase) = hic_phases.get(&node.sid) {
+                        tags.push(gfa::SamTag::new(format!("ps:i:{}", phase.phase_set)));
+                        tags.push(gfa::SamTag::new(format!("hp:i:{}", phase.haplotype)));
+                    }
+                    if let Some((block, haps)) = contig_haplotypes(contigsummary, &node_haplotypes)
+                    {
+                        let haps: Vec<_> = haps.iter().map(|h| h.to_string()).collect();
+                        tags.push(gfa::SamTag::new(format!("pb:i:{block}")));
+                        tags.push(gfa::SamTag::new(format!("hs:Z:{}", haps.join(","))));
+                    }
+                    if let Some(parent) = contig_parent(contigsummary, &node_parents) {
+                        tags.push(gfa::SamTag::new(format!("HP:Z:{parent}")));
+                        parent_groups
+                            .entry(parent)
+                            .or_default()
+                            .push(node.sid.clone());
+                    }
+                    if let Some(scheme) = c.bandage_colors {
+                        let block = phase_blocks[&node.sid];
+                        let color = match scheme {
+                            ColorScheme::CopyNumber => copy_number_color(copynum),
+                            ColorScheme::PhaseBlock => phase_block_color(block),
+                        };
+                        let depth = total as f64 / contigsummary.summary.len() as f64;
+                        tags.push(gfa::SamTag::new(format!("LN:i:{}", node.slen)));
+                        tags.push(gfa::SamTag::new(format!("RC:i:{total}")));
+                        tags.push(gfa::SamTag::new(format!("dp:f:{depth:.2}")));
+                        tags.push(gfa::SamTag::new(format!("CL:z:{color}")));
+                        bandage_rows.push(BandageRow {
+                            name: node.sid.clone(),
+                            length: node.slen,
+                            depth,
+                            copy_number: copynum,
+                            phase_block: block,
+                            color,
+                            chunks: contigsummary.summary.len(),
+                        });
+                    }
                     tags
                 })
                 .unwrap_or_else(Vec::new);
             gfa::Record::from_contents(gfa::Content::Seg(node), tags.into())
         })
         .collect();
+    if let (Some(_), Some(path)) = (c.bandage_colors, c.dump_path.as_ref()) {
+        let path = format!("{path}.bandage.csv");
+        if let Err(why) = dump_bandage_csv(&path, &bandage_rows) {
+            warn!("{why:?},{path}");
+        }
+    }
     let edges = edges
         .into_iter()
         .map(|(edge, tags)| gfa::Record::from_contents(gfa::Content::Edge(edge), tags.into()));
@@ -242,11 +445,480 @@ pub fn assemble(ds: &DataSet, c: &AssembleConfig) -> (Vec<gfa::Record>, Vec<Cont
         let group = gfa::Content::Group(gfa::Group::Set(group));
         gfa::Record::from_contents(group, vec![].into())
     });
+    let parent_groups = parent_groups.into_iter().map(|(parent, ids)| {
+        let group = gfa::UnorderedGroup {
+            uid: Some(format!("HP:Z:{parent}")),
+            ids,
+        };
+        let group = gfa::Content::Group(gfa::Group::Set(group));
+        gfa::Record::from_contents(group, vec![].into())
+    });
     // let group = gfa::Record::from_contents(gfa::Content::Group(group), vec![].into());
-    let records: Vec<_> = groups.chain(nodes).chain(edges).collect();
+    let records: Vec<_> = groups
+        .chain(parent_groups)
+        .chain(nodes)
+        .chain(edges)
+        .collect();
     (records, summaries)
 }
 
+type AssembledComponent = (
+    Vec<gfa::Segment>,
+    Vec<(gfa::Edge, Vec<gfa::SamTag>)>,
+    Vec<ContigSummary>,
+    Vec<ContigEncoding>,
+);
+
+fn assemble_component(
+    ds: &DataSet,
+    reads: &[&EncodedRead],
+    cov: f64,
+    c: &AssembleConfig,
+    prefix: &str,
+) -> AssembledComponent {
+    let mut graph = DitchGraph::new(reads, &ds.selected_chunks, ds.read_type, c);
+    debug!("GRAPH\t{prefix}\t{graph}");
+    let thresholds = cleanup_thresholds(ds, reads, cov, c);
+    let thr = (cov * thresholds.lightweight_edge_frac).round() as usize;
+    graph.remove_lightweight_edges(thr / 2 + 1, false);
+    graph.remove_lightweight_edges(thr, true);
+    graph.clean_up_graph_for_assemble(cov, reads, c, &thresholds, ds.read_type);
+    if let (Some(_), Some(path)) = (c.bandage_colors, c.dump_path.as_ref()) {
+        if graph.active_nodes() <= MAX_DOT_NODES {
+            let path = format!("{path}.dot");
+            if let Err(why) = std::fs::write(&path, graph.to_dot(prefix)) {
+                warn!("{why:?},{path}");
+            }
+        }
+    }
+    let (segments, edges, _, summaries, encodings) = graph.spell_with_prefix(c, prefix);
+    (segments, edges, summaries, encodings)
+}
+
+// Derive the clean-up thresholds from the coverage and the read lengths,
+// then apply the overrides in the config.
+fn cleanup_thresholds(
+    ds: &DataSet,
+    reads: &[&EncodedRead],
+    cov: f64,
+    c: &AssembleConfig,
+) -> CleanupThresholds {
+    let lens: Vec<_> = reads.iter().map(|r| r.original_length).collect();
+    let mut chunk_lens: Vec<_> = ds.selected_chunks.iter().map(|c| c.seq().len()).collect();
+    chunk_lens.sort_unstable();
+    let chunk_len = chunk_lens.get(chunk_lens.len() / 2).copied().unwrap_or(0);
+    let estimated = CleanupThresholds::estimate(cov, &lens, chunk_len);
+    let thresholds = estimated.overridden_by(&c.threshold_overrides);
+    debug!("THRESHOLD\tEdge\tTip\tDiag\tZC\tZCFinal\tZCP\tZCPFinal\tNet");
+    debug!("THRESHOLD\tEstimated\t{estimated}");
+    debug!("THRESHOLD\tUsed\t{thresholds}");
+    thresholds
+}
+
+/// Split the reads into the connected components, i.e., two reads are in the same component
+/// if they share a chunk, directly or indirectly.
+/// The components are sorted by the smallest chunk ID in them, so that the order is stable across runs.
+pub fn split_into_components(reads: &[EncodedRead]) -> Vec<Vec<&EncodedRead>> {
+    use crate::find_union::FindUnion;
+    let max_chunk = reads
+        .iter()
+        .flat_map(|r| r.nodes.iter())
+        .map(|n| n.chunk as usize)
+        .max();
+    let max_chunk = match max_chunk {
+        Some(max) => max,
+        None => return vec![],
+    };
+    let mut fu = FindUnion::new(max_chunk + 1);
+    for read in reads.iter() {
+        for w in read.nodes.windows(2) {
+            fu.unite(w[0].chunk as usize, w[1].chunk as usize);
+        }
+    }
+    let mut components: HashMap<usize, Vec<&EncodedRead>> = HashMap::new();
+    for read in reads.iter().filter(|r| !r.nodes.is_empty()) {
+        let root = fu.find(read.nodes[0].chunk as usize).unwrap();
+        components.entry(root).or_default().push(read);
+    }
+    let mut components: Vec<_> = components
+        .into_values()
+        .map(|reads| {
+            let min = reads
+                .iter()
+                .flat_map(|r| r.nodes.iter())
+                .map(|n| n.chunk)
+                .min();
+            (min, reads)
+        })
+        .collect();
+    components.sort_by_key(|x| x.0);
+    components.into_iter().map(|x| x.1).collect()
+}
+
+// The haploid coverage of a component, estimated in the same way as `misc::update_coverage`.
+// If the component is too small, it falls back to the `global` coverage.
+fn estimate_component_coverage(reads: &[&EncodedRead], global: f64, ploidy: usize) -> f64 {
+    let mut counts: HashMap<_, u32> = HashMap::new();
+    for node in reads.iter().flat_map(|r| r.nodes.iter()) {
+        *counts.entry(node.chunk).or_default() += 1;
+    }
+    if counts.len() < MIN_CHUNKS_FOR_COV {
+        return global;
+    }
+    let mut counts: Vec<_> = counts.values().copied().collect();
+    counts.sort_unstable();
+    counts[counts.len() / 2] as f64 / ploidy.max(1) as f64
+}
+
+// The (chunk, cluster, strand)s of the contig, spelled in the direction giving the smaller sequence.
+// As a contig can be spelled in both direction, the canonical one does not depend on how it is enumerated.
+fn canonical_nodes(summary: &ContigSummary) -> Vec<(u64, u64, bool)> {
+    let forward: Vec<_> = summary
+        .summary
+        .iter()
+        .map(|n| (n.chunk, n.cluster, n.strand))
+        .collect();
+    let reverse: Vec<_> = summary
+        .summary
+        .iter()
+        .rev()
+        .map(|n| (n.chunk, n.cluster, !n.strand))
+        .collect();
+    forward.min(reverse)
+}
+
+// The name of the contig, determined only by the both ends of the contig.
+fn content_name(nodes: &[(u64, u64, bool)]) -> String {
+    let (first, last) = match (nodes.first(), nodes.last()) {
+        (Some(f), Some(l)) => (f, l),
+        _ => return "tig_empty".to_string(),
+    };
+    let mut bytes = vec![];
+    for &&(chunk, cluster, strand) in [first, last].iter() {
+        bytes.extend(chunk.to_le_bytes());
+        bytes.extend(cluster.to_le_bytes());
+        bytes.extend((strand as u64).to_le_bytes());
+    }
+    format!("tig_{:08x}", crate::misc::fnv1a(&bytes) as u32)
+}
+
+/// Rename the contigs based on their content (See [`ContigNaming::Content`]).
+/// Contigs sharing the both ends get suffixes in the order of their canonical (chunk, cluster, strand)s,
+/// so the names do not depend on the order of the contigs nor the components.
+/// Return the list of (old name, new name).
+pub fn rename_by_content(
+    segments: &mut [gfa::Segment],
+    edges: &mut [(gfa::Edge, Vec<gfa::SamTag>)],
+    summaries: &mut [ContigSummary],
+    encodings: &mut [ContigEncoding],
+) -> Vec<(String, String)> {
+    let mut contents: Vec<_> = summaries
+        .iter()
+        .map(|summary| {
+            let nodes = canonical_nodes(summary);
+            (content_name(&nodes), nodes, summary.id.clone())
+        })
+        .collect();
+    contents.sort();
+    let mut renamed: Vec<(String, String)> = vec![];
+    let mut used: HashMap<String, usize> = HashMap::new();
+    for (name, _, id) in contents {
+        let count = used.entry(name.clone()).or_default();
+        let name = match *count {
+            0 => name,
+            x => format!("{name}_{x}"),
+        };
+        *count += 1;
+        renamed.push((id, name));
+    }
+    let table: HashMap<_, _> = renamed.iter().cloned().collect();
+    for summary in summaries.iter_mut() {
+        summary.id = table[&summary.id].clone();
+    }
+    for seg in segments.iter_mut() {
+        seg.sid = table[&seg.sid].clone();
+    }
+    for enc in encodings.iter_mut() {
+        enc.id = table[&enc.id].clone();
+    }
+    for (edge, _) in edges.iter_mut() {
+        edge.sid1.id = table[&edge.sid1.id].clone();
+        edge.sid2.id = table[&edge.sid2.id].clone();
+    }
+    renamed
+}
+
+// A row of the companion CSV of the Bandage-friendly GFA.
+struct BandageRow {
+    name: String,
+    length: u64,
+    depth: f64,
+    copy_number: usize,
+    phase_block: usize,
+    color: &'static str,
+    chunks: usize,
+}
+
+fn dump_bandage_csv(path: &str, rows: &[BandageRow]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut wtr = std::fs::File::create(path).map(std::io::BufWriter::new)?;
+    writeln!(wtr, "Name,Length,Depth,CopyNumber,PhaseBlock,Colour,Chunks")?;
+    for row in rows.iter() {
+        let BandageRow {
+            name,
+            length,
+            depth,
+            copy_number,
+            phase_block,
+            color,
+            chunks,
+        } = row;
+        writeln!(
+            wtr,
+            "{name},{length},{depth:.2},{copy_number},{phase_block},{color},{chunks}"
+        )?;
+    }
+    Ok(())
+}
+
+// Index the connected components of the contig graph. The index is determined by the first segment in the component.
+fn phase_blocks(
+    segments: &[gfa::Segment],
+    edges: &[(gfa::Edge, Vec<gfa::SamTag>)],
+) -> HashMap<String, usize> {
+    use crate::find_union::FindUnion;
+    let index: HashMap<_, _> = segments
+        .iter()
+        .enumerate()
+        .map(|(i, seg)| (seg.sid.as_str(), i))
+        .collect();
+    let mut fu = FindUnion::new(segments.len());
+    for (edge, _) in edges.iter() {
+        if let (Some(&i), Some(&j)) = (
+            index.get(edge.sid1.id.as_str()),
+            index.get(edge.sid2.id.as_str()),
+        ) {
+            fu.unite(i, j);
+        }
+    }
+    let mut blocks: HashMap<usize, usize> = HashMap::new();
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, seg)| {
+            let root = fu.find(i).unwrap();
+            let len = blocks.len();
+            let block = *blocks.entry(root).or_insert(len);
+            (seg.sid.clone(), block)
+        })
+        .collect()
+}
+
+const COPY_NUMBER_COLORS: [&str; 5] = ["#BBBBBB", "#4477AA", "#228833", "#CCBB44", "#EE6677"];
+const PHASE_BLOCK_COLORS: [&str; 8] = [
+    "#4477AA", "#EE6677", "#228833", "#CCBB44", "#66CCEE", "#AA3377", "#332288", "#999933",
+];
+
+fn copy_number_color(copy_number: usize) -> &'static str {
+    COPY_NUMBER_COLORS[copy_number.min(COPY_NUMBER_COLORS.len() - 1)]
+}
+
+fn phase_block_color(block: usize) -> &'static str {
+    PHASE_BLOCK_COLORS[block % PHASE_BLOCK_COLORS.len()]
+}
+
+// The tags written in the FASTA headers.
+const FASTA_TAGS: [&str; 7] = ["cp", "cv", "ps", "hp", "pb", "hs", "HP"];
+
+/// Convert the segments of the assembly into FASTA. The headers have the copy number, the coverage,
+/// and the phasing (`ps`, `hp`, `pb`, `hs`, and `HP`) tags, if any.
+/// The phased contigs come first, sorted by the phase set and the haplotype.
+pub fn to_fasta(gfa: &GFA) -> String {
+    let mut records: Vec<_> = gfa
+        .iter()
+        .filter_map(|record| match &record.content {
+            gfa::Content::Seg(seg) => {
+                let tags: Vec<_> = record
+                    .tags
+                    .iter()
+                    .filter(|tag| FASTA_TAGS.iter().any(|t| tag.inner.starts_with(t)))
+                    .map(|tag| tag.inner.as_str())
+                    .collect();
+                let tag_value = |name: &str| -> Option<usize> {
+                    tags.iter()
+                        .find(|tag| tag.starts_with(name))
+                        .and_then(|tag| tag.split(':').nth(2))
+                        .and_then(|x| x.parse().ok())
+                };
+                let key = (tag_value("ps:"), tag_value("hp:"));
+                let seq = seg.sequence.as_deref().unwrap_or("");
+                let header: Vec<_> = std::iter::once(seg.sid.as_str()).chain(tags).collect();
+                Some((key, format!(">{}\n{}", header.join(" "), seq)))
+            }
+            _ => None,
+        })
+        .collect();
+    records.sort_by_key(|(key, _)| (key.0.is_none(), *key));
+    let records: Vec<_> = records.into_iter().map(|(_, record)| record).collect();
+    records.join("\n")
+}
+
+/// Split the segments of the assembly into `ploidy` sequence sets, one for each haplotype, in FASTA.
+/// A contig phased by the reads (`hs:Z:` tag) goes to the haplotypes carrying it, so a contig with
+/// dosage two appears in two sets. Unphased contigs are shared by all the sets.
+pub fn to_haplotype_fasta(gfa: &GFA, ploidy: usize) -> Vec<String> {
+    let mut sets = vec![vec![]; ploidy];
+    for record in gfa.iter() {
+        if let gfa::Content::Seg(seg) = &record.content {
+            let tags: Vec<_> = record
+                .tags
+                .iter()
+                .filter(|tag| FASTA_TAGS.iter().any(|t| tag.inner.starts_with(t)))
+                .map(|tag| tag.inner.as_str())
+                .collect();
+            let haps: Vec<usize> = match tags.iter().find(|tag| tag.starts_with("hs:")) {
+                Some(tag) => tag[5..].split(',').filter_map(|h| h.parse().ok()).collect(),
+                None => (1..=ploidy).collect(),
+            };
+            let seq = seg.sequence.as_deref().unwrap_or("");
+            let header: Vec<_> = std::iter::once(seg.sid.as_str()).chain(tags).collect();
+            let record = format!(">{}\n{}", header.join(" "), seq);
+            for hap in haps.into_iter().filter(|&h| 0 < h && h <= ploidy) {
+                sets[hap - 1].push(record.clone());
+            }
+        }
+    }
+    sets.into_iter().map(|records| records.join("\n")).collect()
+}
+
+// The phase block and the haplotypes of the contig, by the phased nodes on it.
+// The most frequent (block, haplotypes) pair is taken if it explains at least half of the phased nodes.
+fn contig_haplotypes(
+    summary: &ContigSummary,
+    node_haplotypes: &HashMap<(u64, u64), (usize, Vec<usize>)>,
+) -> Option<(usize, Vec<usize>)> {
+    let mut counts: HashMap<_, usize> = HashMap::new();
+    for elm in summary.summary.iter() {
+        if let Some(phase) = node_haplotypes.get(&(elm.chunk, elm.cluster)) {
+            *counts.entry(phase).or_default() += 1;
+        }
+    }
+    let total: usize = counts.values().sum();
+    let (phase, max) = counts
+        .into_iter()
+        .max_by(|(x, c), (y, d)| c.cmp(d).then(y.cmp(x)))?;
+    (total <= 2 * max).then(|| phase.clone())
+}
+
+// (chunk, cluster) -> the number of the maternal and paternal reads on it.
+fn count_node_parents(
+    ds: &DataSet,
+    read_parents: &HashMap<u64, Parent>,
+) -> HashMap<(u64, u64), (usize, usize)> {
+    let mut counts: HashMap<_, (usize, usize)> = HashMap::new();
+    for read in ds.encoded_reads.iter() {
+        let parent = match read_parents.get(&read.id) {
+            Some(parent) => parent,
+            None => continue,
+        };
+        for node in read.nodes.iter() {
+            let count = counts.entry((node.chunk, node.cluster)).or_default();
+            match parent {
+                Parent::Maternal => count.0 += 1,
+                Parent::Paternal => count.1 += 1,
+            }
+        }
+    }
+    counts
+}
+
+// The parental origin of the contig, by the labelled reads on its nodes.
+fn contig_parent(
+    summary: &ContigSummary,
+    node_parents: &HashMap<(u64, u64), (usize, usize)>,
+) -> Option<Parent> {
+    let (maternal, paternal) = summary
+        .summary
+        .iter()
+        .filter_map(|elm| node_parents.get(&(elm.chunk, elm.cluster)))
+        .fold((0, 0), |(m, p), &(x, y)| (m + x, p + y));
+    crate::trio_binning::majority_of_counts(maternal, paternal)
+}
+
+// Place the contigs on the reference by the positions of their chunks.
+fn locate_contigs(ds: &DataSet, summaries: &mut [ContigSummary]) {
+    let positions: HashMap<_, _> = ds
+        .selected_chunks
+        .iter()
+        .filter_map(|c| c.reference.as_ref().map(|p| (c.id, p)))
+        .collect();
+    if positions.is_empty() {
+        return;
+    }
+    for summary in summaries.iter_mut() {
+        let elements = summary
+            .summary
+            .iter()
+            .filter_map(|elm| positions.get(&elm.chunk).map(|&p| (elm.strand, p.clone())));
+        summary.reference = crate::locate_chunks::contig_position(elements);
+    }
+}
+
+fn dump_names(path: &str, names: &[(String, Option<String>)]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut wtr = std::fs::File::create(path).map(std::io::BufWriter::new)?;
+    writeln!(wtr, "old\tnew")?;
+    for (old, new) in names.iter() {
+        let new = new.as_deref().unwrap_or("*");
+        writeln!(wtr, "{old}\t{new}")?;
+    }
+    Ok(())
+}
+
+fn read_summary(path: &str) -> Option<AssemblySummary> {
+    let rdr = std::fs::File::open(path)
+        .map(std::io::BufReader::new)
+        .ok()?;
+    serde_json::de::from_reader(rdr).ok()
+}
+
+/// Map the contigs of the `previous` assembly to the contigs of the `current` one sharing the most (chunk, cluster)s.
+/// Return the list of (old name, new name), where the new name is `None` if no (chunk, cluster) is shared.
+pub fn match_names(
+    previous: &AssemblySummary,
+    current: &AssemblySummary,
+) -> Vec<(String, Option<String>)> {
+    let mut node_to_contig: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, contig) in current.contigs.iter().enumerate() {
+        for n in contig.summary.iter() {
+            node_to_contig
+                .entry((n.chunk, n.cluster))
+                .or_default()
+                .push(i);
+        }
+    }
+    previous
+        .contigs
+        .iter()
+        .map(|contig| {
+            let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+            let shared = contig
+                .summary
+                .iter()
+                .filter_map(|n| node_to_contig.get(&(n.chunk, n.cluster)))
+                .flatten();
+            for &i in shared {
+                *counts.entry(i).or_default() += 1;
+            }
+            let new = counts
+                .iter()
+                .max_by_key(|&(&i, &count)| (count, std::cmp::Reverse(i)))
+                .map(|(&i, _)| current.contigs[i].id.clone());
+            (contig.id.clone(), new)
+        })
+        .collect()
+}
+
 fn get_contig_copy_numbers(summaries: &[ContigSummary]) -> Vec<usize> {
     summaries
         .iter()
@@ -315,3 +987,264 @@ fn count_contig_connection(ds: &DataSet, summaries: &[ContigSummary]) -> Vec<Vec
     }
     shared_reads
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn gen_read(id: u64, chunks: &[u64]) -> EncodedRead {
+        let nodes: Vec<_> = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, &chunk)| {
+                let cigar = vec![definitions::Op::Match(10)];
+                definitions::Node::new(chunk, true, vec![b'A'; 10], cigar, 10 * i, 1)
+            })
+            .collect();
+        EncodedRead {
+            id,
+            original_length: 10 * chunks.len(),
+            nodes,
+            ..Default::default()
+        }
+    }
+    #[test]
+    fn split_into_components_test() {
+        let reads = vec![
+            gen_read(0, &[5, 6]),
+            gen_read(1, &[0, 1, 2]),
+            gen_read(2, &[6, 7]),
+            gen_read(3, &[2, 3]),
+            gen_read(4, &[]),
+        ];
+        let components = split_into_components(&reads);
+        let ids: Vec<Vec<_>> = components
+            .iter()
+            .map(|reads| reads.iter().map(|r| r.id).collect())
+            .collect();
+        assert_eq!(ids, vec![vec![1, 3], vec![0, 2]]);
+    }
+    #[test]
+    fn content_name_test() {
+        let elm = |chunk, cluster, strand| ContigElement {
+            chunk,
+            cluster,
+            strand,
+            occ: 10,
+            copy_number: Some(1),
+        };
+        let forward = ContigSummary {
+            id: "tig_0000".to_string(),
+            summary: vec![elm(0, 1, true), elm(1, 0, true), elm(2, 0, false)],
+            reference: None,
+        };
+        let reverse = ContigSummary {
+            id: "tig_0001".to_string(),
+            summary: vec![elm(2, 0, true), elm(1, 0, false), elm(0, 1, false)],
+            reference: None,
+        };
+        let other = ContigSummary {
+            id: "tig_0002".to_string(),
+            summary: vec![elm(0, 0, true), elm(1, 0, true), elm(2, 0, false)],
+            reference: None,
+        };
+        let name = content_name(&canonical_nodes(&forward));
+        assert_eq!(name, content_name(&canonical_nodes(&reverse)));
+        assert_ne!(name, content_name(&canonical_nodes(&other)));
+        // The names do not depend on the order of the contigs.
+        let mut summaries = vec![forward.clone(), other.clone(), reverse.clone()];
+        rename_by_content(&mut [], &mut [], &mut summaries, &mut []);
+        let mut swapped = vec![reverse, other, forward];
+        rename_by_content(&mut [], &mut [], &mut swapped, &mut []);
+        assert_eq!(summaries[0].id, swapped[2].id);
+        assert_eq!(summaries[1].id, swapped[1].id);
+        assert_eq!(summaries[2].id, swapped[0].id);
+        assert_eq!(summaries[0].id, name);
+        assert_eq!(summaries[2].id, format!("{name}_1"));
+        // The previous contigs are mapped to the new ones sharing the most chunks.
+        let previous = AssemblySummary {
+            contigs: summaries,
+            links: vec![],
+        };
+        let mut current = previous.clone();
+        current.contigs.truncate(2);
+        current.contigs[1].summary.truncate(1);
+        let names = match_names(&previous, &current);
+        assert_eq!(names[0], (name.clone(), Some(name.clone())));
+        assert_eq!(names[2], (format!("{name}_1"), Some(name)));
+    }
+    // Two haplotypes on eight chunks, differing at the odd chunks.
+    fn gen_diploid_dataset(seed: u64) -> DataSet {
+        use rand::{Rng, SeedableRng};
+        let mut rng: rand_xoshiro::Xoroshiro128StarStar = SeedableRng::seed_from_u64(seed);
+        let len = 8;
+        let mut chunks: Vec<_> = (0..len)
+            .map(|id| Chunk::new(id, vec![b'A'; 2_000], 2))
+            .collect();
+        chunks.iter_mut().filter(|c| c.id % 2 == 1).for_each(|c| {
+            c.cluster_num = 2;
+        });
+        let encoded_reads: Vec<_> = (0..60)
+            .map(|id| {
+                let hap = id % 2;
+                let start = rng.gen_range(0..len - 2);
+                let end = (start + rng.gen_range(3..6)).min(len);
+                let nodes: Vec<_> = (start..end)
+                    .enumerate()
+                    .map(|(i, chunk)| {
+                        let cluster = if chunk % 2 == 1 { hap } else { 0 };
+                        let cigar = vec![definitions::Op::Match(2_000)];
+                        let seq = vec![b'A'; 2_000];
+                        definitions::Node::new(chunk, true, seq, cigar, 2_000 * i, cluster)
+                    })
+                    .collect();
+                let edges = nodes
+                    .windows(2)
+                    .map(|w| definitions::Edge {
+                        from: w[0].chunk,
+                        to: w[1].chunk,
+                        offset: 0,
+                        label: Vec::<u8>::new().into(),
+                    })
+                    .collect();
+                EncodedRead {
+                    id,
+                    original_length: 2_000 * nodes.len(),
+                    edges,
+                    nodes,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let mut ds = DataSet::new();
+        ds.selected_chunks = chunks;
+        ds.encoded_reads = encoded_reads;
+        ds.coverage = Coverage::Protected(10f64);
+        ds.read_type = ReadType::CCS;
+        ds.seed = seed;
+        ds
+    }
+    #[test]
+    fn thread_independent_test() {
+        let ds = gen_diploid_dataset(4329);
+        let config = AssembleConfig::new(100, false, true, 4, 1f64, false, None);
+        let gfas: Vec<_> = [1, 4]
+            .iter()
+            .map(|&threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap()
+                    .install(|| format!("{}", ds.assemble(&config)))
+            })
+            .collect();
+        assert_eq!(gfas[0], gfas[1]);
+    }
+    // Two haplotypes differing at every 300bp, and error-free reads from them.
+    fn gen_raw_diploid_dataset(seed: u64) -> DataSet {
+        use rand::{Rng, SeedableRng};
+        let mut rng: rand_xoshiro::Xoroshiro128StarStar = SeedableRng::seed_from_u64(seed);
+        let (len, chunk_len, read_len) = (12_000, 2_000, 6_000);
+        let hap1: Vec<u8> = (0..len).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect();
+        let mut hap2 = hap1.clone();
+        for pos in (150..len).step_by(300) {
+            hap2[pos] = match hap1[pos] {
+                b'A' => b'C',
+                b'C' => b'G',
+                b'G' => b'T',
+                _ => b'A',
+            };
+        }
+        let mut ds = DataSet::new();
+        ds.selected_chunks = hap1
+            .chunks_exact(chunk_len)
+            .enumerate()
+            .map(|(id, seq)| Chunk::new(id as u64, seq.to_vec(), 2))
+            .collect();
+        ds.raw_reads = (0..60)
+            .map(|id| {
+                let hap = if id % 2 == 0 { &hap1 } else { &hap2 };
+                let start = rng.gen_range(0..len - read_len);
+                RawRead {
+                    name: format!("read{id}"),
+                    desc: String::new(),
+                    id,
+                    seq: hap[start..start + read_len].to_vec().into(),
+                }
+            })
+            .collect();
+        ds.aligner = Aligner::Builtin;
+        ds.coverage = Coverage::Protected(15f64);
+        ds.read_type = ReadType::CCS;
+        ds.seed = seed;
+        ds
+    }
+    #[test]
+    fn pipeline_thread_independent_test() {
+        use crate::encode::Encode;
+        use crate::local_clustering::LocalClustering;
+        let ds = gen_raw_diploid_dataset(2391);
+        let config = AssembleConfig::new(100, false, true, 4, 1f64, false, None);
+        let (sim_thr, sd) = (ds.read_type.sim_thr(), ds.read_type.sd_of_error());
+        let results: Vec<_> = [1, 4]
+            .iter()
+            .map(|&threads| {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                pool.install(|| {
+                    let mut ds = ds.clone();
+                    ds.encode(threads, sim_thr, sd);
+                    ds.local_clustering();
+                    let clusters: Vec<Vec<_>> = ds
+                        .encoded_reads
+                        .iter()
+                        .map(|r| r.nodes.iter().map(|n| (n.chunk, n.cluster)).collect())
+                        .collect();
+                    (clusters, format!("{}", ds.assemble(&config)))
+                })
+            })
+            .collect();
+        assert!(results[0].0.iter().any(|nodes| !nodes.is_empty()));
+        assert_eq!(results[0], results[1]);
+    }
+    #[test]
+    fn cleanup_thresholds_test() {
+        // ONT/CLR-like error rates should not move the thresholds from the former constants at 30x.
+        let reads: Vec<_> = (0..100)
+            .map(|id| EncodedRead {
+                id,
+                original_length: 20_000,
+                ..Default::default()
+            })
+            .collect();
+        let reads: Vec<_> = reads.iter().collect();
+        let config = AssembleConfig::default();
+        let default = CleanupThresholds::default();
+        for read_type in [ReadType::CCS, ReadType::CLR, ReadType::ONT] {
+            let mut ds = DataSet::new();
+            ds.selected_chunks = (0..10)
+                .map(|i| Chunk::new(i, vec![b'A'; 2_000], 2))
+                .collect();
+            ds.error_rate = ErrorRate::guess(read_type);
+            let thr = cleanup_thresholds(&ds, &reads, 30f64, &config);
+            assert!((thr.lightweight_edge_frac - default.lightweight_edge_frac).abs() < 0.001);
+            assert!((thr.tip_frac - default.tip_frac).abs() < 0.001);
+            assert!((thr.zero_copy_frac - default.zero_copy_frac).abs() < 0.001);
+            assert!((thr.zero_copy_path_frac - default.zero_copy_path_frac).abs() < 0.001);
+            let final_frac = default.zero_copy_path_frac_final;
+            assert!((thr.zero_copy_path_frac_final - final_frac).abs() < 0.001);
+        }
+    }
+    #[test]
+    fn bandage_color_test() {
+        assert_eq!(copy_number_color(0), COPY_NUMBER_COLORS[0]);
+        assert_eq!(copy_number_color(1), COPY_NUMBER_COLORS[1]);
+        assert_eq!(copy_number_color(100), COPY_NUMBER_COLORS[4]);
+        assert_ne!(phase_block_color(0), phase_block_color(1));
+        assert_eq!(
+            phase_block_color(0),
+            phase_block_color(PHASE_BLOCK_COLORS.len())
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Thresholds used to clean up the string graph before spelling contigs.
+//!
+//! They used to be hard-coded values tuned for ~30x haploid coverage. Here we derive them
+//! from the haploid coverage and the read length distribution of the dataset,
+//! so that they scale to low- or high-coverage data. Each of them can be overridden by
+//! [`ThresholdOverrides`].
+use crate::copy_number_estimation::CoverageCalibrator;
+use serde::*;
+
+// The coverage the former hard-coded values were tuned for.
+// The z-scores below are chosen so that the derived fractions are the same as the former ones at this coverage.
+// The derived lengths depend on the read lengths instead;
+// they are the same as the former ones for 20kbp reads and 2kbp chunks.
+const REFERENCE_COV: f64 = 30f64;
+// The fraction of the reads spanning a net should exceed this value to squish it.
+const SPAN_PROB: f64 = 0.6;
+// The fraction of the reads spanning a node should exceed this value for the node to be in the local coverage.
+const LOCAL_SPAN_PROB: f64 = 0.5;
+
+/// Thresholds for the graph clean-up. See [`CleanupThresholds::estimate`] for how they are derived.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CleanupThresholds {
+    /// Edges with less than `lightweight_edge_frac * cov` reads are removed before the clean-up.
+    pub lightweight_edge_frac: f64,
+    /// Zero-copy tips with less than `tip_frac * local coverage` reads are removed.
+    pub tip_frac: f64,
+    /// The diameter (in chunks) to compute the local coverage around tips.
+    pub tip_diag: usize,
+    /// `thr` of `remove_zero_copy_elements` during the repeat resolution.
+    pub zero_copy_frac: f64,
+    /// `thr` of `remove_zero_copy_elements` after the repeat resolution.
+    pub zero_copy_frac_final: f64,
+    /// `thr` of `remove_zero_copy_path` during the repeat resolution.
+    pub zero_copy_path_frac: f64,
+    /// `thr` of `remove_zero_copy_path` after the repeat resolution.
+    pub zero_copy_path_frac_final: f64,
+    /// Nets consisting of contigs up to this length (in chunks) are squished.
+    pub small_net_len: usize,
+}
+
+impl std::default::Default for CleanupThresholds {
+    fn default() -> Self {
+        Self {
+            lightweight_edge_frac: 0.15,
+            tip_frac: 0.8,
+            tip_diag: 4,
+            zero_copy_frac: 0.8,
+            zero_copy_frac_final: 0.9,
+            zero_copy_path_frac: 0.1,
+            zero_copy_path_frac_final: 0.3,
+            small_net_len: 3,
+        }
+    }
+}
+
+impl std::fmt::Display for CleanupThresholds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.3}\t{:.3}\t{}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{}",
+            self.lightweight_edge_frac,
+            self.tip_frac,
+            self.tip_diag,
+            self.zero_copy_frac,
+            self.zero_copy_frac_final,
+            self.zero_copy_path_frac,
+            self.zero_copy_path_frac_final,
+            self.small_net_len,
+        )
+    }
+}
+
+impl CleanupThresholds {
+    /// Derive the thresholds from the haploid coverage `cov`, the length distribution of the reads,
+    /// and the length of the chunks.
+    ///
+    /// The occurrence of a single-copy node follows Poisson distribution with mean `cov`, so the
+    /// relative deviation of the coverage is `1/sqrt(cov)`.
+    /// - Fractions to *keep* true elements (tips, zero-copy elements) are `1 - z/sqrt(cov)`,
+    ///   i.e., they get closer to 1 as the coverage gets higher.
+    /// - Fractions to *remove* erroneous elements (light edges, zero-copy paths) are `z/sqrt(cov)`.
+    ///   They do not depend on the per-base error rate, as the similarity threshold of the encoding
+    ///   is adjusted to it, and the erroneous elements come from the few mis-encoded reads.
+    /// - Lengths are the number of chunks spanned by most of the reads.
+    pub fn estimate(cov: f64, lens: &[usize], chunk_len: usize) -> Self {
+        if cov <= 0f64 || lens.is_empty() || chunk_len == 0 {
+            return Self::default();
+        }
+        let default = Self::default();
+        let dev = cov.recip().sqrt();
+        let ref_dev = REFERENCE_COV.recip().sqrt();
+        // z-score reproducing `frac` at the reference coverage.
+        let keep = |frac: f64| 1f64 - (1f64 - frac) / ref_dev * dev;
+        let remove = |frac: f64| frac / ref_dev * dev;
+        let calibrator = CoverageCalibrator::new(lens);
+        let spanned_len = |prob: f64| {
+            (1..)
+                .take_while(|&len| prob < calibrator.prob_spanning(len * chunk_len))
+                .take(16)
+                .last()
+                .unwrap_or(1)
+        };
+        Self {
+            lightweight_edge_frac: remove(default.lightweight_edge_frac).clamp(0.05, 0.3),
+            tip_frac: keep(default.tip_frac).clamp(0.5, 0.95),
+            tip_diag: spanned_len(LOCAL_SPAN_PROB).clamp(2, 8),
+            zero_copy_frac: keep(default.zero_copy_frac).clamp(0.5, 0.95),
+            zero_copy_frac_final: keep(default.zero_copy_frac_final).clamp(0.6, 0.99),
+            zero_copy_path_frac: remove(default.zero_copy_path_frac).clamp(0.05, 0.3),
+            zero_copy_path_frac_final: remove(default.zero_copy_path_frac_final).clamp(0.1, 0.5),
+            small_net_len: spanned_len(SPAN_PROB).clamp(2, 6),
+        }
+    }
+    /// Replace the thresholds by the overridden values, if any.
+    pub fn overridden_by(mut self, overrides: &ThresholdOverrides) -> Self {
+        let o = overrides;
+        let f = |x: &mut f64, y: Option<f64>| *x = y.unwrap_or(*x);
+        let u = |x: &mut usize, y: Option<usize>| *x = y.unwrap_or(*x);
+        f(&mut self.lightweight_edge_frac, o.lightweight_edge_frac);
+        f(&mut self.tip_frac, o.tip_frac);
+        u(&mut self.tip_diag, o.tip_diag);
+        f(&mut self.zero_copy_frac, o.zero_copy_frac);
+        f(&mut self.zero_copy_frac_final, o.zero_copy_frac_final);
+        f(&mut self.zero_copy_path_frac, o.zero_copy_path_frac);
+        f(
+            &mut self.zero_copy_path_frac_final,
+            o.zero_copy_path_frac_final,
+        );
+        u(&mut self.small_net_len, o.small_net_len);
+        self
+    }
+}
+
+/// User-specified values of [`CleanupThresholds`]. `None` means "derive from the data".
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThresholdOverrides {
+    pub lightweight_edge_frac: Option<f64>,
+    pub tip_frac: Option<f64>,
+    pub tip_diag: Option<usize>,
+    pub zero_copy_frac: Option<f64>,
+    pub zero_copy_frac_final: Option<f64>,
+    pub zero_copy_path_frac: Option<f64>,
+    pub zero_copy_path_frac_final: Option<f64>,
+    pub small_net_len: Option<usize>,
+}
+
+impl ThresholdOverrides {
+    /// Set a value from a `name=value` string, such as `tip_frac=0.7`.
+    pub fn set(&mut self, entry: &str) -> Result<(), String> {
+        let (name, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("{entry} is not in name=value form"))?;
+        let float = || value.parse::<f64>().map_err(|e| format!("{entry}:{e}"));
+        let int = || value.parse::<usize>().map_err(|e| format!("{entry}:{e}"));
+        match name {
+            "lightweight_edge_frac" => self.lightweight_edge_frac = Some(float()?),
+            "tip_frac" => self.tip_frac = Some(float()?),
+            "tip_diag" => self.tip_diag = Some(int()?),
+            "zero_copy_frac" => self.zero_copy_frac = Some(float()?),
+            "zero_copy_frac_final" => self.zero_copy_frac_final = Some(float()?),
+            "zero_copy_path_frac" => self.zero_copy_path_frac = Some(float()?),
+            "zero_copy_path_frac_final" => self.zero_copy_path_frac_final = Some(float()?),
+            "small_net_len" => self.small_net_len = Some(int()?),
+            _ => return Err(format!("Unknown threshold:{name}")),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn estimate_test() {
+        let lens = vec![20_000; 100];
+        let low = CleanupThresholds::estimate(10f64, &lens, 2_000);
+        let reference = CleanupThresholds::estimate(REFERENCE_COV, &lens, 2_000);
+        let high = CleanupThresholds::estimate(100f64, &lens, 2_000);
+        let default = CleanupThresholds::default();
+        assert!((reference.tip_frac - default.tip_frac).abs() < 0.001);
+        assert!((reference.zero_copy_path_frac - default.zero_copy_path_frac).abs() < 0.001);
+        assert!(low.tip_frac < reference.tip_frac && reference.tip_frac < high.tip_frac);
+        assert!(high.lightweight_edge_frac < low.lightweight_edge_frac);
+        let short = CleanupThresholds::estimate(REFERENCE_COV, &vec![5_000; 100], 2_000);
+        assert!(short.small_net_len <= reference.small_net_len);
+    }
+    #[test]
+    fn reference_coverage_test() {
+        let lens = vec![20_000; 100];
+        let derived = CleanupThresholds::estimate(REFERENCE_COV, &lens, 2_000);
+        let default = CleanupThresholds::default();
+        let fracs = [
+            (derived.lightweight_edge_frac, default.lightweight_edge_frac),
+            (derived.tip_frac, default.tip_frac),
+            (derived.zero_copy_frac, default.zero_copy_frac),
+            (derived.zero_copy_frac_final, default.zero_copy_frac_final),
+            (derived.zero_copy_path_frac, default.zero_copy_path_frac),
+            (
+                derived.zero_copy_path_frac_final,
+                default.zero_copy_path_frac_final,
+            ),
+        ];
+        for (derived, default) in fracs.iter() {
+            assert!(
+                (derived - default).abs() < 0.0001,
+                "{},{}",
+                derived,
+                default
+            );
+        }
+        assert_eq!(derived.tip_diag, default.tip_diag);
+        assert_eq!(derived.small_net_len, default.small_net_len);
+    }
+    #[test]
+    fn overrides_test() {
+        let mut overrides = ThresholdOverrides::default();
+        overrides.set("tip_frac=0.5").unwrap();
+        overrides.set("small_net_len=5").unwrap();
+        assert!(overrides.set("tip_frac").is_err());
+        assert!(overrides.set("unknown=1").is_err());
+        let thr = CleanupThresholds::default().overridden_by(&overrides);
+        assert_eq!(thr.tip_frac, 0.5);
+        assert_eq!(thr.small_net_len, 5);
+        assert_eq!(thr.tip_diag, CleanupThresholds::default().tip_diag);
+    }
+}
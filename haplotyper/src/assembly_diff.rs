@@ -0,0 +1,349 @@
+//! Compare two assemblies of the same dataset at the chunk level.
+//! As the two assemblies share the chunks and the clusters, we can compare the contigs by the (chunk, cluster) they consist of,
+//! rather than by their sequences.
+use crate::assemble::{AssemblySummary, Edge};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+type Node = (u64, u64);
+
+/// A junction phased differently in the two assemblies.
+/// In the first assembly, `from` is adjacent to `to_first`, while in the second, it is adjacent to `to_second`.
+/// `to_first` and `to_second` are different clusters of the same chunk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rephased {
+    pub first_contig: String,
+    pub second_contig: String,
+    pub from: Node,
+    pub to_first: Node,
+    pub to_second: Node,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AssemblyDiff {
+    /// A contig in the first assembly and the contigs in the second assembly it was split into.
+    pub split: Vec<(String, Vec<String>)>,
+    /// The contigs in the first assembly and the contig in the second assembly they were merged into.
+    pub merged: Vec<(Vec<String>, String)>,
+    pub rephased: Vec<Rephased>,
+    /// (chunk, copy number in the first, copy number in the second).
+    pub copy_number_changes: Vec<(u64, usize, usize)>,
+    /// Bubbles only in the first assembly. Each bubble is the sorted chunk IDs in its arms.
+    pub disappeared_bubbles: Vec<Vec<u64>>,
+    /// Bubbles only in the second assembly.
+    pub appeared_bubbles: Vec<Vec<u64>>,
+}
+
+impl std::fmt::Display for AssemblyDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |xs: &[u64]| {
+            let xs: Vec<_> = xs.iter().map(|x| format!("{x}")).collect();
+            xs.join(",")
+        };
+        let mut lines = vec![
+            format!("SUMMARY\tSplit\t{}", self.split.len()),
+            format!("SUMMARY\tMerged\t{}", self.merged.len()),
+            format!("SUMMARY\tRephased\t{}", self.rephased.len()),
+            format!("SUMMARY\tCopyNumber\t{}", self.copy_number_changes.len()),
+            format!("SUMMARY\tBubbleLost\t{}", self.disappeared_bubbles.len()),
+            format!("SUMMARY\tBubbleGained\t{}", self.appeared_bubbles.len()),
+        ];
+        for (from, to) in self.split.iter() {
+            lines.push(format!("SPLIT\t{from}\t{}", to.join(",")));
+        }
+        for (from, to) in self.merged.iter() {
+            lines.push(format!("MERGED\t{}\t{to}", from.join(",")));
+        }
+        for r in self.rephased.iter() {
+            let ((f, fc), (t1, t1c), (t2, t2c)) = (r.from, r.to_first, r.to_second);
+            let (ctg1, ctg2) = (&r.first_contig, &r.second_contig);
+            lines.push(format!(
+                "REPHASED\t{ctg1}\t{ctg2}\t{f}-{fc}\t{t1}-{t1c}\t{t2}-{t2c}"
+            ));
+        }
+        for (chunk, first, second) in self.copy_number_changes.iter() {
+            lines.push(format!("COPYNUM\t{chunk}\t{first}\t{second}"));
+        }
+        for bubble in self.disappeared_bubbles.iter() {
+            lines.push(format!("BUBBLE\tLost\t{}", join(bubble)));
+        }
+        for bubble in self.appeared_bubbles.iter() {
+            lines.push(format!("BUBBLE\tGained\t{}", join(bubble)));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Compare `first` and `second`. They should be assembled from the same dataset.
+pub fn diff(first: &AssemblySummary, second: &AssemblySummary) -> AssemblyDiff {
+    let (split, merged) = split_and_merged(first, second);
+    let rephased = rephased(first, second);
+    let copy_number_changes = copy_number_changes(first, second);
+    let (first_bubbles, second_bubbles) = (bubbles(first), bubbles(second));
+    let disappeared_bubbles = first_bubbles.difference(&second_bubbles).cloned().collect();
+    let appeared_bubbles = second_bubbles.difference(&first_bubbles).cloned().collect();
+    AssemblyDiff {
+        split,
+        merged,
+        rephased,
+        copy_number_changes,
+        disappeared_bubbles,
+        appeared_bubbles,
+    }
+}
+
+// Node -> the names of the contigs containing that node.
+fn node_to_contigs(asm: &AssemblySummary) -> HashMap<Node, Vec<&str>> {
+    let mut contigs: HashMap<_, Vec<_>> = HashMap::new();
+    for contig in asm.contigs.iter() {
+        for elm in contig.summary.iter() {
+            contigs
+                .entry((elm.chunk, elm.cluster))
+                .or_default()
+                .push(contig.id.as_str());
+        }
+    }
+    contigs
+}
+
+// Compare the contigs by the nodes appearing exactly once in both assemblies.
+// Repetitive nodes would connect unrelated contigs otherwise.
+type SplitAndMerged = (Vec<(String, Vec<String>)>, Vec<(Vec<String>, String)>);
+fn split_and_merged(first: &AssemblySummary, second: &AssemblySummary) -> SplitAndMerged {
+    let (first_nodes, second_nodes) = (node_to_contigs(first), node_to_contigs(second));
+    let mut first_to_second: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let mut second_to_first: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (node, first_ctgs) in first_nodes.iter() {
+        let second_ctgs = match second_nodes.get(node) {
+            Some(ctgs) => ctgs,
+            None => continue,
+        };
+        if let ([first_ctg], [second_ctg]) = (first_ctgs.as_slice(), second_ctgs.as_slice()) {
+            first_to_second
+                .entry(first_ctg)
+                .or_default()
+                .insert(second_ctg);
+            second_to_first
+                .entry(second_ctg)
+                .or_default()
+                .insert(first_ctg);
+        }
+    }
+    let to_string = |xs: &BTreeSet<&str>| xs.iter().map(|x| x.to_string()).collect();
+    let split = first_to_second
+        .iter()
+        .filter(|(_, to)| 1 < to.len())
+        .map(|(from, to)| (from.to_string(), to_string(to)))
+        .collect();
+    let merged = second_to_first
+        .iter()
+        .filter(|(_, from)| 1 < from.len())
+        .map(|(to, from)| (to_string(from), to.to_string()))
+        .collect();
+    (split, merged)
+}
+
+// (node, adjacent chunk) -> (adjacent node, contig name).
+fn adjacencies(asm: &AssemblySummary) -> BTreeMap<(Node, u64), Vec<(Node, &str)>> {
+    let mut adjs: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for contig in asm.contigs.iter() {
+        for w in contig.summary.windows(2) {
+            let (x, y) = ((w[0].chunk, w[0].cluster), (w[1].chunk, w[1].cluster));
+            let name = contig.id.as_str();
+            adjs.entry((x, y.0)).or_default().push((y, name));
+            adjs.entry((y, x.0)).or_default().push((x, name));
+        }
+    }
+    adjs
+}
+
+fn rephased(first: &AssemblySummary, second: &AssemblySummary) -> Vec<Rephased> {
+    let (first_adjs, second_adjs) = (adjacencies(first), adjacencies(second));
+    let mut rephased = vec![];
+    for (&(from, chunk), first_tos) in first_adjs.iter() {
+        // Each junction is visited twice, from the both side.
+        if chunk < from.0 {
+            continue;
+        }
+        let second_tos = match second_adjs.get(&(from, chunk)) {
+            Some(tos) => tos,
+            None => continue,
+        };
+        for &(to_first, first_contig) in first_tos.iter() {
+            if second_tos.iter().any(|&(to, _)| to == to_first) {
+                continue;
+            }
+            if let Some(&(to_second, second_contig)) = second_tos.first() {
+                rephased.push(Rephased {
+                    first_contig: first_contig.to_string(),
+                    second_contig: second_contig.to_string(),
+                    from,
+                    to_first,
+                    to_second,
+                });
+            }
+        }
+    }
+    rephased
+}
+
+// The copy number of each chunk, i.e., the sum of the copy numbers of its clusters.
+fn copy_numbers(asm: &AssemblySummary) -> BTreeMap<u64, usize> {
+    let mut node_cps: BTreeMap<Node, usize> = BTreeMap::new();
+    for elm in asm.contigs.iter().flat_map(|c| c.summary.iter()) {
+        if let Some(cp) = elm.copy_number {
+            node_cps.insert((elm.chunk, elm.cluster), cp);
+        }
+    }
+    let mut copy_numbers: BTreeMap<u64, usize> = BTreeMap::new();
+    for ((chunk, _), cp) in node_cps {
+        *copy_numbers.entry(chunk).or_default() += cp;
+    }
+    copy_numbers
+}
+
+fn copy_number_changes(
+    first: &AssemblySummary,
+    second: &AssemblySummary,
+) -> Vec<(u64, usize, usize)> {
+    let (first, second) = (copy_numbers(first), copy_numbers(second));
+    let chunks: BTreeSet<_> = first.keys().chain(second.keys()).copied().collect();
+    chunks
+        .into_iter()
+        .filter_map(|chunk| {
+            let first = first.get(&chunk).copied().unwrap_or(0);
+            let second = second.get(&chunk).copied().unwrap_or(0);
+            (first != second).then_some((chunk, first, second))
+        })
+        .collect()
+}
+
+// Simple bubbles: two or more contigs connected to the same contig ends at the both ends.
+fn bubbles(asm: &AssemblySummary) -> BTreeSet<Vec<u64>> {
+    // (contig, is_tail) -> connected (contig, is_tail)
+    let mut neighbors: BTreeMap<(&str, bool), BTreeSet<(&str, bool)>> = BTreeMap::new();
+    for edge in asm.links.iter().filter(|e| e.from != e.to) {
+        let (from, to) = (
+            (edge.from.as_str(), edge.from_tail),
+            (edge.to.as_str(), edge.to_tail),
+        );
+        neighbors.entry(from).or_default().insert(to);
+        neighbors.entry(to).or_default().insert(from);
+    }
+    let mut arms: BTreeMap<_, Vec<&str>> = BTreeMap::new();
+    for contig in asm.contigs.iter() {
+        let id = contig.id.as_str();
+        let (head, tail) = match (neighbors.get(&(id, false)), neighbors.get(&(id, true))) {
+            (Some(head), Some(tail)) if head.len() == 1 && tail.len() == 1 => (head, tail),
+            _ => continue,
+        };
+        let (head, tail) = (head.iter().next().unwrap(), tail.iter().next().unwrap());
+        arms.entry((head.min(tail), head.max(tail)))
+            .or_default()
+            .push(id);
+    }
+    let chunks: HashMap<_, _> = asm
+        .contigs
+        .iter()
+        .map(|c| (c.id.as_str(), c.summary.iter().map(|elm| elm.chunk)))
+        .collect();
+    arms.values()
+        .filter(|arms| 1 < arms.len())
+        .map(|arms| {
+            let mut bubble: Vec<_> = arms.iter().flat_map(|arm| chunks[arm].clone()).collect();
+            bubble.sort_unstable();
+            bubble.dedup();
+            bubble
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::ditch_graph::{ContigElement, ContigSummary};
+    fn contig(id: &str, nodes: &[(u64, u64, usize)]) -> ContigSummary {
+        let summary = nodes
+            .iter()
+            .map(|&(chunk, cluster, cp)| ContigElement {
+                chunk,
+                cluster,
+                strand: true,
+                occ: 10,
+                copy_number: Some(cp),
+            })
+            .collect();
+        ContigSummary {
+            id: id.to_string(),
+            summary,
+            reference: None,
+        }
+    }
+    // Link from the tail of `from` to the head of `to`.
+    fn link(from: &str, to: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            from_tail: true,
+            to: to.to_string(),
+            to_tail: false,
+        }
+    }
+    #[test]
+    fn diff_test() {
+        // A bubble: a -> (b1|b2) -> c.
+        let first = AssemblySummary {
+            contigs: vec![
+                contig("a", &[(0, 0, 2), (1, 0, 2)]),
+                contig("b1", &[(2, 0, 1)]),
+                contig("b2", &[(2, 1, 1)]),
+                contig("c", &[(3, 0, 2), (4, 0, 2)]),
+            ],
+            links: vec![
+                link("a", "b1"),
+                link("a", "b2"),
+                link("b1", "c"),
+                link("b2", "c"),
+            ],
+        };
+        // The bubble is collapsed and the contigs are merged.
+        let second = AssemblySummary {
+            contigs: vec![contig(
+                "x",
+                &[(0, 0, 2), (1, 0, 2), (2, 0, 2), (3, 0, 2), (4, 0, 2)],
+            )],
+            links: vec![],
+        };
+        let diff = diff(&first, &second);
+        assert!(diff.split.is_empty());
+        assert_eq!(diff.merged.len(), 1);
+        assert_eq!(diff.merged[0].0, vec!["a", "b1", "c"]);
+        assert!(diff.copy_number_changes.is_empty());
+        assert_eq!(diff.disappeared_bubbles, vec![vec![2]]);
+        assert!(diff.appeared_bubbles.is_empty());
+        let diff = super::diff(&second, &first);
+        assert_eq!(diff.split.len(), 1);
+        assert_eq!(diff.appeared_bubbles, vec![vec![2]]);
+    }
+    #[test]
+    fn rephased_test() {
+        let first = AssemblySummary {
+            contigs: vec![
+                contig("a", &[(0, 0, 1), (1, 0, 1)]),
+                contig("b", &[(0, 1, 1), (1, 1, 1)]),
+            ],
+            links: vec![],
+        };
+        let second = AssemblySummary {
+            contigs: vec![
+                contig("x", &[(0, 0, 1), (1, 1, 1)]),
+                contig("y", &[(0, 1, 1), (1, 0, 1)]),
+            ],
+            links: vec![],
+        };
+        let rephased = rephased(&first, &second);
+        assert_eq!(rephased.len(), 2);
+        assert_eq!(rephased[0].from, (0, 0));
+        assert_eq!(rephased[0].to_first, (1, 0));
+        assert_eq!(rephased[0].to_second, (1, 1));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::assemble::ditch_graph::ContigEncoding;
 use crate::assemble::ditch_graph::UnitAlignmentInfo;
+use crate::homopolymer::HomopolymerModel;
 use crate::model_tune::ModelFit;
 use definitions::*;
 use gfa::Segment;
@@ -40,6 +41,8 @@ pub struct PolishConfig {
     window_size: usize,
     radius: usize,
     round_num: usize,
+    // Homopolymer models on the forward/reverse strand, used on top of the pair-HMM. Empty if not available.
+    homopolymers: [HomopolymerModel; 2],
 }
 impl PolishConfig {
     pub fn new(
@@ -57,8 +60,13 @@ impl PolishConfig {
             window_size,
             radius,
             round_num,
+            homopolymers: Default::default(),
         }
     }
+    /// Polish the homopolymer lengths by the pair-HMM corrected by these models on the forward and reverse strand.
+    pub fn set_homopolymer_models(&mut self, homopolymers: [HomopolymerModel; 2]) {
+        self.homopolymers = homopolymers;
+    }
 }
 
 impl std::default::Default for PolishConfig {
@@ -70,6 +78,7 @@ impl std::default::Default for PolishConfig {
             window_size: 2000,
             radius: 100,
             round_num: 2,
+            homopolymers: Default::default(),
         }
     }
 }
@@ -85,6 +94,9 @@ impl Polish for DataSet {
     ) -> Vec<Segment> {
         let mut alignments_on_contigs = self.distribute_to_contig(segments, encs, config);
         let models = self.fit_models_on_both_strands().unwrap();
+        let mut config = config.clone();
+        config.set_homopolymer_models(HomopolymerModel::on_strands(&self.model_param));
+        let config = &config;
         let polished: Vec<_> = alignments_on_contigs
             .iter_mut()
             .map(|(sid, alignments)| {
@@ -116,9 +128,8 @@ impl Polish for DataSet {
         let alignments: Vec<_> = self
             .encoded_reads
             .par_iter()
-            .enumerate()
-            .flat_map(|(i, read)| {
-                let seed = config.seed + i as u64;
+            .flat_map(|read| {
+                let seed = crate::misc::sub_seed(config.seed, "distribute_to_contig", read.id);
                 let mut rng: Xoroshiro128PlusPlus = SeedableRng::seed_from_u64(seed);
                 let mut raw_read = read.recover_raw_read();
                 raw_read.iter_mut().for_each(u8::make_ascii_uppercase);
@@ -441,6 +452,8 @@ fn bootstrap_consensus(seqs: &[&[u8]], ops: &mut [Vec<Op>], radius: usize) -> Ve
     kiley::bialignment::guided::polish_until_converge_with(&draft, seqs, ops, radius)
 }
 
+// The maximum number of the rounds to polish the homopolymer lengths.
+const HOMOPOLYMER_ROUND: usize = 3;
 fn polish_seg(
     models: &PairHiddenMarkovModelOnStrands,
     draft: &[u8],
@@ -472,14 +485,41 @@ fn polish_seg(
         true => polished,
         false => bootstrap_consensus(&use_seqs, &mut temp_ops, radius),
     };
-    let config = kiley::hmm::HMMPolishConfig::new(radius / 2, max_cov, 0);
+    let hmm_config = kiley::hmm::HMMPolishConfig::new(radius / 2, max_cov, 0);
     polished = models.polish_until_converge_antidiagonal(
         &polished,
         &use_seqs,
         &mut temp_ops,
         &use_strands,
-        &config,
+        &hmm_config,
     );
+    // The pair-HMM has the same indel rates everywhere. Polish the homopolymer lengths again under the homopolymer models.
+    let homopolymers = &config.homopolymers;
+    let rounds = match homopolymers.iter().all(|h| h.is_empty()) {
+        true => 0,
+        false => HOMOPOLYMER_ROUND,
+    };
+    for _ in 0..rounds {
+        use crate::homopolymer::*;
+        let band = radius / 2;
+        let (seqs, strands) = (&use_seqs, &use_strands);
+        let tables = modification_tables(
+            models,
+            homopolymers,
+            &polished,
+            seqs,
+            &temp_ops,
+            strands,
+            band,
+        );
+        match polish_homopolymers(&polished, &tables) {
+            Some(next) => polished = next,
+            None => break,
+        }
+        for (ops, seq) in temp_ops.iter_mut().zip(use_seqs.iter()) {
+            *ops = global_align(seq, &polished);
+        }
+    }
     assert_eq!(temp_ops.len(), use_ops.len());
     for (old, new) in std::iter::zip(use_ops, temp_ops) {
         *old = new;
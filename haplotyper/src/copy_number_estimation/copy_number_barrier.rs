@@ -27,11 +27,13 @@ const SAMPLE_LEN: usize = 1_000;
 /// and the haploid coverage. The latter, haploid coverage, can be estimated automatically
 /// by using `Config::estimate_coverage(seed)`.
 /// Also, `Config::default()` would do the same thing.
+/// When estimating the coverage, the median node is regarded as having `ploidy` copies (2 by default).
 #[derive(Debug, Clone)]
 pub struct Config {
     // If None, automatically estimate the haploid coverage.
     haploid_coverage: Option<f64>,
     seed: u64,
+    ploidy: usize,
 }
 
 impl Config {
@@ -39,14 +41,19 @@ impl Config {
         Self {
             haploid_coverage: None,
             seed,
+            ploidy: 2,
         }
     }
     pub fn new(haploid_coverage: f64, seed: u64) -> Self {
         Self {
             haploid_coverage: Some(haploid_coverage),
             seed,
+            ploidy: 2,
         }
     }
+    pub fn set_ploidy(&mut self, ploidy: usize) {
+        self.ploidy = ploidy;
+    }
 }
 
 impl std::default::Default for Config {
@@ -54,6 +61,7 @@ impl std::default::Default for Config {
         Self {
             haploid_coverage: None,
             seed: 24309,
+            ploidy: 2,
         }
     }
 }
@@ -87,9 +95,10 @@ impl CopyNumberEstimation for DataSet {
         }
     }
     fn estimate_copy_numbers(&self, config: &Config) -> (Vec<(Node, usize)>, Vec<(Edge, usize)>) {
-        let chunk_len = 2_000;
-        let graph = Graph::new(&self.encoded_reads, chunk_len);
-        graph.estimate_copy_numbers(config)
+        let graph = Graph::new(&self.encoded_reads);
+        let mut config = config.clone();
+        config.set_ploidy(self.ploidy);
+        graph.estimate_copy_numbers(&config)
     }
 }
 
@@ -200,7 +209,9 @@ impl Graph {
         edges.sort_by_key(|x| x.0);
         edges.iter().map(|x| x.1).collect()
     }
-    fn new<T: std::borrow::Borrow<EncodedRead>>(reads: &[T], chunk_len: usize) -> Self {
+    // The coverages are calibrated by the (mean) length of each chunk in the reads,
+    // as the chunks may have different lengths.
+    fn new<T: std::borrow::Borrow<EncodedRead>>(reads: &[T]) -> Self {
         let node_to_idx = Self::serialize_node(reads);
         let edge_to_idx = Self::serialize_edge(reads);
         let (downstream_edges, upstream_edges) =
@@ -209,8 +220,11 @@ impl Graph {
         let mut nodes: Vec<u64> = vec![0; node_to_idx.len()];
         let mut edges_len: Vec<_> = vec![0; edge_to_idx.len()];
         let mut edges: Vec<u64> = vec![0; edge_to_idx.len()];
+        let mut nodes_len: Vec<usize> = vec![0; node_to_idx.len()];
         for node in reads.iter().flat_map(|r| r.borrow().nodes.iter()) {
-            nodes[node_to_idx[&(node.chunk, node.cluster)]] += 1;
+            let idx = node_to_idx[&(node.chunk, node.cluster)];
+            nodes[idx] += 1;
+            nodes_len[idx] += node.seq().len();
         }
         for read in reads.iter().map(|r| r.borrow()) {
             for (i, edge) in read.edges.iter().enumerate() {
@@ -228,13 +242,24 @@ impl Graph {
         // Tune the count of the edges.
         let lens: Vec<_> = reads.iter().map(|r| r.borrow().original_length).collect();
         let calibrator = super::CoverageCalibrator::new(&lens);
+        let chunk_lens: Vec<_> = nodes_len
+            .iter()
+            .zip(nodes.iter())
+            .map(|(&len, &count)| len / count.max(1) as usize)
+            .collect();
         nodes
             .iter_mut()
-            .for_each(|x| *x = calibrator.calib(*x, chunk_len).round() as u64);
-        edges.iter_mut().zip(edges_len.iter()).for_each(|(x, len)| {
-            let gap_len = (*len / *x as i64 + 2 * chunk_len as i64).max(0) as usize;
-            *x = calibrator.calib(*x, gap_len).round() as u64;
-        });
+            .zip(chunk_lens.iter())
+            .for_each(|(x, &chunk_len)| *x = calibrator.calib(*x, chunk_len).round() as u64);
+        let edge_lens = edges_len.iter().zip(edge_terminals.iter());
+        edges
+            .iter_mut()
+            .zip(edge_lens)
+            .for_each(|(x, (len, &(from, to)))| {
+                let span = (chunk_lens[from] + chunk_lens[to]) as i64;
+                let gap_len = (*len / *x as i64 + span).max(0) as usize;
+                *x = calibrator.calib(*x, gap_len).round() as u64;
+            });
         Self {
             node_to_idx,
             edge_to_idx,
@@ -249,13 +274,13 @@ impl Graph {
     fn round<R: Rng>(rng: &mut R, f: f64) -> usize {
         f.trunc() as usize + rng.gen_bool(f.fract()) as usize
     }
-    fn estimate_coverage(&self) -> f64 {
+    fn estimate_coverage(&self, ploidy: usize) -> f64 {
         if self.nodes.is_empty() {
             0f64
         } else {
             let mut weights: Vec<_> = self.nodes.clone();
             let position = weights.len() / 2;
-            (*weights.select_nth_unstable(position).1) as f64 / 2f64
+            (*weights.select_nth_unstable(position).1) as f64 / ploidy.max(1) as f64
         }
     }
     fn estimate_copy_numbers(&self, config: &Config) -> CopyNumResult {
@@ -278,7 +303,7 @@ impl Graph {
     fn estimate_copy_numbers_inner(&self, config: &Config) -> (Vec<usize>, Vec<usize>) {
         let hap_cov = config
             .haploid_coverage
-            .unwrap_or_else(|| self.estimate_coverage());
+            .unwrap_or_else(|| self.estimate_coverage(config.ploidy));
         debug!("COPYNUMBER\tHapCov\t{:.3}", hap_cov);
         let mut rng: Xoroshiro128StarStar = SeedableRng::seed_from_u64(config.seed);
         let mut node_cp: Vec<_> = self
@@ -834,7 +859,7 @@ mod test {
             edges,
             nodes,
         };
-        let graph = Graph::new(&[read], 2_000);
+        let graph = Graph::new(&[read]);
         assert_eq!(graph.nodes.len(), 2);
         assert_eq!(graph.edges.len(), 1);
         let nodes: Vec<_> = (0..2).map(|x| graph.node_to_idx[&(x, 0)]).collect();
@@ -863,7 +888,7 @@ mod test {
         let calibrator = crate::copy_number_estimation::CoverageCalibrator::new(&lens);
         let ajd = calibrator.calib(mean_cov as u64, 2_000);
         println!("{}\t{}\t{}\t{}", total_chunks, hap.len(), mean_cov, ajd);
-        let graph = Graph::new(&reads, 2_000);
+        let graph = Graph::new(&reads);
         println!("{}", graph);
         let config = Config::estimate_coverage(392480);
         let (node_cp_e, _edge_cp_e) = graph.estimate_copy_numbers_inner(&config);
@@ -929,7 +954,7 @@ mod test {
             ajd
         );
         // Haplotype coverage should be estimated automatically.
-        let graph = Graph::new(&reads, 2_000);
+        let graph = Graph::new(&reads);
         println!("{}", graph);
         let config = Config::estimate_coverage(392480);
         let (node_cp_e, _edge_cp_e) = graph.estimate_copy_numbers_inner(&config);
@@ -451,7 +451,10 @@ impl Optimizer {
 /// should have `cv:i:` tag and `ln:i:` tag.
 /// The `cov` parameter is the haplotype coverage,
 /// the `len` parameter is the average length of the raw reads,
-/// and `chunk_len` parameter is the length of the chunk.
+/// and `chunk_len` parameter is the (mean) length of the chunk.
+/// If a segment has `cl:i:` tag, the mean length of the chunks in it,
+/// its coverage and length are calibrated by it instead of `chunk_len`.
+/// Likewise, an edge is calibrated by the chunk lengths of the segments it connects.
 /// If the assembly graph is gapless, `len` and `chunk_len` would be 0.
 /// After estimation, the estimated copy number would be added to gfa as `cp:i:` tag.
 pub fn estimate_copy_number_on_gfa(gfa: &mut gfa::GFA, cov: f64, lens: &[usize], chunk_len: usize) {
@@ -468,22 +471,34 @@ pub fn estimate_copy_number_on_gfa(gfa: &mut gfa::GFA, cov: f64, lens: &[usize],
     let old_cov = cov;
     let cov = calibrator.calib_f64(cov, chunk_len);
     debug!("Coverage\t{}\t{}", old_cov, cov);
+    let get_tag = |tags: &[gfa::SamTag], key: &str| -> Option<usize> {
+        tags.iter()
+            .find(|tag| tag.inner.starts_with(key))
+            .and_then(|tag| tag.inner.split(':').nth(2))
+            .and_then(|x| x.parse().ok())
+    };
+    // The mean length of the chunks in each segment.
+    let seg_chunk_lens: Vec<_> = gfa
+        .iter()
+        .filter(|record| matches!(&record.content, gfa::Content::Seg(_)))
+        .map(|record| {
+            get_tag(&record.tags, "cl:")
+                .filter(|&len| 0 < len)
+                .unwrap_or(chunk_len)
+        })
+        .collect();
     let nodes: Vec<_> = gfa
         .iter()
-        .filter_map(|record| {
-            if let gfa::Content::Seg(seg) = &record.content {
-                let coverage: usize = record
-                    .tags
-                    .iter()
-                    .find(|tag| tag.inner.starts_with("cv"))
-                    .and_then(|tag| tag.inner.split(':').nth(2))
-                    .and_then(|x| x.parse().ok())?;
-                let weight = calibrator.calib(coverage as u64, chunk_len) / cov;
-                let len = seg.slen as usize / chunk_len;
-                Some((weight, len))
-            } else {
-                None
-            }
+        .filter_map(|record| match &record.content {
+            gfa::Content::Seg(seg) => Some((seg, &record.tags)),
+            _ => None,
+        })
+        .zip(seg_chunk_lens.iter())
+        .filter_map(|((seg, tags), &seg_chunk_len)| {
+            let coverage = get_tag(tags, "cv:")?;
+            let len = seg.slen as usize / seg_chunk_len.max(1);
+            let weight = calibrator.calib(coverage as u64, seg_chunk_len) / cov;
+            Some((weight, len))
         })
         .collect();
     assert_eq!(nodes.len(), node_index.len());
@@ -508,7 +523,8 @@ pub fn estimate_copy_number_on_gfa(gfa: &mut gfa::GFA, cov: f64, lens: &[usize],
                     .and_then(|tag| tag.inner.split(':').nth(2))
                     .and_then(|cov| cov.parse().ok())
                     .unwrap_or_else(|| panic!("{:?}", record.tags));
-                let gap_len = (gap_len + 2 * chunk_len as isize).max(0) as usize;
+                let ends_len = seg_chunk_lens[from] + seg_chunk_lens[to];
+                let gap_len = (gap_len + ends_len as isize).max(0) as usize;
                 let weight = calibrator.calib(coverage as u64, gap_len) / cov;
                 Some((from, from_plus, to, to_plus, weight))
             }
@@ -10,17 +10,68 @@ pub trait CopyNumberEstimation {
     fn estimate_copy_numbers(&self, config: &Config) -> CopyNumResult;
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct Config {
     haploid_coverage: Option<f64>,
+    // The copy number of the median node, used to estimate the haploid coverage.
+    ploidy: usize,
+}
+
+impl std::default::Default for Config {
+    fn default() -> Self {
+        Self {
+            haploid_coverage: None,
+            ploidy: 2,
+        }
+    }
 }
 
 impl Config {
     pub fn new(haploid_coverage: f64) -> Self {
         Self {
             haploid_coverage: Some(haploid_coverage),
+            ploidy: 2,
+        }
+    }
+    pub fn set_ploidy(&mut self, ploidy: usize) {
+        self.ploidy = ploidy;
+    }
+}
+
+impl CopyNumberEstimation for definitions::DataSet {
+    fn update_copy_numbers(&mut self, config: &Config) {
+        let (node_cp, _) = self.estimate_copy_numbers(config);
+        // Reset copy numbers.
+        self.selected_chunks.iter_mut().for_each(|c| c.copy_num = 0);
+        // Update copy numbers.
+        let mut chunks: HashMap<u64, &mut definitions::Chunk> =
+            self.selected_chunks.iter_mut().map(|c| (c.id, c)).collect();
+        for ((chunk, _), cp) in node_cp {
+            if let Some(chunk) = chunks.get_mut(&chunk) {
+                chunk.copy_num += cp;
+            }
         }
     }
+    fn estimate_copy_numbers(&self, config: &Config) -> CopyNumResult {
+        use rand::SeedableRng;
+        let (graph, node_to_idx, edge_to_idx) = Graph::new(&self.encoded_reads);
+        let mut config = config.clone();
+        config.set_ploidy(self.ploidy);
+        let seed = crate::misc::sub_seed(self.seed, "copy_number_mrf", 0);
+        let mut rng: rand_xoshiro::Xoroshiro128StarStar = SeedableRng::seed_from_u64(seed);
+        let ((node_cp, edge_cp), _) = graph.map_estimate_copy_numbers(&mut rng, &config);
+        let mut nodes: Vec<_> = node_to_idx
+            .into_iter()
+            .map(|(node, idx)| (node, node_cp[idx]))
+            .collect();
+        nodes.sort_unstable();
+        let mut edges: Vec<_> = edge_to_idx
+            .into_iter()
+            .map(|(edge, idx)| (edge, edge_cp[idx]))
+            .collect();
+        edges.sort_unstable();
+        (nodes, edges)
+    }
 }
 const ERROR_FRAC: f64 = 0.05;
 const BURN_IN: usize = 20_000;
@@ -152,13 +203,13 @@ impl Graph {
         };
         (graph, node_to_idx, edge_to_idx)
     }
-    fn estimate_coverage(&self) -> f64 {
+    fn estimate_coverage(&self, ploidy: usize) -> f64 {
         if self.coverages.is_empty() {
             0f64
         } else {
             let mut weights: Vec<_> = self.coverages.clone();
             let position = weights.len() / 2;
-            (weights.select_nth_unstable(position).1).0 as f64 / 2f64
+            (weights.select_nth_unstable(position).1).0 as f64 / ploidy.max(1) as f64
         }
     }
     // Rounding p into p.trunc() + 1/0 depending on the p.fract().
@@ -194,7 +245,7 @@ impl Graph {
     ) -> ((Vec<usize>, Vec<usize>), f64) {
         let hap_cov = config
             .haploid_coverage
-            .unwrap_or_else(|| self.estimate_coverage());
+            .unwrap_or_else(|| self.estimate_coverage(config.ploidy));
         let mut mcmc_config = MCMCConfig::new(1f64, 1f64, hap_cov);
         let (mut node_cp, mut edge_cp) = self.initial_guess(&mcmc_config, rng);
         // To get MAP estimates, get the lowest potential combination.
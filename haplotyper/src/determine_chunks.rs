@@ -19,12 +19,21 @@ pub struct DetermineUnitConfig {
     pub exclude_repeats: f64,
     pub purge_copy_num: usize,
     pub seed: u64,
+    // If some, the chunks are tiled on this reference (FASTA) instead of picked randomly from the reads.
+    pub reference: Option<String>,
+    // The lengths of the chunks are chosen from [min_chunk_len, max_chunk_len]. By default, both are `chunk_len`.
+    pub min_chunk_len: usize,
+    pub max_chunk_len: usize,
+    // If some, the removed chunks are written to `{report}.tsv` and `{report}.fa`.
+    pub report: Option<String>,
 }
 
 pub const STDDEV_OR_ERROR: f64 = 0.01;
 const FIRST_RELAX: f64 = 2f64;
 
 pub const TAKE_THR: f64 = 0.999;
+// If the reads diverge from a chunk by this much more than usual, the chunk gets half as long.
+const DIVERGENCE_SCALE: f64 = 0.01;
 impl DetermineUnitConfig {
     pub fn new(
         chunk_len: usize,
@@ -44,8 +53,45 @@ impl DetermineUnitConfig {
             exclude_repeats,
             purge_copy_num,
             seed,
+            reference: None,
+            min_chunk_len: chunk_len,
+            max_chunk_len: chunk_len,
+            report: None,
         }
     }
+    /// Seed the chunks by tiling the reference of the target region.
+    pub fn set_reference(&mut self, reference: &str) {
+        self.reference = Some(reference.to_string());
+    }
+    /// Let the length of each chunk vary in [min_len, max_len], depending on the repeats and the divergence around it.
+    /// The range is widened to contain the chunk length if it does not.
+    pub fn set_adaptive_length(&mut self, min_len: usize, max_len: usize) {
+        self.min_chunk_len = min_len.min(self.chunk_len);
+        self.max_chunk_len = max_len.max(self.chunk_len);
+    }
+    /// Write the chunks removed during the selection, with the reasons, to `{prefix}.tsv` and `{prefix}.fa`.
+    pub fn set_report(&mut self, prefix: &str) {
+        self.report = Some(prefix.to_string());
+    }
+    pub fn is_adaptive(&self) -> bool {
+        self.min_chunk_len < self.max_chunk_len
+    }
+    /// The length of a chunk, from the fraction of the repetitive k-mers around it, its occurrence relative to
+    /// the expected one, and the divergence of the reads aligned to it, compared to the other chunks.
+    /// Repetitive or over-represented chunks get longer (up to twice for each), and diverged ones get shorter.
+    pub fn adaptive_chunk_len(
+        &self,
+        repetitiveness: f64,
+        copy_ratio: f64,
+        divergence: f64,
+    ) -> usize {
+        let repeat =
+            1f64 + (repetitiveness / self.exclude_repeats.max(f64::MIN_POSITIVE)).min(1f64);
+        let copy = copy_ratio.clamp(1f64, 2f64);
+        let diverged = 1f64 + divergence.max(0f64) / DIVERGENCE_SCALE;
+        let len = self.chunk_len as f64 * repeat * copy / diverged;
+        (len.round() as usize).clamp(self.min_chunk_len, self.max_chunk_len)
+    }
 }
 
 pub trait DetermineUnit {
@@ -76,10 +122,26 @@ const COPY_NUM_OFFSET: usize = 3;
 const LOWER_FRAC: f64 = 0.1;
 impl DetermineUnit for definitions::DataSet {
     fn select_chunks(&mut self, config: &DetermineUnitConfig) {
-        show_minimap2_version();
+        if self.aligner == Aligner::Minimap2 {
+            show_minimap2_version();
+        }
         self.selected_chunks.clear();
         self.encoded_reads.clear();
+        self.removed_chunks.clear();
         debug!("Select Unit: Configuration:{:?}", config);
+        if let Some(path) = config.reference.as_ref() {
+            match crate::locate_chunks::load_reference(path) {
+                Ok(reference) if select_chunks_by_reference(self, &reference, config) => {
+                    report_removed_chunks(self, config);
+                    return;
+                }
+                Ok(_) => warn!("No chunk from {path}. Pick chunks from the reads instead."),
+                Err(why) => warn!("{why:?},{path}. Pick chunks from the reads instead."),
+            }
+            self.selected_chunks.clear();
+            self.encoded_reads.clear();
+            self.removed_chunks.clear();
+        }
         let mut rng: Xoroshiro128Plus = SeedableRng::seed_from_u64(config.seed);
         self.selected_chunks = pick_random(self, config, &mut rng);
         debug!("UNITNUM\t{}\tPICKED", self.selected_chunks.len());
@@ -100,9 +162,11 @@ impl DetermineUnit for definitions::DataSet {
                 haploid_coverage.ceil() as usize * (config.purge_copy_num + COPY_NUM_OFFSET);
             let filter_size = (haploid_coverage * LOWER_FRAC).ceil() as usize;
             remove_frequent_chunks(self, upper_count);
+            let target_lens = adaptive_lengths(self, &repetitive_kmer, haploid_coverage, config);
             dump_histogram(self);
             let polish_config = PolishChunkConfig::new(self.read_type, filter_size, FIRST_CONS_COV);
-            self.consensus_chunk(&polish_config);
+            record_low_coverage(self, filter_size, |ds| ds.consensus_chunk(&polish_config));
+            split_long_chunks(self, &target_lens);
             debug!("UNITNUM\t{}\tPOLISHED\t1", self.selected_chunks.len());
         }
         // 2nd polishing.
@@ -133,11 +197,11 @@ impl DetermineUnit for definitions::DataSet {
             let filter_size = (haploid_coverage * LOWER_FRAC).ceil() as usize;
             remove_overlapping_chunks(self, ovlp_thr, config).unwrap();
             remove_frequent_chunks(self, upper_count);
-            filter_chunk_by_ovlp(self, config);
+            filter_chunk_by_ovlp(self);
             debug!("UNITNUM\t{}\tFILTERED\t1", self.selected_chunks.len());
             let polish_config = PolishChunkConfig::new(self.read_type, filter_size, CONS_COV);
             dump_histogram(self);
-            self.polish_chunk(&polish_config);
+            record_low_coverage(self, filter_size, |ds| ds.polish_chunk(&polish_config));
             debug!("UNITNUM\t{}\tPOLISHED\t2", self.selected_chunks.len());
         };
         // Final polish
@@ -150,12 +214,12 @@ impl DetermineUnit for definitions::DataSet {
                 haploid_coverage.ceil() as usize * (config.purge_copy_num + COPY_NUM_OFFSET);
             let filter_size = (haploid_coverage * LOWER_FRAC).ceil() as usize;
             remove_frequent_chunks(self, upper_count);
-            filter_chunk_by_ovlp(self, config);
+            filter_chunk_by_ovlp(self);
             self.encode(config.threads, sim_thr, self.read_type.sd_of_error());
             sim_thr = calc_sim_thr(self, TAKE_THR).max(self.read_type.sim_thr());
             debug!("ERRORRATE\t{}\t{}", self.error_rate(), sim_thr);
             remove_frequent_chunks(self, upper_count);
-            filter_chunk_by_ovlp(self, config);
+            filter_chunk_by_ovlp(self);
             compaction_chunks(self);
             debug!("UNITNUM\t{}\tFILTERED\t2", self.selected_chunks.len());
             remove_frequent_chunks(self, upper_count);
@@ -163,9 +227,8 @@ impl DetermineUnit for definitions::DataSet {
             debug!("ERRORRATE\t{}\t{}", self.error_rate(), sim_thr);
             let polish_config = PolishChunkConfig::new(self.read_type, 2 * filter_size, CONS_COV);
             dump_histogram(self);
-            self.polish_chunk(&polish_config);
-            self.selected_chunks
-                .retain(|c| repetitive_kmer.repetitiveness(c.seq()) < config.exclude_repeats);
+            record_low_coverage(self, 2 * filter_size, |ds| ds.polish_chunk(&polish_config));
+            remove_repetitive_chunks(self, &repetitive_kmer, config);
             debug!("UNITNUM\t{}\tPOLISHED\t3", self.selected_chunks.len());
         }
         {
@@ -177,22 +240,220 @@ impl DetermineUnit for definitions::DataSet {
             remove_frequent_chunks(self, upper_count);
             dump_histogram(self);
         }
-        // If half of the coverage supports large deletion, remove them.
-        const OCCUPY_FRACTION: f64 = 0.5;
-        use crate::purge_diverged::*;
-        let p_config = PurgeLargeDelConfig::new(crate::MAX_ALLOWED_GAP, OCCUPY_FRACTION, true);
-        self.purge_largeindel(&p_config);
+        purge_large_deletions(self);
         compaction_chunks(self);
+        report_removed_chunks(self, config);
     }
 }
 
-fn remove_frequent_chunks(ds: &mut DataSet, upper_count: usize) {
+// Tile the reference into the chunks, and refine each of them into the consensus of the reads.
+// The tiles with too few reads (e.g., on the regions diverged from the reference) are discarded, and
+// the regions not covered by the tiles are filled by the chunks from the reads, as in the random mode.
+// Return false if no tile survived, leaving the dataset with no chunks.
+fn select_chunks_by_reference(
+    ds: &mut DataSet,
+    reference: &[(String, Vec<u8>)],
+    config: &DetermineUnitConfig,
+) -> bool {
+    use crate::repeat_masking::RepeatMask;
+    let repetitive_kmer = ds.get_repetitive_kmer();
+    ds.selected_chunks = tile_reference(reference, config);
+    remove_repetitive_chunks(ds, &repetitive_kmer, config);
+    compaction_chunks(ds);
+    debug!("UNITNUM\t{}\tTILED", ds.selected_chunks.len());
+    let mut sim_thr = ds.read_type.sim_thr();
+    ds.encode(config.threads, FIRST_RELAX * sim_thr, STDDEV_OR_ERROR);
+    let haploid_coverage = update_get_coverage(ds);
+    let upper_count = haploid_coverage.ceil() as usize * (config.purge_copy_num + COPY_NUM_OFFSET);
+    let filter_size = (haploid_coverage * LOWER_FRAC).ceil() as usize;
+    remove_frequent_chunks(ds, upper_count);
+    let polish_config = PolishChunkConfig::new(ds.read_type, filter_size, CONS_COV);
+    record_low_coverage(ds, filter_size, |ds| ds.polish_chunk(&polish_config));
+    debug!("UNITNUM\t{}\tPOLISHED\t1", ds.selected_chunks.len());
+    if ds.selected_chunks.is_empty() {
+        return false;
+    }
+    ds.encode(config.threads, sim_thr, STDDEV_OR_ERROR);
+    sim_thr = calc_sim_thr(ds, TAKE_THR).max(ds.read_type.sim_thr());
+    let fill_config = crate::encode::deletion_fill::CorrectDeletionConfig::new(
+        false,
+        Some(sim_thr),
+        Some(STDDEV_OR_ERROR),
+    );
+    for _ in 0..10 {
+        let new_chunk = fill_sparse_region(ds, &repetitive_kmer, config)
+            + fill_tips(ds, &repetitive_kmer, config);
+        crate::encode::deletion_fill::correct_chunk_deletion(ds, &fill_config);
+        if new_chunk < MIN_REQ_NEW_UNIT {
+            break;
+        }
+    }
+    compaction_chunks(ds);
+    filter_chunk_by_ovlp(ds);
+    let tiled = ds.selected_chunks.iter().filter(|c| c.reference.is_some());
+    debug!(
+        "UNITNUM\t{}\tFILLED\t{}",
+        ds.selected_chunks.len(),
+        tiled.count()
+    );
+    let polish_config = PolishChunkConfig::new(ds.read_type, filter_size, CONS_COV);
+    record_low_coverage(ds, filter_size, |ds| ds.polish_chunk(&polish_config));
+    compaction_chunks(ds);
+    ds.encode(config.threads, sim_thr, ds.read_type.sd_of_error());
+    debug!("ERRORRATE\t{}", ds.error_rate());
+    remove_frequent_chunks(ds, upper_count);
+    dump_histogram(ds);
+    purge_large_deletions(ds);
+    compaction_chunks(ds);
+    true
+}
+
+// Cut the reference into `chunk_len`-bp chunks separated by `margin` bp.
+// Chunks containing non-ACGT bases are skipped.
+fn tile_reference(reference: &[(String, Vec<u8>)], config: &DetermineUnitConfig) -> Vec<Chunk> {
+    let step = config.chunk_len + config.margin;
+    reference
+        .iter()
+        .flat_map(|(name, seq)| {
+            (0..)
+                .map(move |i| (i * step, i * step + config.chunk_len))
+                .take_while(move |&(_, end)| end <= seq.len())
+                .map(move |(start, end)| (name, start, end, &seq[start..end]))
+        })
+        .filter(|(.., seq)| seq.iter().all(|b| b"ACGTacgt".contains(b)))
+        .enumerate()
+        .map(|(idx, (name, start, end, seq))| {
+            let mut chunk = Chunk::new(idx as u64, seq.to_ascii_uppercase(), config.min_cluster);
+            chunk.reference = Some(ReferencePosition {
+                contig: name.clone(),
+                start,
+                end,
+                strand: true,
+                identity: 1f64,
+            });
+            chunk
+        })
+        .collect()
+}
+
+fn count_occurrences(ds: &DataSet) -> HashMap<u64, usize> {
     let mut counts: HashMap<_, usize> = HashMap::new();
     for node in ds.encoded_reads.iter().flat_map(|r| r.nodes.iter()) {
         *counts.entry(node.chunk).or_default() += 1;
     }
+    counts
+}
+
+// Remove the chunks satisfying `is_removed`, recording them with the metric given by it.
+fn remove_chunks_with<F: Fn(&Chunk) -> Option<f64>>(
+    ds: &mut DataSet,
+    reason: RemovalReason,
+    threshold: f64,
+    is_removed: F,
+) {
+    let mut removed = vec![];
+    ds.selected_chunks.retain(|chunk| match is_removed(chunk) {
+        Some(value) => {
+            removed.push(RemovedChunk::new(chunk, reason, value, threshold));
+            false
+        }
+        None => true,
+    });
+    ds.removed_chunks.extend(removed);
+}
+
+// Run `polish`, recording the chunks dropped by it as they had no more than `filter_size` reads.
+fn record_low_coverage<F: FnOnce(&mut DataSet)>(ds: &mut DataSet, filter_size: usize, polish: F) {
+    let counts = count_occurrences(ds);
+    let chunks = ds.selected_chunks.clone();
+    polish(ds);
+    let remaining: std::collections::HashSet<_> = ds.selected_chunks.iter().map(|c| c.id).collect();
+    let removed = chunks
+        .iter()
+        .filter(|chunk| !remaining.contains(&chunk.id))
+        .map(|chunk| {
+            let occ = counts.get(&chunk.id).copied().unwrap_or(0) as f64;
+            RemovedChunk::new(chunk, RemovalReason::LowCoverage, occ, filter_size as f64)
+        });
+    ds.removed_chunks.extend(removed);
+}
+
+fn remove_repetitive_chunks(
+    ds: &mut DataSet,
+    repetitive_kmer: &crate::repeat_masking::RepeatAnnot,
+    config: &DetermineUnitConfig,
+) {
+    let thr = config.exclude_repeats;
+    remove_chunks_with(ds, RemovalReason::Repetitive, thr, |chunk| {
+        let repetitiveness = repetitive_kmer.repetitiveness(chunk.seq());
+        (thr <= repetitiveness).then_some(repetitiveness)
+    });
+}
+
+// If half of the coverage supports large deletion, remove them.
+// The chunks are kept even if all the occurrences are purged, but the lost occurrences are recorded.
+fn purge_large_deletions(ds: &mut DataSet) {
+    const OCCUPY_FRACTION: f64 = 0.5;
+    use crate::purge_diverged::*;
+    let p_config = PurgeLargeDelConfig::new(crate::MAX_ALLOWED_GAP, OCCUPY_FRACTION, true);
+    let before = count_occurrences(ds);
+    ds.purge_largeindel(&p_config);
+    record_purged_occurrences(ds, &before);
+}
+
+// Record the chunks having fewer occurrences than `before`, with the number of the lost occurrences.
+fn record_purged_occurrences(ds: &mut DataSet, before: &HashMap<u64, usize>) {
+    let after = count_occurrences(ds);
+    let purged = ds.selected_chunks.iter().filter_map(|chunk| {
+        let before = before.get(&chunk.id).copied().unwrap_or(0);
+        let lost = before.saturating_sub(after.get(&chunk.id).copied().unwrap_or(0));
+        (0 < lost).then(|| {
+            let (lost, before) = (lost as f64, before as f64);
+            RemovedChunk::new(chunk, RemovalReason::LargeDeletion, lost, before)
+        })
+    });
+    let purged: Vec<_> = purged.collect();
+    ds.removed_chunks.extend(purged);
+}
+
+/// Write the removed chunks into `{prefix}.tsv` and `{prefix}.fa`.
+/// Each record is indexed by the order of the removal, as the IDs are re-assigned between the stages.
+pub fn write_removed_chunks(ds: &DataSet, prefix: &str) -> std::io::Result<()> {
+    use std::io::{BufWriter, Write};
+    let mut tsv = std::fs::File::create(format!("{prefix}.tsv")).map(BufWriter::new)?;
+    let mut fasta = std::fs::File::create(format!("{prefix}.fa")).map(BufWriter::new)?;
+    writeln!(tsv, "index\tid\treason\tvalue\tthreshold\tlength")?;
+    for (idx, removed) in ds.removed_chunks.iter().enumerate() {
+        let (id, reason, len) = (removed.id, removed.reason, removed.seq().len());
+        let (value, thr) = (removed.value, removed.threshold);
+        writeln!(tsv, "{idx}\t{id}\t{reason}\t{value:.3}\t{thr:.3}\t{len}")?;
+        writeln!(fasta, ">{idx} id={id} reason={reason}\n{}", removed.seq)?;
+    }
+    tsv.flush()?;
+    fasta.flush()
+}
+
+fn report_removed_chunks(ds: &DataSet, config: &DetermineUnitConfig) {
+    let mut counts: HashMap<_, usize> = HashMap::new();
+    for removed in ds.removed_chunks.iter() {
+        *counts.entry(removed.reason).or_default() += 1;
+    }
+    for (reason, count) in counts.iter() {
+        debug!("UNITNUM\t{count}\tREMOVED\t{reason}");
+    }
+    if let Some(prefix) = config.report.as_ref() {
+        if let Err(why) = write_removed_chunks(ds, prefix) {
+            warn!("{why:?},{prefix}");
+        }
+    }
+}
+
+fn remove_frequent_chunks(ds: &mut DataSet, upper_count: usize) {
+    let mut counts = count_occurrences(ds);
     counts.retain(|_, occ| *occ > upper_count);
-    ds.selected_chunks.retain(|u| !counts.contains_key(&u.id));
+    remove_chunks_with(ds, RemovalReason::Frequent, upper_count as f64, |chunk| {
+        counts.get(&chunk.id).map(|&occ| occ as f64)
+    });
     for read in ds.encoded_reads.iter_mut() {
         let mut idx = 0;
         loop {
@@ -231,7 +492,7 @@ fn pick_random<R: Rng>(ds: &DataSet, config: &DetermineUnitConfig, rng: &mut R)
     let subseqs: Vec<_> = ds
         .raw_reads
         .iter()
-        .flat_map(|r| split_into(r, config))
+        .flat_map(|r| split_into(r, config, &mask))
         .map(|u| (u, mask.repetitiveness(u)))
         .filter(|&(_, repetitiveness)| repetitiveness < config.exclude_repeats)
         .collect();
@@ -284,6 +545,38 @@ fn mm2_chunk_overlap(ds: &DataSet, config: &DetermineUnitConfig) -> std::io::Res
     Ok(mm2)
 }
 
+// All-vs-all alignments between the chunks.
+fn chunk_overlaps(
+    ds: &DataSet,
+    config: &DetermineUnitConfig,
+) -> std::io::Result<Vec<bio_utils::paf::PAF>> {
+    match ds.aligner {
+        Aligner::Minimap2 => {
+            let mm2 = mm2_chunk_overlap(ds, config)?;
+            let alignments = String::from_utf8_lossy(&mm2)
+                .lines()
+                .filter_map(bio_utils::paf::PAF::new)
+                .collect();
+            Ok(alignments)
+        }
+        Aligner::Builtin => {
+            use crate::minimizer_aligner::*;
+            let names: Vec<_> = ds
+                .selected_chunks
+                .iter()
+                .map(|c| c.id.to_string())
+                .collect();
+            let chunks: Vec<_> = std::iter::zip(&names, &ds.selected_chunks)
+                .map(|(name, chunk)| (name.as_str(), chunk.seq()))
+                .collect();
+            let mut aln_config = AlignerConfig::from_read_type(ds.read_type);
+            aln_config.set_skip_self(true);
+            let index = MinimizerIndex::new(chunks.clone(), &aln_config);
+            Ok(index.map_all(&chunks))
+        }
+    }
+}
+
 pub fn is_proper_overlap(paf: &bio_utils::paf::PAF) -> bool {
     const ALLOWED_END_GAP: usize = 25;
     let (qstart, qend, qlen) = (paf.qstart, paf.qend, paf.qlen);
@@ -311,25 +604,28 @@ fn remove_overlapping_chunks(
     config: &DetermineUnitConfig,
 ) -> std::io::Result<()> {
     let chunk_len = ds.selected_chunks.len();
-    // How long one overlap should be at least.
-    let overlap_len = config.chunk_len / 2;
-    let mm2 = mm2_chunk_overlap(ds, config)?;
-    let alignments = std::str::from_utf8(&mm2).unwrap();
+    let alignments = chunk_overlaps(ds, config)?;
     let alignments = alignments
-        .lines()
-        .filter_map(bio_utils::paf::PAF::new)
+        .into_iter()
         .filter(is_proper_overlap)
         .filter(|paf| {
             let identity = paf.matchnum as f64 / paf.blocklen as f64;
+            // How long one overlap should be at least.
+            let overlap_len = paf.qlen.min(paf.tlen) / 2;
             overlap_thr < identity && overlap_len < paf.blocklen
         });
     let mut graph = vec![vec![]; chunk_len];
+    // The identity of the best overlap of each chunk.
+    let mut best_identity = vec![0f64; chunk_len];
     for aln in alignments {
         let chunk1: usize = aln.tname.parse().unwrap();
         let chunk2: usize = aln.qname.parse().unwrap();
         if chunk1 != chunk2 {
             graph[chunk1].push(chunk2);
             graph[chunk2].push(chunk1);
+            let identity = aln.matchnum as f64 / aln.blocklen as f64;
+            best_identity[chunk1] = best_identity[chunk1].max(identity);
+            best_identity[chunk2] = best_identity[chunk2].max(identity);
         }
     }
     for edges in graph.iter_mut() {
@@ -337,8 +633,10 @@ fn remove_overlapping_chunks(
         edges.dedup();
     }
     let to_be_removed = approx_vertex_cover(graph, ds.selected_chunks.len());
-    ds.selected_chunks
-        .retain(|chunk| !to_be_removed[chunk.id as usize]);
+    remove_chunks_with(ds, RemovalReason::OverlapOnChunks, overlap_thr, |chunk| {
+        let idx = chunk.id as usize;
+        to_be_removed[idx].then_some(best_identity[idx])
+    });
     ds.encoded_reads.par_iter_mut().for_each(|read| {
         let mut idx = 0;
         loop {
@@ -421,19 +719,18 @@ fn enumerate_filled_edges(
                 .iter()
                 .all(|s| repetitive_kmers.repetitiveness(s) < config.exclude_repeats)
     });
-    take_consensus(&edge_count, &ds.read_type, config)
+    take_consensus(&edge_count, &ds.read_type)
 }
 
 fn take_consensus<K: Hash + Clone + Eq + Sync + Send>(
     chunks: &HashMap<K, Vec<Vec<u8>>>,
     read_type: &ReadType,
-    config: &DetermineUnitConfig,
 ) -> HashMap<K, Vec<u8>> {
     chunks
         .par_iter()
         .map(|(key, seqs)| {
-            let radius = read_type.band_width(config.chunk_len);
             let draft = pick_median_length(seqs.as_slice());
+            let radius = read_type.band_width(draft.len());
             let consensus = kiley::bialignment::guided::polish_until_converge(&draft, seqs, radius);
             (key.clone(), consensus)
         })
@@ -661,7 +958,7 @@ fn enumerate_filled_tips(
             (key, seqs)
         })
         .collect();
-    take_consensus(&tail_counts, &ds.read_type, config)
+    take_consensus(&tail_counts, &ds.read_type)
 }
 
 fn fill_tip(
@@ -712,39 +1009,137 @@ fn fill_tip(
 }
 
 // Or, something went wrong? Please check it out.
-fn split_into<'a>(r: &'a RawRead, c: &DetermineUnitConfig) -> Vec<&'a [u8]> {
+// If the length is adaptive, each chunk is extended according to the repetitiveness of the `chunk_len`-bp window from its start.
+fn split_into<'a>(
+    r: &'a RawRead,
+    c: &DetermineUnitConfig,
+    mask: &crate::repeat_masking::RepeatAnnot,
+) -> Vec<&'a [u8]> {
     let seq = r.seq();
     if seq.len() < c.margin * 2 {
-        vec![]
-    } else {
-        let bound = seq.len() - c.margin;
-        (0..)
-            .map(|i| (c.margin + i * c.chunk_len, c.margin + (i + 1) * c.chunk_len))
-            .take_while(|&(_, end)| end <= bound)
-            .map(|(s, e)| &seq[s..e])
-            .collect()
+        return vec![];
     }
+    let bound = seq.len() - c.margin;
+    let mut subseqs = vec![];
+    let mut start = c.margin;
+    while start + c.chunk_len <= bound {
+        let len = match c.is_adaptive() {
+            true => {
+                let repetitiveness = mask.repetitiveness(&seq[start..start + c.chunk_len]);
+                c.adaptive_chunk_len(repetitiveness, 1f64, 0f64)
+            }
+            false => c.chunk_len,
+        };
+        if bound < start + len {
+            break;
+        }
+        subseqs.push(&seq[start..start + len]);
+        start += len;
+    }
+    subseqs
 }
 
-fn filter_chunk_by_ovlp(ds: &mut DataSet, config: &DetermineUnitConfig) {
-    let overlap_thr: usize = match ds.read_type {
-        ReadType::CCS => config.chunk_len / 2,
-        ReadType::CLR => config.chunk_len / 3,
-        ReadType::ONT => config.chunk_len / 3,
-        ReadType::None => config.chunk_len / 3,
+// The target length of each chunk, from the repetitiveness, the occurrence, and the divergence of the encoded reads.
+// Empty if the length is not adaptive.
+fn adaptive_lengths(
+    ds: &DataSet,
+    repetitive_kmer: &crate::repeat_masking::RepeatAnnot,
+    haploid_coverage: f64,
+    config: &DetermineUnitConfig,
+) -> HashMap<u64, usize> {
+    if !config.is_adaptive() {
+        return HashMap::new();
+    }
+    let chunks: HashMap<_, _> = ds.selected_chunks.iter().map(|c| (c.id, c)).collect();
+    let mut errors: HashMap<_, Vec<_>> = HashMap::new();
+    for node in ds.encoded_reads.iter().flat_map(|r| r.nodes.iter()) {
+        if let Some(chunk) = chunks.get(&node.chunk) {
+            errors
+                .entry(node.chunk)
+                .or_default()
+                .push(error(node, chunk));
+        }
+    }
+    let mut all_errors: Vec<_> = errors.values().flatten().copied().collect();
+    if all_errors.is_empty() {
+        return HashMap::new();
+    }
+    let median = all_errors.len() / 2;
+    let (_, &mut typical_error, _) =
+        all_errors.select_nth_unstable_by(median, |x, y| x.partial_cmp(y).unwrap());
+    let expected_occ = (haploid_coverage * ds.ploidy.max(1) as f64).max(1f64);
+    errors
+        .iter()
+        .map(|(id, errors)| {
+            let repetitiveness = repetitive_kmer.repetitiveness(chunks[id].seq());
+            let copy_ratio = errors.len() as f64 / expected_occ;
+            let mean_error = errors.iter().sum::<f64>() / errors.len() as f64;
+            let divergence = mean_error - typical_error;
+            let len = config.adaptive_chunk_len(repetitiveness, copy_ratio, divergence);
+            trace!("ADAPTIVE\t{id}\t{copy_ratio:.2}\t{divergence:.3}\t{len}");
+            (*id, len)
+        })
+        .collect()
+}
+
+// Split the chunks much longer than their target lengths into pieces with (almost) equal lengths.
+// The first piece keeps the original ID. The encoded reads should be re-encoded afterwards.
+fn split_long_chunks(ds: &mut DataSet, target_lens: &HashMap<u64, usize>) {
+    let mut max_id = match ds.selected_chunks.iter().map(|c| c.id).max() {
+        Some(id) => id,
+        None => return,
+    };
+    let mut pieces = vec![];
+    for chunk in ds.selected_chunks.iter_mut() {
+        let target = match target_lens.get(&chunk.id) {
+            Some(&target) if 2 * chunk.seq().len() > 3 * target => target,
+            _ => continue,
+        };
+        let num = (chunk.seq().len() as f64 / target as f64).round() as usize;
+        let piece_len = (chunk.seq().len() as f64 / num as f64).ceil() as usize;
+        let seq = chunk.seq().to_vec();
+        let mut seqs = seq.chunks(piece_len);
+        *chunk = Chunk::new(chunk.id, seqs.next().unwrap().to_vec(), chunk.copy_num);
+        for piece in seqs {
+            max_id += 1;
+            pieces.push(Chunk::new(max_id, piece.to_vec(), chunk.copy_num));
+        }
+    }
+    debug!("UNITNUM\t{}\tSPLIT", pieces.len());
+    ds.selected_chunks.extend(pieces);
+}
+
+fn filter_chunk_by_ovlp(ds: &mut DataSet) {
+    // Two chunks overlapping more than this fraction of the shorter one are conflicting.
+    let overlap_frac = match ds.read_type {
+        ReadType::CCS => 2,
+        ReadType::CLR => 3,
+        ReadType::ONT => 3,
+        ReadType::None => 3,
     };
+    let lens: HashMap<_, _> = ds
+        .selected_chunks
+        .iter()
+        .map(|c| (c.id, c.seq().len()))
+        .collect();
     let chunk_len = ds.selected_chunks.iter().map(|u| u.id).max().unwrap();
     let chunk_len = chunk_len as usize + 1;
     assert!(ds.selected_chunks.len() <= chunk_len);
     let mut graph = vec![vec![]; chunk_len];
+    // The longest overlap of each chunk, with the threshold.
+    let mut longest_overlap = vec![(0, 0); chunk_len];
     for read in ds.encoded_reads.iter() {
         for (i, node1) in read.nodes.iter().enumerate() {
             for node2 in read.nodes.iter().skip(i + 1) {
                 let node_end = node1.position_from_start + node1.seq.as_slice().len();
                 let mode_start = node2.position_from_start;
                 let ovlp_len = node_end.max(mode_start) - mode_start;
+                let overlap_thr = lens[&node1.chunk].min(lens[&node2.chunk]) / overlap_frac;
                 if overlap_thr < ovlp_len {
                     let (node1, node2) = (node1.chunk as usize, node2.chunk as usize);
+                    for node in [node1, node2] {
+                        longest_overlap[node] = longest_overlap[node].max((ovlp_len, overlap_thr));
+                    }
                     if !graph[node1].contains(&node2) {
                         assert!(!graph[node2].contains(&node1));
                         graph[node1].push(node2);
@@ -756,6 +1151,21 @@ fn filter_chunk_by_ovlp(ds: &mut DataSet, config: &DetermineUnitConfig) {
     }
 
     let to_be_removed = approx_vertex_cover(graph, chunk_len);
+    let removed: Vec<_> = ds
+        .selected_chunks
+        .iter()
+        .filter(|chunk| to_be_removed[chunk.id as usize])
+        .map(|chunk| {
+            let (ovlp_len, thr) = longest_overlap[chunk.id as usize];
+            RemovedChunk::new(
+                chunk,
+                RemovalReason::OverlapOnReads,
+                ovlp_len as f64,
+                thr as f64,
+            )
+        })
+        .collect();
+    ds.removed_chunks.extend(removed);
     ds.selected_chunks
         .retain(|chunk| !to_be_removed[chunk.id as usize]);
     debug!("UNITNUM\t{}\tVertexCovering", ds.selected_chunks.len());
@@ -819,3 +1229,65 @@ pub fn calc_sim_thr(ds: &DataSet, quantile: f64) -> f64 {
     let idx = ((error_rates.len() as f64 * quantile).floor() as usize).min(error_rates.len() - 1);
     error_rates[idx]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn adaptive_chunk_len_test() {
+        let mut config = DetermineUnitConfig::new(2_000, 100, 500, 1, 0.8, 8, 42);
+        assert!(!config.is_adaptive());
+        assert_eq!(config.adaptive_chunk_len(0.8, 2f64, 0f64), 2_000);
+        config.set_adaptive_length(1_000, 4_000);
+        assert!(config.is_adaptive());
+        assert_eq!(config.adaptive_chunk_len(0f64, 1f64, 0f64), 2_000);
+        assert_eq!(config.adaptive_chunk_len(0.4, 1f64, 0f64), 3_000);
+        assert_eq!(config.adaptive_chunk_len(0.8, 1.5, 0f64), 4_000);
+        assert_eq!(config.adaptive_chunk_len(0f64, 1f64, 0.01), 1_000);
+        assert_eq!(config.adaptive_chunk_len(0f64, 1f64, 0.1), 1_000);
+        assert_eq!(config.adaptive_chunk_len(0f64, 0.5, -0.01), 2_000);
+        config.set_adaptive_length(2_500, 1_500);
+        assert!(!config.is_adaptive());
+        assert_eq!(config.adaptive_chunk_len(0.8, 2f64, 0f64), 2_000);
+    }
+    #[test]
+    fn remove_chunks_with_test() {
+        let mut ds = DataSet::new();
+        ds.selected_chunks = (0..4).map(|i| Chunk::new(i, vec![b'A'; 10], 2)).collect();
+        remove_chunks_with(&mut ds, RemovalReason::Frequent, 2f64, |chunk| {
+            (chunk.id % 2 == 1).then_some(chunk.id as f64)
+        });
+        let ids: Vec<_> = ds.selected_chunks.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![0, 2]);
+        let removed: Vec<_> = ds.removed_chunks.iter().map(|r| (r.id, r.value)).collect();
+        assert_eq!(removed, vec![(1, 1f64), (3, 3f64)]);
+        assert!(ds.removed_chunks.iter().all(|r| r.seq().len() == 10));
+    }
+    #[test]
+    fn record_purged_occurrences_test() {
+        let mut ds = DataSet::new();
+        ds.selected_chunks = (0..3).map(|i| Chunk::new(i, vec![b'A'; 10], 2)).collect();
+        let node = |chunk: u64| Node {
+            chunk,
+            ..Default::default()
+        };
+        let read = |nodes: Vec<Node>| EncodedRead {
+            nodes,
+            ..Default::default()
+        };
+        ds.encoded_reads = vec![read(vec![node(0), node(1), node(2)]); 2];
+        let before = count_occurrences(&ds);
+        // Chunk 1 loses one occurrence, and chunk 2 loses all of them.
+        ds.encoded_reads[0].nodes.retain(|n| n.chunk == 0);
+        ds.encoded_reads[1].nodes.retain(|n| n.chunk != 2);
+        record_purged_occurrences(&mut ds, &before);
+        assert_eq!(ds.selected_chunks.len(), 3);
+        let purged: Vec<_> = ds
+            .removed_chunks
+            .iter()
+            .map(|r| (r.id, r.value, r.threshold))
+            .collect();
+        assert_eq!(purged, vec![(1, 1f64, 2f64), (2, 2f64, 2f64)]);
+        assert!(ds.removed_chunks.iter().all(|r| r.reason.keeps_chunk()));
+    }
+}
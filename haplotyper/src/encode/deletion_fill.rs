@@ -2,6 +2,7 @@
 use crate::ALN_PARAMETER;
 use definitions::*;
 use rayon::prelude::*;
+use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 // identity would be increased by this value when evaluating the edges.
 const EDGE_BOUND: f64 = 0.5;
@@ -29,7 +30,6 @@ impl CorrectDeletionConfig {
 pub trait CorrectDeletion {
     fn correct_deletion(&mut self, config: &CorrectDeletionConfig);
 }
-const SEED_CONST: usize = 49823094830;
 impl CorrectDeletion for DataSet {
     fn correct_deletion(&mut self, config: &CorrectDeletionConfig) {
         let mut find_new_chunks = correct_chunk_deletion(self, config);
@@ -56,7 +56,8 @@ impl CorrectDeletion for DataSet {
                 .flat_map(|r| r.nodes.iter_mut())
                 .for_each(|n| n.cluster = 0);
             use crate::multiplicity_estimation::*;
-            let seed = (SEED_CONST * self.encoded_reads.len()) as u64;
+            let task = self.encoded_reads.len() as u64;
+            let seed = crate::misc::sub_seed(self.seed, "correct_deletion", task);
             let config = MultiplicityEstimationConfig::new(seed, None);
             self.estimate_multiplicity(&config);
             // Retain all the chunks changed their copy numbers.
@@ -140,6 +141,7 @@ pub fn correct_chunk_deletion(ds: &mut DataSet, config: &CorrectDeletionConfig)
     });
     use crate::estimate_error_rate::estimate_error_rate;
     let errors = estimate_error_rate(ds, fallback);
+    errors.record_read_errors(ds);
     let standard_dev = config.stddev_of_error.unwrap_or(errors.median_of_sqrt_err);
     let mut failed_trials: Vec<_> = ds
         .encoded_reads
@@ -255,7 +257,7 @@ impl FailedUpdates {
 // Take consensus of each cluster of each chunk, return the consensus seuqneces.
 // UnitID->(clsuterID, its consensus).
 // fn take_consensus_sequence(ds: &DataSet) -> HashMap<u64, Vec<(u64, Vec<u8>)>> {
-fn take_consensus_sequence(ds: &DataSet) -> HashMap<(u64, u64), Vec<u8>> {
+pub(crate) fn take_consensus_sequence(ds: &DataSet) -> HashMap<(u64, u64), Vec<u8>> {
     fn polish(xs: &[&[u8]], chunk: &Chunk, band: usize) -> Vec<u8> {
         kiley::bialignment::guided::polish_until_converge(chunk.seq(), xs, band)
     }
@@ -400,7 +402,7 @@ fn try_encoding_head(
             let chunk_info = (chunk, cluster, cons.as_slice());
             encode_node(seq, position, chunk_info, error_rate_bound)
         })
-        .max_by_key(|x| x.1)
+        .max_by_key(best_encoding_key)
 }
 
 fn try_encoding_tail(
@@ -440,7 +442,19 @@ fn try_encoding_tail(
             let error_rate_bound = expected + THR * stddev;
             encode_node(seq, positions, chunk_info, error_rate_bound)
         })
-        .max_by_key(|x| x.1)
+        .max_by_key(best_encoding_key)
+}
+
+// The candidates come from a HashMap, so ties in the score are broken by the node itself
+// to make the result independent of the iteration order.
+fn best_encoding_key((node, score): &(Node, i32)) -> (i32, Reverse<(u64, u64, bool, usize)>) {
+    let key = (
+        node.chunk,
+        node.cluster,
+        node.is_forward,
+        node.position_from_start,
+    );
+    (*score, Reverse(key))
 }
 
 // Try to Encode Node. Return Some(node) if the alignment is good.
@@ -36,11 +36,27 @@ impl Encode for definitions::DataSet {
     }
 }
 
+/// Encode the reads by the alignments to the chunks, computed by the aligner of the dataset.
 pub fn encode_by_mm2(ds: &mut definitions::DataSet, p: usize, sim_thr: f64) -> std::io::Result<()> {
-    let mm2 = mm2_alignment(ds, p)?;
-    let alignments: Vec<_> = String::from_utf8_lossy(&mm2)
-        .lines()
-        .filter_map(bio_utils::paf::PAF::new)
+    let alignments: Vec<_> = match ds.aligner {
+        definitions::Aligner::Minimap2 => {
+            let mm2 = mm2_alignment(ds, p)?;
+            String::from_utf8_lossy(&mm2)
+                .lines()
+                .filter_map(bio_utils::paf::PAF::new)
+                .collect()
+        }
+        definitions::Aligner::Builtin => builtin_alignment(ds),
+    };
+    // Reads with an estimated error rate use their own thresholds.
+    let read_thr = crate::estimate_error_rate::read_sim_thresholds(ds, sim_thr);
+    let read_thr: HashMap<_, _> = ds
+        .raw_reads
+        .iter()
+        .filter_map(|r| read_thr.get(&r.id).map(|&thr| (r.name.as_str(), thr)))
+        .collect();
+    let alignments: Vec<_> = alignments
+        .into_iter()
         .filter(|a| a.tstart < ALLOWED_END_GAP && a.tlen - a.tend < ALLOWED_END_GAP)
         .filter_map(|aln| {
             use bio_utils::sam;
@@ -54,6 +70,7 @@ pub fn encode_by_mm2(ds: &mut definitions::DataSet, p: usize, sim_thr: f64) -> s
                 _ => unreachable!(),
             });
             let percent_identity = mat_num as f64 / aln_len as f64;
+            let sim_thr = read_thr.get(aln.qname.as_str()).copied().unwrap_or(sim_thr);
             (1f64 - sim_thr < percent_identity).then_some(aln)
         })
         .collect();
@@ -352,6 +369,27 @@ pub fn mm2_alignment(ds: &definitions::DataSet, p: usize) -> std::io::Result<Vec
     Ok(mm2)
 }
 
+/// Align the reads to the chunks by the built-in aligner, in memory.
+pub fn builtin_alignment(ds: &definitions::DataSet) -> Vec<bio_utils::paf::PAF> {
+    use crate::minimizer_aligner::*;
+    let names: Vec<_> = ds
+        .selected_chunks
+        .iter()
+        .map(|c| c.id.to_string())
+        .collect();
+    let targets: Vec<_> = std::iter::zip(&names, &ds.selected_chunks)
+        .map(|(name, chunk)| (name.as_str(), chunk.seq()))
+        .collect();
+    let config = AlignerConfig::from_read_type(ds.read_type);
+    let index = MinimizerIndex::new(targets, &config);
+    let queries: Vec<_> = ds
+        .raw_reads
+        .iter()
+        .map(|r| (r.name.as_str(), r.seq()))
+        .collect();
+    index.map_all(&queries)
+}
+
 fn is_uppercase(read: &definitions::EncodedRead) -> bool {
     let nodes = read
         .nodes
@@ -11,12 +11,7 @@ impl Entry for definitions::DataSet {
             "ONT" => ReadType::ONT,
             _ => ReadType::None,
         };
-        let compress_thr = match read_type {
-            ReadType::CCS => 100,
-            ReadType::CLR => 40,
-            ReadType::ONT => 100,
-            ReadType::None => 100,
-        };
+        let compress_thr = compression_threshold(read_type);
         let raw_reads: Vec<_> = raw_data
             .into_iter()
             .enumerate()
@@ -45,8 +40,19 @@ impl Entry for definitions::DataSet {
     }
 }
 
+// Homopolymers longer than this value are compressed at the entry.
+pub(crate) fn compression_threshold(read_type: definitions::ReadType) -> usize {
+    use definitions::ReadType;
+    match read_type {
+        ReadType::CCS => 100,
+        ReadType::CLR => 40,
+        ReadType::ONT => 100,
+        ReadType::None => 100,
+    }
+}
+
 // Compress homopolymer longer than x base into x base.
-fn compress_homopolymer(seq: &[u8], len: usize) -> Vec<u8> {
+pub(crate) fn compress_homopolymer(seq: &[u8], len: usize) -> Vec<u8> {
     let mut compressed = Vec::with_capacity(seq.len());
     let mut seq = seq.iter().map(|x| x.to_ascii_uppercase()).peekable();
     while let Some(base) = seq.next() {
@@ -14,6 +14,103 @@ impl ErrorRate {
     pub fn chunk(&self, (chunk, cluster): (u64, u64)) -> f64 {
         self.chunk_error_rate[chunk as usize][cluster as usize]
     }
+    /// Record the error rates of the encoded reads into `ds.read_error_rates`.
+    /// Reads not encoded in this round keep their previous records.
+    pub fn record_read_errors(&self, ds: &mut DataSet) {
+        let mut records: HashMap<_, _> = ds.read_error_rates.iter().map(|r| (r.id, *r)).collect();
+        for read in ds.encoded_reads.iter().filter(|r| !r.nodes.is_empty()) {
+            let record = ReadErrorRate {
+                id: read.id,
+                error_rate: self.read(read.id).max(0f64),
+                num_nodes: read.nodes.len(),
+            };
+            records.insert(read.id, record);
+        }
+        let mut records: Vec<_> = records.into_values().collect();
+        records.sort_by_key(|r| r.id);
+        ds.read_error_rates = records;
+    }
+}
+
+// Reads with fewer nodes than this are not reliable enough to have their own thresholds/weights.
+const MIN_NODES_FOR_READ_ERROR: usize = 3;
+fn reliable_error_rates(ds: &DataSet) -> impl Iterator<Item = &ReadErrorRate> {
+    ds.read_error_rates
+        .iter()
+        .filter(|r| MIN_NODES_FOR_READ_ERROR <= r.num_nodes)
+}
+
+fn median_of(xs: &mut [f64]) -> Option<f64> {
+    if xs.is_empty() {
+        return None;
+    }
+    let idx = xs.len() / 2;
+    Some(
+        *xs.select_nth_unstable_by(idx, |x, y| x.partial_cmp(y).unwrap())
+            .1,
+    )
+}
+
+fn median_read_error(ds: &DataSet) -> Option<f64> {
+    let mut errors: Vec<_> = reliable_error_rates(ds).map(|r| r.error_rate).collect();
+    median_of(&mut errors)
+}
+
+/// Per-read similarity thresholds, shifting `sim_thr` by the deviation of the read's error rate from the median.
+/// The thresholds are clamped into [sim_thr/2, 2 sim_thr]. Reads without a reliable estimation are not listed,
+/// and should use `sim_thr` as is.
+pub fn read_sim_thresholds(ds: &DataSet, sim_thr: f64) -> HashMap<u64, f64> {
+    let median = match median_read_error(ds) {
+        Some(median) => median,
+        None => return HashMap::new(),
+    };
+    reliable_error_rates(ds)
+        .map(|r| {
+            let thr = (sim_thr + r.error_rate - median).clamp(sim_thr / 2f64, 2f64 * sim_thr);
+            (r.id, thr)
+        })
+        .collect()
+}
+
+const MIN_READ_WEIGHT: f64 = 0.25;
+/// The weights of the reads, the ratio of the median error rate to the read's error rate, in [0.25, 1].
+/// Reads better than the median, or without a reliable estimation, are not listed and have the weight of 1.
+pub fn read_weights(ds: &DataSet) -> HashMap<u64, f64> {
+    let median = match median_read_error(ds) {
+        Some(median) => median,
+        None => return HashMap::new(),
+    };
+    reliable_error_rates(ds)
+        .filter(|r| median < r.error_rate)
+        .map(|r| (r.id, (median / r.error_rate).clamp(MIN_READ_WEIGHT, 1f64)))
+        .collect()
+}
+
+// Scale factor from the median absolute deviation to the standard deviation.
+const MAD_TO_SD: f64 = 1.4826;
+/// Discard reads whose error rate is larger than median + `max_deviation` * (robust standard deviation),
+/// both from the raw reads and the encoded reads. Return the number of discarded reads.
+pub fn discard_outlier_reads(ds: &mut DataSet, max_deviation: f64) -> usize {
+    let mut errors: Vec<_> = reliable_error_rates(ds).map(|r| r.error_rate).collect();
+    let median = match median_of(&mut errors) {
+        Some(median) => median,
+        None => return 0,
+    };
+    let mut deviations: Vec<_> = errors.iter().map(|e| (e - median).abs()).collect();
+    let mad = median_of(&mut deviations).unwrap();
+    let upper_bound = median + max_deviation * mad * MAD_TO_SD;
+    let outliers: std::collections::HashSet<_> = reliable_error_rates(ds)
+        .filter(|r| upper_bound < r.error_rate)
+        .map(|r| r.id)
+        .collect();
+    debug!(
+        "OUTLIER\t{median:.4}\t{mad:.4}\t{upper_bound:.4}\t{}",
+        outliers.len()
+    );
+    ds.raw_reads.retain(|r| !outliers.contains(&r.id));
+    ds.encoded_reads.retain(|r| !outliers.contains(&r.id));
+    ds.read_error_rates.retain(|r| !outliers.contains(&r.id));
+    outliers.len()
 }
 
 type Read = (usize, Vec<(usize, usize, f64)>);
@@ -54,7 +151,10 @@ pub fn estimate_error_rate(ds: &DataSet, fallback: f64) -> ErrorRate {
     };
     let mut read_error_rate = vec![0f64; max_read_id + 1];
     for read in ds.encoded_reads.iter() {
-        read_error_rate[read.id as usize] = fallback;
+        read_error_rate[read.id as usize] = ds
+            .read_error_rate(read.id)
+            .map(|r| r.error_rate)
+            .unwrap_or(fallback);
     }
     let chunks: HashMap<_, _> = ds.selected_chunks.iter().map(|c| (c.id, c)).collect();
     let errors: Vec<_> = ds
@@ -131,3 +231,39 @@ pub fn estimate_error_rate(ds: &DataSet, fallback: f64) -> ErrorRate {
         median_of_sqrt_err: median,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn discard_outlier_reads_test() {
+        let mut ds = DataSet::default();
+        let errors = [0.05, 0.06, 0.05, 0.07, 0.06, 0.05, 0.3, 0.06];
+        for (id, &error_rate) in errors.iter().enumerate() {
+            let id = id as u64;
+            let read = RawRead {
+                name: format!("{id}"),
+                desc: String::new(),
+                id,
+                seq: b"ACGT".to_vec().into(),
+            };
+            ds.raw_reads.push(read);
+            let num_nodes = 10;
+            ds.read_error_rates.push(ReadErrorRate {
+                id,
+                error_rate,
+                num_nodes,
+            });
+        }
+        let thrs = read_sim_thresholds(&ds, 0.1);
+        assert!(thrs[&6] > thrs[&0]);
+        assert!(thrs.values().all(|&thr| (0.05..=0.2).contains(&thr)));
+        let weights = read_weights(&ds);
+        assert!(weights[&6] < 0.5);
+        assert!(!weights.contains_key(&0));
+        assert_eq!(discard_outlier_reads(&mut ds, 3f64), 1);
+        assert!(ds.raw_reads.iter().all(|r| r.id != 6));
+        assert!(ds.read_error_rate(6).is_none());
+        assert!(ds.read_error_rate(7).is_some());
+    }
+}
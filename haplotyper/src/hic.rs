@@ -0,0 +1,343 @@
+//! Hi-C ingestion and Hi-C based phasing.
+//!
+//! The Hi-C pairs are mapped onto the consensus of each (chunk, cluster) with minimap2, and the pairs
+//! with both mates uniquely mapped are stored as [`HiCEdge`]s. In the assembly, the contigs with
+//! copy number one (haplotigs) are phased by these contacts. Two haplotigs sharing a chunk are
+//! alleles of each other, so they go to different haplotypes, while haplotigs with many contacts go to the same one.
+//! As the contacts span long distances, this links the phase blocks separated by homozygous regions.
+use crate::assemble::ditch_graph::ContigSummary;
+use crate::find_union::FindUnion;
+use definitions::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct HiCConfig {
+    threads: usize,
+    // Mates with mapping quality less than this value are discarded.
+    min_mapq: u8,
+}
+
+impl std::default::Default for HiCConfig {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            min_mapq: 5,
+        }
+    }
+}
+
+impl HiCConfig {
+    pub fn new(threads: usize, min_mapq: u8) -> Self {
+        Self { threads, min_mapq }
+    }
+}
+
+pub trait HiC {
+    /// Replace `hic_pairs` by the pairs in `r1` and `r2` (FASTA/FASTQ, the i-th records are the mates).
+    /// Registering the same files again does not duplicate the pairs.
+    fn register_hic_pairs(&mut self, r1: &str, r2: &str) -> std::io::Result<()>;
+    /// Map `hic_pairs` onto the (chunk, cluster)s, and replace `hic_edges`.
+    fn map_hic_pairs(&mut self, config: &HiCConfig) -> std::io::Result<()>;
+}
+
+impl HiC for DataSet {
+    fn register_hic_pairs(&mut self, r1: &str, r2: &str) -> std::io::Result<()> {
+        let (mates1, mates2) = (parse_seqs(r1)?, parse_seqs(r2)?);
+        if mates1.len() != mates2.len() {
+            error!("{r1} and {r2} have different number of records.");
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidData));
+        }
+        let pairs = std::iter::zip(mates1, mates2)
+            .enumerate()
+            .map(|(i, (seq1, seq2))| {
+                let pair_id = i as u64;
+                HiCPair {
+                    pair1: 2 * pair_id,
+                    pair2: 2 * pair_id + 1,
+                    pair_id,
+                    seq1,
+                    seq2,
+                }
+            });
+        self.hic_pairs = pairs.collect();
+        debug!("HIC\tPAIRS\t{}", self.hic_pairs.len());
+        Ok(())
+    }
+    fn map_hic_pairs(&mut self, config: &HiCConfig) -> std::io::Result<()> {
+        let consensus = crate::encode::deletion_fill::take_consensus_sequence(self);
+        let paf = map_mates(&self.hic_pairs, &consensus, config.threads)?;
+        let mut hits: HashMap<(u64, bool), (u64, u64, u8)> = HashMap::new();
+        for (query, hit) in String::from_utf8_lossy(&paf).lines().filter_map(parse_hit) {
+            let slot = hits.entry(query).or_insert(hit);
+            if slot.2 < hit.2 {
+                *slot = hit;
+            }
+        }
+        self.hic_edges = self
+            .hic_pairs
+            .iter()
+            .filter_map(|pair| {
+                let &(chunk1, cluster1, mapq1) = hits.get(&(pair.pair_id, true))?;
+                let &(chunk2, cluster2, mapq2) = hits.get(&(pair.pair_id, false))?;
+                let is_unique = config.min_mapq <= mapq1.min(mapq2);
+                (is_unique && chunk1 != chunk2).then_some(HiCEdge {
+                    pair_id: pair.pair_id,
+                    pair1: chunk1,
+                    pair2: chunk2,
+                    cluster1,
+                    cluster2,
+                })
+            })
+            .collect();
+        debug!("HIC\tEDGES\t{}\t{}", self.hic_edges.len(), hits.len());
+        Ok(())
+    }
+}
+
+fn parse_seqs(file: &str) -> std::io::Result<Vec<String>> {
+    let reader = std::fs::File::open(file).map(std::io::BufReader::new)?;
+    let seqs = match file.ends_with('a') {
+        true => bio_utils::fasta::parse_into_vec_from(reader)?
+            .into_iter()
+            .map(|record| {
+                let (_, _, seq) = record.into();
+                seq.to_ascii_uppercase()
+            })
+            .collect(),
+        false => bio_utils::fastq::parse_into_vec_from(reader)?
+            .into_iter()
+            .map(|record| String::from_utf8_lossy(record.seq()).to_ascii_uppercase())
+            .collect(),
+    };
+    Ok(seqs)
+}
+
+// Query (pair ID, is the first mate) and the target (chunk, cluster, mapq) of a PAF line.
+fn parse_hit(line: &str) -> Option<((u64, bool), (u64, u64, u8))> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let (pair_id, mate) = fields.first()?.split_once('_')?;
+    let (chunk, cluster) = fields.get(5)?.split_once('-')?;
+    let mapq = fields.get(11)?.parse().ok()?;
+    let query = (pair_id.parse().ok()?, mate == "1");
+    Some((query, (chunk.parse().ok()?, cluster.parse().ok()?, mapq)))
+}
+
+fn map_mates(
+    pairs: &[HiCPair],
+    consensus: &HashMap<(u64, u64), Vec<u8>>,
+    threads: usize,
+) -> std::io::Result<Vec<u8>> {
+    use std::io::{BufWriter, Write};
+    let mut dir = std::env::current_dir()?;
+    dir.push(format!("{}", rand::random::<u64>() % 100_000_000));
+    std::fs::create_dir(&dir)?;
+    let references = {
+        let mut path = dir.clone();
+        path.push("clusters.fa");
+        let mut wtr = std::fs::File::create(&path).map(BufWriter::new)?;
+        for ((chunk, cluster), seq) in consensus.iter() {
+            let seq = std::str::from_utf8(seq).unwrap();
+            writeln!(wtr, ">{chunk}-{cluster}\n{seq}")?;
+        }
+        wtr.flush()?;
+        path.into_os_string().into_string().unwrap()
+    };
+    let mates = {
+        let mut path = dir.clone();
+        path.push("mates.fa");
+        let mut wtr = std::fs::File::create(&path).map(BufWriter::new)?;
+        for pair in pairs.iter() {
+            writeln!(wtr, ">{}_1\n{}", pair.pair_id, pair.seq1)?;
+            writeln!(wtr, ">{}_2\n{}", pair.pair_id, pair.seq2)?;
+        }
+        wtr.flush()?;
+        path.into_os_string().into_string().unwrap()
+    };
+    let threads = format!("{threads}");
+    let args = vec!["-t", &threads, "-x", "sr", "--secondary=no"];
+    let paf = crate::minimap2::minimap2_args(&references, &mates, &args);
+    std::fs::remove_dir_all(dir)?;
+    Ok(paf)
+}
+
+/// The phase of a contig. Contigs in the same `phase_set` with the same `haplotype` are on the same haplotype.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContigPhase {
+    pub phase_set: usize,
+    /// 1 or 2.
+    pub haplotype: usize,
+}
+
+// Two haplotigs need this many contacts to be linked.
+const MIN_CONTACTS: u32 = 3;
+// The weight of the constraint that two alleles should be in different haplotypes.
+const ALLELE_WEIGHT: f64 = 1_000f64;
+const MAX_FLIP_ROUND: usize = 100;
+
+/// Phase the contigs with copy number one by the Hi-C contacts. Contigs without enough contacts are not phased.
+pub fn phase_contigs(
+    summaries: &[ContigSummary],
+    edges: &[HiCEdge],
+) -> HashMap<String, ContigPhase> {
+    let haplotigs: Vec<_> = summaries.iter().filter(|s| is_haplotig(s)).collect();
+    let mut node_to_contig: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut chunk_to_contigs: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, contig) in haplotigs.iter().enumerate() {
+        for elm in contig.summary.iter() {
+            node_to_contig.insert((elm.chunk, elm.cluster), i);
+            chunk_to_contigs.entry(elm.chunk).or_default().push(i);
+        }
+    }
+    let mut weights: HashMap<(usize, usize), f64> = HashMap::new();
+    let mut contacts: HashMap<(usize, usize), u32> = HashMap::new();
+    for edge in edges.iter() {
+        let i = node_to_contig.get(&(edge.pair1, edge.cluster1));
+        let j = node_to_contig.get(&(edge.pair2, edge.cluster2));
+        if let (Some(&i), Some(&j)) = (i, j) {
+            if i != j {
+                *contacts.entry((i.min(j), i.max(j))).or_default() += 1;
+            }
+        }
+    }
+    for (&key, &count) in contacts.iter().filter(|&(_, &c)| MIN_CONTACTS <= c) {
+        *weights.entry(key).or_default() += count as f64;
+    }
+    for contigs in chunk_to_contigs.values_mut() {
+        contigs.sort_unstable();
+        contigs.dedup();
+        for (k, &i) in contigs.iter().enumerate() {
+            for &j in contigs.iter().skip(k + 1) {
+                *weights.entry((i, j)).or_default() -= ALLELE_WEIGHT;
+            }
+        }
+    }
+    let mut fu = FindUnion::new(haplotigs.len());
+    let mut neighbors = vec![vec![]; haplotigs.len()];
+    for (&(i, j), &w) in weights.iter() {
+        fu.unite(i, j);
+        neighbors[i].push((j, w));
+        neighbors[j].push((i, w));
+    }
+    neighbors.iter_mut().for_each(|ns| ns.sort_by_key(|x| x.0));
+    let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..haplotigs.len() {
+        components.entry(fu.find(i).unwrap()).or_default().push(i);
+    }
+    let mut components: Vec<_> = components.into_values().filter(|c| 1 < c.len()).collect();
+    components.sort();
+    let mut phases = HashMap::new();
+    for (phase_set, component) in components.iter().enumerate() {
+        let spins = phase_component(component, &neighbors);
+        for (&i, spin) in std::iter::zip(component, spins) {
+            let haplotype = if spin { 1 } else { 2 };
+            let phase = ContigPhase {
+                phase_set,
+                haplotype,
+            };
+            phases.insert(haplotigs[i].id.clone(), phase);
+        }
+        debug!("HIC\tPHASESET\t{phase_set}\t{}", component.len());
+    }
+    phases
+}
+
+fn is_haplotig(summary: &ContigSummary) -> bool {
+    let (total, num) = summary
+        .summary
+        .iter()
+        .filter_map(|elm| elm.copy_number)
+        .fold((0, 0), |(t, n), cp| (t + cp, n + 1));
+    0 < num && (total as f64 / num as f64).round() as usize == 1
+}
+
+// Assign each contig in the component to one of the two haplotypes (true/false),
+// maximizing the total weight between the contigs in the same haplotype.
+fn phase_component(component: &[usize], neighbors: &[Vec<(usize, f64)>]) -> Vec<bool> {
+    let index: HashMap<_, _> = component.iter().enumerate().map(|(k, &i)| (i, k)).collect();
+    let mut spins: Vec<Option<bool>> = vec![None; component.len()];
+    let field = |k: usize, spins: &[Option<bool>]| -> f64 {
+        neighbors[component[k]]
+            .iter()
+            .filter_map(|&(j, w)| spins[index[&j]].map(|s| if s { w } else { -w }))
+            .sum()
+    };
+    // Greedy: fix the contig most constrained by the fixed ones.
+    spins[0] = Some(true);
+    while spins.iter().any(|s| s.is_none()) {
+        let (k, f) = (0..component.len())
+            .filter(|&k| spins[k].is_none())
+            .map(|k| (k, field(k, &spins)))
+            .max_by(|x, y| x.1.abs().partial_cmp(&y.1.abs()).unwrap())
+            .unwrap();
+        spins[k] = Some(0f64 <= f);
+    }
+    // Local search: flip a contig if it increases the total weight.
+    for _ in 0..MAX_FLIP_ROUND {
+        let mut flipped = false;
+        for k in 0..component.len() {
+            let current = spins[k].unwrap();
+            let f = field(k, &spins);
+            if (0f64 <= f) != current && f != 0f64 {
+                spins[k] = Some(!current);
+                flipped = true;
+            }
+        }
+        if !flipped {
+            break;
+        }
+    }
+    spins.into_iter().map(|s| s.unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble::ditch_graph::ContigElement;
+    fn contig(id: &str, nodes: &[(u64, u64)]) -> ContigSummary {
+        let summary = nodes
+            .iter()
+            .map(|&(chunk, cluster)| ContigElement {
+                chunk,
+                cluster,
+                strand: true,
+                occ: 10,
+                copy_number: Some(1),
+            })
+            .collect();
+        ContigSummary {
+            id: id.to_string(),
+            summary,
+            reference: None,
+        }
+    }
+    fn edges(from: (u64, u64), to: (u64, u64), count: usize) -> Vec<HiCEdge> {
+        (0..count)
+            .map(|i| HiCEdge {
+                pair_id: i as u64,
+                pair1: from.0,
+                cluster1: from.1,
+                pair2: to.0,
+                cluster2: to.1,
+            })
+            .collect()
+    }
+    #[test]
+    fn phase_contigs_test() {
+        // Two bubbles, on chunks 0-1 and 5-6, separated by a homozygous region.
+        let summaries = vec![
+            contig("a0", &[(0, 0), (1, 0)]),
+            contig("a1", &[(0, 1), (1, 1)]),
+            contig("b0", &[(5, 0), (6, 0)]),
+            contig("b1", &[(5, 1), (6, 1)]),
+        ];
+        let mut hic = edges((1, 0), (5, 1), 10);
+        hic.extend(edges((1, 1), (6, 0), 8));
+        hic.extend(edges((0, 0), (6, 0), 1));
+        let phases = phase_contigs(&summaries, &hic);
+        assert_eq!(phases.len(), 4);
+        assert!(phases.values().all(|p| p.phase_set == 0));
+        assert_eq!(phases["a0"].haplotype, phases["b1"].haplotype);
+        assert_eq!(phases["a1"].haplotype, phases["b0"].haplotype);
+        assert_ne!(phases["a0"].haplotype, phases["a1"].haplotype);
+    }
+}
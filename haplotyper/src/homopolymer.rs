@@ -0,0 +1,383 @@
+//! Homopolymer-length-dependent indel rates on top of the pair-HMM.
+//! Reads, especially ONT reads, make most of their indels in homopolymers, and the rate grows with the length.
+//! The rates are estimated from the alignments of the reads to the chunks ([`estimate_homopolymer_indels`]).
+//! The pair-HMM of kiley has the same indel rates at every base, so [`HomopolymerModel`] replaces
+//! its likelihood of the homopolymer lengths by these rates in the modification tables ([`modification_tables`]),
+//! which are used in the variant calling and in the polishing ([`polish_homopolymers`]).
+use definitions::{HMMParam, HMMParamOnStrands, HomopolymerIndel};
+use kiley::hmm::{PairHiddenMarkovModelOnStrands, COPY_SIZE, NUM_ROW};
+use kiley::Op;
+
+/// Homopolymers longer than this value share the rates.
+pub const MAX_HOMOPOLYMER_LEN: usize = 10;
+// Lengths observed less than this value inherit the rates of the shorter homopolymers.
+// The table ends at the longest length observed at least this many times.
+const MIN_OBSERVATION: usize = 20;
+// Upper bound of the rates, to keep the log-likelihood finite.
+const MAX_RATE: f64 = 0.4;
+// Lower bound of the rates.
+const MIN_RATE: f64 = 0.0001;
+
+/// Runs of the same base in `seq`, as (start, length, base).
+pub fn homopolymer_runs(seq: &[u8]) -> Vec<(usize, usize, u8)> {
+    let mut runs: Vec<(usize, usize, u8)> = vec![];
+    for (i, &base) in seq.iter().enumerate() {
+        match runs.last_mut() {
+            Some(last) if last.2 == base => last.1 += 1,
+            _ => runs.push((i, 1, base)),
+        }
+    }
+    runs
+}
+
+/// The changes of the homopolymer lengths in the query, as (the length in the reference, the change).
+/// Homopolymers at the ends of the reference are skipped, as their lengths are not determined by the alignment.
+pub fn run_length_changes(refseq: &[u8], query: &[u8], ops: &[Op]) -> Vec<(usize, isize)> {
+    run_changes(refseq, query, ops)
+        .into_iter()
+        .map(|((_, len, _), change)| (len, change))
+        .collect()
+}
+
+// Same as `run_length_changes`, but with the runs, (start, length, base), instead of the lengths.
+fn run_changes(refseq: &[u8], query: &[u8], ops: &[Op]) -> Vec<((usize, usize, u8), isize)> {
+    // Deleted or not, for each reference position.
+    let mut deleted = vec![false; refseq.len()];
+    // Inserted bases before each reference position.
+    let mut inserted: Vec<Vec<u8>> = vec![vec![]; refseq.len() + 1];
+    let (mut rpos, mut qpos) = (0, 0);
+    for &op in ops {
+        match op {
+            Op::Match | Op::Mismatch => {
+                rpos += 1;
+                qpos += 1;
+            }
+            Op::Del => {
+                deleted[rpos] = true;
+                rpos += 1;
+            }
+            Op::Ins => {
+                inserted[rpos].push(query[qpos]);
+                qpos += 1;
+            }
+        }
+    }
+    assert_eq!(rpos, refseq.len());
+    assert_eq!(qpos, query.len());
+    homopolymer_runs(refseq)
+        .into_iter()
+        .filter(|&(start, len, _)| 0 < start && start + len < refseq.len())
+        .map(|(start, len, base)| {
+            let del = deleted[start..start + len].iter().filter(|&&d| d).count();
+            let ins = inserted[start..=start + len]
+                .iter()
+                .flatten()
+                .filter(|&&b| b == base)
+                .count();
+            ((start, len, base), ins as isize - del as isize)
+        })
+        .collect()
+}
+
+/// Estimate the homopolymer-length-dependent indel rates from the changes of the homopolymer lengths,
+/// typically computed by [`run_length_changes`].
+pub fn estimate_homopolymer_indels<I: Iterator<Item = (usize, isize)>>(
+    changes: I,
+) -> HomopolymerIndel {
+    // (# of runs, # of runs getting longer, # of runs getting shorter)
+    let mut counts = vec![(0, 0, 0); MAX_HOMOPOLYMER_LEN];
+    for (len, change) in changes {
+        let count = &mut counts[len.min(MAX_HOMOPOLYMER_LEN) - 1];
+        count.0 += 1;
+        count.1 += (0 < change) as usize;
+        count.2 += (change < 0) as usize;
+    }
+    let max_len = counts
+        .iter()
+        .rposition(|c| MIN_OBSERVATION <= c.0)
+        .map_or(0, |idx| idx + 1);
+    let (mut ins, mut del) = (vec![], vec![]);
+    for &(total, longer, shorter) in counts.iter().take(max_len) {
+        let (ins_rate, del_rate) = match (MIN_OBSERVATION <= total, ins.last(), del.last()) {
+            (true, _, _) => (longer as f64 / total as f64, shorter as f64 / total as f64),
+            (false, Some(&i), Some(&d)) => (i, d),
+            (false, _, _) => break,
+        };
+        ins.push(ins_rate.clamp(MIN_RATE, MAX_RATE));
+        del.push(del_rate.clamp(MIN_RATE, MAX_RATE));
+    }
+    HomopolymerIndel { ins, del }
+}
+
+// Log-likelihood of observing a homopolymer with `change` bases longer than `len`.
+fn log_lk(homop: &HomopolymerIndel, len: usize, change: isize) -> f64 {
+    let (ins, del) = (homop.ins(len).unwrap(), homop.del(len).unwrap());
+    match change {
+        0 => (1f64 - ins - del).ln(),
+        x if 0 < x => x as f64 * ins.ln(),
+        x => -x as f64 * del.ln(),
+    }
+}
+
+// The row of the modification table to delete the base.
+const DEL_ROW: usize = 8 + COPY_SIZE;
+// The row of the modification table to insert the base before the position.
+fn ins_row(base: u8) -> Option<usize> {
+    b"ACGT".iter().position(|&b| b == base).map(|i| 4 + i)
+}
+
+/// The homopolymer-length-dependent indel rates of a strand, paired with the ones implicit in the pair-HMM.
+/// As the pair-HMM has the same indel rates at every base, a homopolymer of length l gets one base longer
+/// (shorter) with the probability of about `(l+1) * mat_ins / 4` (`l * mat_del`).
+#[derive(Debug, Clone, Default)]
+pub struct HomopolymerModel {
+    rates: HomopolymerIndel,
+    pair_hmm: HomopolymerIndel,
+}
+
+impl HomopolymerModel {
+    pub fn new(param: &HMMParam) -> Self {
+        let len = param.homopolymer.len();
+        let ins = (1..=len).map(|l| (l + 1) as f64 * param.mat_ins / 4f64);
+        let ins = ins.map(|x| x.clamp(MIN_RATE, MAX_RATE)).collect();
+        let del = (1..=len).map(|l| l as f64 * param.mat_del);
+        let del = del.map(|x| x.clamp(MIN_RATE, MAX_RATE)).collect();
+        Self {
+            rates: param.homopolymer.clone(),
+            pair_hmm: HomopolymerIndel { ins, del },
+        }
+    }
+    /// The models on the forward and the reverse strand.
+    pub fn on_strands(param: &HMMParamOnStrands) -> [Self; 2] {
+        [Self::new(&param.forward), Self::new(&param.reverse)]
+    }
+    /// Return true if the rates are not estimated. Then, the model does not change the likelihoods.
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+    /// The maximum length in the table of the rates.
+    pub fn len(&self) -> usize {
+        self.rates.len()
+    }
+    /// The rates of the homopolymer of length `len` getting one base longer and shorter.
+    pub fn rates(&self, len: usize) -> Option<(f64, f64)> {
+        self.rates.ins(len).zip(self.rates.del(len))
+    }
+    /// The log-likelihood to add to the one of the pair-HMM when a homopolymer of length `len`,
+    /// which is `change` bases longer in a read, is made `new_len` long.
+    pub fn correction(&self, len: usize, new_len: usize, change: isize) -> f64 {
+        if self.is_empty() || len == 0 || new_len == 0 {
+            return 0f64;
+        }
+        let new_change = change + len as isize - new_len as isize;
+        let (rates, pair_hmm) = (&self.rates, &self.pair_hmm);
+        let learned = log_lk(rates, new_len, new_change) - log_lk(rates, len, change);
+        let implicit = log_lk(pair_hmm, new_len, new_change) - log_lk(pair_hmm, len, change);
+        learned - implicit
+    }
+    /// Add the corrections to the modification table of a read aligned to the template by `ops`,
+    /// at the columns making a homopolymer one base shorter (deleting a base in it)
+    /// or longer (inserting the same base in or next to it).
+    pub fn correct_modification_table(
+        &self,
+        template: &[u8],
+        query: &[u8],
+        ops: &[Op],
+        table: &mut [f64],
+    ) {
+        if self.is_empty() {
+            return;
+        }
+        for ((start, len, base), change) in run_changes(template, query, ops) {
+            if 1 < len {
+                let shorter = self.correction(len, len - 1, change);
+                for pos in start..start + len {
+                    if let Some(x) = table.get_mut(pos * NUM_ROW + DEL_ROW) {
+                        *x += shorter;
+                    }
+                }
+            }
+            if let Some(row) = ins_row(base) {
+                let longer = self.correction(len, len + 1, change);
+                for pos in start..=start + len {
+                    if let Some(x) = table.get_mut(pos * NUM_ROW + row) {
+                        *x += longer;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The modification tables of the reads by the pair-HMM, corrected by the homopolymer models on each strand
+/// (`[forward, reverse]`). The likelihood of the template is subtracted, i.e., each column is the gain of the modification.
+pub fn modification_tables<T: std::borrow::Borrow<[u8]>>(
+    hmm: &PairHiddenMarkovModelOnStrands,
+    homopolymers: &[HomopolymerModel; 2],
+    template: &[u8],
+    reads: &[T],
+    ops: &[Vec<Op>],
+    strands: &[bool],
+    band: usize,
+) -> Vec<Vec<f64>> {
+    reads
+        .iter()
+        .zip(ops)
+        .zip(strands)
+        .map(|((seq, op), &strand)| {
+            let (hmm, homop) = match strand {
+                true => (hmm.forward(), &homopolymers[0]),
+                false => (hmm.reverse(), &homopolymers[1]),
+            };
+            let (mut table, lk) =
+                hmm.modification_table_antidiagonal(template, seq.borrow(), op, band);
+            table.iter_mut().for_each(|x| *x -= lk);
+            homop.correct_modification_table(template, seq.borrow(), op, &mut table);
+            table
+        })
+        .collect()
+}
+
+// Log-likelihood gain required to change the length of a homopolymer.
+const MIN_LK_GAIN: f64 = 3f64;
+/// Make the homopolymers in `draft` one base longer or shorter if the total gain in the modification tables,
+/// typically by [`modification_tables`], is more than [`MIN_LK_GAIN`].
+/// Return `None` if no length is changed.
+pub fn polish_homopolymers<T: std::borrow::Borrow<[f64]>>(
+    draft: &[u8],
+    tables: &[T],
+) -> Option<Vec<u8>> {
+    let column_gain = |idx: usize| -> f64 {
+        tables
+            .iter()
+            .filter_map(|table| table.borrow().get(idx))
+            .sum()
+    };
+    let runs = homopolymer_runs(draft);
+    let mut polished = Vec::with_capacity(draft.len() + 10);
+    let mut is_changed = false;
+    for (i, &(start, len, base)) in runs.iter().enumerate() {
+        // The lengths at the ends are not determined by the alignments.
+        let is_inner = 0 < i && i + 1 < runs.len();
+        let shorter = (1 < len).then(|| (len - 1, column_gain(start * NUM_ROW + DEL_ROW)));
+        let longer = ins_row(base).map(|row| (len + 1, column_gain(start * NUM_ROW + row)));
+        let best = shorter
+            .into_iter()
+            .chain(longer)
+            .filter(|&(_, gain)| is_inner && MIN_LK_GAIN < gain)
+            .max_by(|x, y| x.1.partial_cmp(&y.1).unwrap());
+        let len = match best {
+            Some((len, _)) => {
+                is_changed = true;
+                len
+            }
+            None => len,
+        };
+        polished.resize(polished.len() + len, base);
+    }
+    is_changed.then_some(polished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn run_length_changes_test() {
+        let refseq = b"ACGGGTA";
+        let query = b"ACGGGGTA";
+        let mut ops = vec![Op::Match; 5];
+        ops.push(Op::Ins);
+        ops.extend([Op::Match; 2]);
+        let changes = run_length_changes(refseq, query, &ops);
+        assert_eq!(changes, vec![(1, 0), (3, 1), (1, 0)]);
+        let query = b"ACGGTA";
+        let ops = vec![
+            Op::Match,
+            Op::Match,
+            Op::Del,
+            Op::Match,
+            Op::Match,
+            Op::Match,
+            Op::Match,
+        ];
+        let changes = run_length_changes(refseq, query, &ops);
+        assert_eq!(changes, vec![(1, 0), (3, -1), (1, 0)]);
+    }
+    #[test]
+    fn estimate_homopolymer_indels_test() {
+        let changes = (0..100).map(|i| (1, (i < 2) as isize));
+        let changes = changes.chain((0..30).map(|i| (2, -((i < 3) as isize))));
+        let changes = changes.chain((0..5).map(|_| (3, 1)));
+        let homop = estimate_homopolymer_indels(changes);
+        assert_eq!(homop.len(), 2);
+        assert!((homop.ins(1).unwrap() - 0.02).abs() < 0.0001);
+        assert!((homop.del(2).unwrap() - 0.1).abs() < 0.0001);
+        assert!((homop.ins(3).unwrap() - MIN_RATE).abs() < 0.0001);
+        assert!((homop.del(10).unwrap() - 0.1).abs() < 0.0001);
+    }
+    // Deletions in long homopolymers are much more frequent than the pair-HMM's.
+    fn long_deletion_model() -> HomopolymerModel {
+        let homopolymer = HomopolymerIndel {
+            ins: vec![0.01, 0.02, 0.03, 0.05],
+            del: vec![0.01, 0.02, 0.03, 0.1],
+        };
+        HomopolymerModel::new(&HMMParam {
+            homopolymer,
+            ..Default::default()
+        })
+    }
+    #[test]
+    fn correction_test() {
+        let model = long_deletion_model();
+        // A deletion in a long homopolymer is more likely than the pair-HMM thinks,
+        // so a read lacking a base supports the shorter homopolymer less.
+        assert!(model.correction(4, 3, -1) < 0f64);
+        // An insertion in a homopolymer of length 3 is also more likely,
+        // so a read with four bases supports the shorter homopolymer more.
+        assert!(0f64 < model.correction(4, 3, 0));
+        assert!((model.correction(4, 4, 1)).abs() < 0.0001);
+        assert!((model.correction(4, 0, 1)).abs() < 0.0001);
+        let empty = HomopolymerModel::new(&HMMParam::default());
+        assert!(empty.is_empty());
+        assert!((empty.correction(4, 3, -1)).abs() < 0.0001);
+    }
+    #[test]
+    fn correct_modification_table_test() {
+        let model = long_deletion_model();
+        let template = b"ACGTAAAACGT";
+        let ops = vec![Op::Match; template.len()];
+        let mut table = vec![0f64; (template.len() + 1) * NUM_ROW];
+        model.correct_modification_table(template, template, &ops, &mut table);
+        let shorter = model.correction(4, 3, 0);
+        let longer = model.correction(4, 5, 0);
+        for pos in 4..8 {
+            assert!((table[pos * NUM_ROW + DEL_ROW] - shorter).abs() < 0.0001);
+        }
+        for pos in 4..9 {
+            assert!((table[pos * NUM_ROW + 4] - longer).abs() < 0.0001);
+        }
+        // Single bases can not be shorter, and other bases are not homopolymers.
+        assert!(table[2 * NUM_ROW + DEL_ROW].abs() < 0.0001);
+        assert!(table[5 * NUM_ROW + 5].abs() < 0.0001);
+    }
+    #[test]
+    fn polish_homopolymers_test() {
+        let draft = b"ACGTAAAACGT";
+        let mut table = vec![0f64; (draft.len() + 1) * NUM_ROW];
+        let tables = vec![table.clone(); 10];
+        assert!(polish_homopolymers(draft, &tables).is_none());
+        table[4 * NUM_ROW + 4] = 1f64;
+        let tables = vec![table.clone(); 10];
+        let polished = polish_homopolymers(draft, &tables).unwrap();
+        assert_eq!(polished, b"ACGTAAAAACGT".to_vec());
+        table[4 * NUM_ROW + DEL_ROW] = 2f64;
+        let tables = vec![table.clone(); 10];
+        let polished = polish_homopolymers(draft, &tables).unwrap();
+        assert_eq!(polished, b"ACGTAAACGT".to_vec());
+        // The first and the last homopolymers are never changed.
+        let mut table = vec![0f64; (draft.len() + 1) * NUM_ROW];
+        table[DEL_ROW] = 10f64;
+        table[4] = 10f64;
+        assert!(polish_homopolymers(draft, &[table]).is_none());
+    }
+}
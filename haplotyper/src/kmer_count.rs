@@ -0,0 +1,417 @@
+//! Counting canonical k-mers (k <= 63) into sorted arrays.
+//!
+//! The k-mers are encoded in 2 bits per base into `u128`. The sequences are processed in batches.
+//! The k-mers of a batch are partitioned by their leading bits, and each partition is sorted,
+//! run-length counted and merged into the counts so far, in parallel.
+//! As the partitions are ordered by the leading bits, their concatenation is sorted, and
+//! a k-mer can be looked up by binary search without any hashing.
+use rayon::prelude::*;
+use std::borrow::Borrow;
+
+pub type Kmer = u128;
+pub const MAX_K: usize = 63;
+// The k-mers are partitioned by the leading bits.
+const PARTITION_BITS: usize = 8;
+// The number of the bases processed at once.
+const BATCH_BASES: usize = 1 << 24;
+
+/// Canonical k-mers of a sequence, encoded in 2 bits per base.
+#[derive(Debug, Clone)]
+pub struct CanonicalKmers<'a> {
+    input: &'a [u8],
+    idx: usize,
+    forward: Kmer,
+    reverse: Kmer,
+    next: Option<Kmer>,
+    k: usize,
+}
+
+impl<'a> CanonicalKmers<'a> {
+    pub fn new(input: &'a [u8], k: usize) -> Self {
+        assert!(0 < k && k <= MAX_K);
+        let forward: Kmer = input
+            .iter()
+            .take(k)
+            .enumerate()
+            .map(|(i, &b)| (BASE2BIT[b as usize] as Kmer) << (2 * i))
+            .sum();
+        let reverse: Kmer = input
+            .iter()
+            .take(k)
+            .rev()
+            .enumerate()
+            .map(|(i, &b)| (BASE2BITCMP[b as usize] as Kmer) << (2 * i))
+            .sum();
+        let next = (k <= input.len()).then_some(forward.min(reverse));
+        Self {
+            input,
+            idx: k,
+            forward,
+            reverse,
+            next,
+            k,
+        }
+    }
+}
+
+impl<'a> std::iter::Iterator for CanonicalKmers<'a> {
+    type Item = Kmer;
+    fn next(&mut self) -> Option<Self::Item> {
+        let ret_value = self.next;
+        if let Some(&base) = self.input.get(self.idx) {
+            self.idx += 1;
+            let (fbit, rbit) = (BASE2BIT[base as usize], BASE2BITCMP[base as usize]);
+            self.forward = (self.forward >> 2) | ((fbit as Kmer) << (2 * (self.k - 1)));
+            let mask: Kmer = (1 << (2 * self.k)) - 1;
+            self.reverse = (mask & (self.reverse << 2)) | rbit as Kmer;
+            self.next = Some(self.forward.min(self.reverse));
+        } else {
+            self.next = None;
+        }
+        ret_value
+    }
+}
+
+const BASE2BIT: [u8; 256] = {
+    let mut slots = [0; 256];
+    slots[b'C' as usize] = 1;
+    slots[b'c' as usize] = 1;
+    slots[b'G' as usize] = 2;
+    slots[b'g' as usize] = 2;
+    slots[b'T' as usize] = 3;
+    slots[b't' as usize] = 3;
+    slots
+};
+
+const BASE2BITCMP: [u8; 256] = {
+    let mut slots = [0; 256];
+    slots[b'A' as usize] = 3;
+    slots[b'a' as usize] = 3;
+    slots[b'C' as usize] = 2;
+    slots[b'c' as usize] = 2;
+    slots[b'G' as usize] = 1;
+    slots[b'g' as usize] = 1;
+    slots
+};
+
+/// The occurrences of the canonical k-mers, sorted by the k-mers.
+#[derive(Debug, Clone, Default)]
+pub struct KmerCounts {
+    k: usize,
+    kmers: Vec<Kmer>,
+    counts: Vec<u32>,
+}
+
+impl KmerCounts {
+    /// Count the canonical k-mers in `seqs`.
+    pub fn new<T: Borrow<[u8]> + Sync>(seqs: &[T], k: usize) -> Self {
+        Self::with_batch_size(seqs, k, BATCH_BASES)
+    }
+    /// No k-mers, to be counted by [`KmerCounts::add`].
+    pub fn empty(k: usize) -> Self {
+        assert!(0 < k && k <= MAX_K);
+        Self {
+            k,
+            kmers: vec![],
+            counts: vec![],
+        }
+    }
+    /// Count the canonical k-mers in `seqs`, in addition to the ones counted so far.
+    pub fn add<T: Borrow<[u8]> + Sync>(&mut self, seqs: &[T]) {
+        self.add_with_batch_size(seqs, BATCH_BASES);
+    }
+    fn with_batch_size<T: Borrow<[u8]> + Sync>(seqs: &[T], k: usize, batch_bases: usize) -> Self {
+        let mut counts = Self::empty(k);
+        counts.add_with_batch_size(seqs, batch_bases);
+        counts
+    }
+    fn add_with_batch_size<T: Borrow<[u8]> + Sync>(&mut self, seqs: &[T], batch_bases: usize) {
+        let k = self.k;
+        let shift = (2 * k).saturating_sub(PARTITION_BITS);
+        let num_partitions = 1 << (2 * k).min(PARTITION_BITS);
+        // Split the counts so far into the partitions, from the last one.
+        let mut partitions: Vec<(Vec<Kmer>, Vec<u32>)> = vec![(vec![], vec![]); num_partitions];
+        for (i, partition) in partitions.iter_mut().enumerate().rev() {
+            let start = self
+                .kmers
+                .partition_point(|&kmer| ((kmer >> shift) as usize) < i);
+            *partition = (self.kmers.split_off(start), self.counts.split_off(start));
+        }
+        for batch in batches(seqs, batch_bases) {
+            let buckets: Vec<Vec<Vec<Kmer>>> = batch
+                .par_iter()
+                .fold(
+                    || vec![vec![]; num_partitions],
+                    |mut buckets, seq| {
+                        for kmer in CanonicalKmers::new(seq.borrow(), k) {
+                            buckets[(kmer >> shift) as usize].push(kmer);
+                        }
+                        buckets
+                    },
+                )
+                .collect();
+            partitions
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(i, (kmers, counts))| {
+                    let mut batch: Vec<_> = buckets.iter().flat_map(|b| b[i].iter()).collect();
+                    batch.sort_unstable();
+                    let batch = run_length(batch.into_iter().copied());
+                    let merged = merge((kmers.as_slice(), counts.as_slice()), batch);
+                    *kmers = merged.0;
+                    *counts = merged.1;
+                });
+        }
+        let len = partitions.iter().map(|p| p.0.len()).sum();
+        let (mut kmers, mut counts) = (Vec::with_capacity(len), Vec::with_capacity(len));
+        for (ks, cs) in partitions {
+            kmers.extend(ks);
+            counts.extend(cs);
+        }
+        self.kmers = kmers;
+        self.counts = counts;
+    }
+    pub fn k(&self) -> usize {
+        self.k
+    }
+    /// The number of the distinct k-mers.
+    pub fn len(&self) -> usize {
+        self.kmers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.kmers.is_empty()
+    }
+    /// The occurrence of `kmer`, zero if absent.
+    pub fn get(&self, kmer: Kmer) -> u32 {
+        match self.kmers.binary_search(&kmer) {
+            Ok(idx) => self.counts[idx],
+            Err(_) => 0,
+        }
+    }
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (Kmer, u32)> + '_ {
+        std::iter::zip(self.kmers.iter().copied(), self.counts.iter().copied())
+    }
+    /// Keep the k-mers whose occurrences satisfy `pred`.
+    pub fn retain<F: Fn(u32) -> bool>(&mut self, pred: F) {
+        let mut counts = self.counts.iter();
+        self.kmers.retain(|_| pred(*counts.next().unwrap()));
+        self.counts.retain(|&count| pred(count));
+        self.kmers.shrink_to_fit();
+        self.counts.shrink_to_fit();
+    }
+    /// The set of the k-mers whose occurrences satisfy `pred`.
+    pub fn into_set<F: Fn(u32) -> bool>(mut self, pred: F) -> KmerSet {
+        self.retain(pred);
+        KmerSet {
+            k: self.k,
+            kmers: self.kmers,
+        }
+    }
+}
+
+/// A set of the canonical k-mers, sorted.
+#[derive(Debug, Clone, Default)]
+pub struct KmerSet {
+    k: usize,
+    kmers: Vec<Kmer>,
+}
+
+impl KmerSet {
+    pub fn k(&self) -> usize {
+        self.k
+    }
+    pub fn len(&self) -> usize {
+        self.kmers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.kmers.is_empty()
+    }
+    pub fn contains(&self, kmer: Kmer) -> bool {
+        self.kmers.binary_search(&kmer).is_ok()
+    }
+    /// The k-mers in `self` but not in `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        assert_eq!(self.k, other.k);
+        let mut others = other.kmers.iter().peekable();
+        let kmers: Vec<_> = self
+            .kmers
+            .iter()
+            .filter(|&&kmer| {
+                while others.next_if(|&&x| x < kmer).is_some() {}
+                others.peek() != Some(&&kmer)
+            })
+            .copied()
+            .collect();
+        Self { k: self.k, kmers }
+    }
+}
+
+// Split `seqs` into consecutive batches having about `batch_bases` bases.
+fn batches<T: Borrow<[u8]>>(seqs: &[T], batch_bases: usize) -> Vec<&[T]> {
+    let mut batches = vec![];
+    let (mut start, mut bases) = (0, 0);
+    for (i, seq) in seqs.iter().enumerate() {
+        bases += seq.borrow().len();
+        if batch_bases <= bases {
+            batches.push(&seqs[start..i + 1]);
+            start = i + 1;
+            bases = 0;
+        }
+    }
+    if start < seqs.len() {
+        batches.push(&seqs[start..]);
+    }
+    batches
+}
+
+fn run_length<I: Iterator<Item = Kmer>>(sorted: I) -> (Vec<Kmer>, Vec<u32>) {
+    let (mut kmers, mut counts): (Vec<Kmer>, Vec<u32>) = (vec![], vec![]);
+    for kmer in sorted {
+        match kmers.last() {
+            Some(&last) if last == kmer => *counts.last_mut().unwrap() += 1,
+            _ => {
+                kmers.push(kmer);
+                counts.push(1);
+            }
+        }
+    }
+    (kmers, counts)
+}
+
+// Merge two sorted k-mer counts.
+fn merge((xs, xc): (&[Kmer], &[u32]), (ys, yc): (Vec<Kmer>, Vec<u32>)) -> (Vec<Kmer>, Vec<u32>) {
+    if xs.is_empty() {
+        return (ys, yc);
+    }
+    let len = xs.len() + ys.len();
+    let (mut kmers, mut counts) = (Vec::with_capacity(len), Vec::with_capacity(len));
+    let (mut i, mut j) = (0, 0);
+    while i < xs.len() && j < ys.len() {
+        match xs[i].cmp(&ys[j]) {
+            std::cmp::Ordering::Less => {
+                kmers.push(xs[i]);
+                counts.push(xc[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                kmers.push(ys[j]);
+                counts.push(yc[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                kmers.push(xs[i]);
+                counts.push(xc[i] + yc[j]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    kmers.extend_from_slice(&xs[i..]);
+    counts.extend_from_slice(&xc[i..]);
+    kmers.extend_from_slice(&ys[j..]);
+    counts.extend_from_slice(&yc[j..]);
+    kmers.shrink_to_fit();
+    counts.shrink_to_fit();
+    (kmers, counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoroshiro128Plus;
+    use std::collections::HashMap;
+    fn revcmp(seq: &[u8]) -> Vec<u8> {
+        seq.iter()
+            .rev()
+            .map(|&b| match b {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect()
+    }
+    fn naive_count(seqs: &[Vec<u8>], k: usize) -> HashMap<Vec<u8>, u32> {
+        let mut counts: HashMap<_, u32> = HashMap::new();
+        for seq in seqs.iter() {
+            for kmer in seq.windows(k) {
+                let kmer = kmer.to_vec().min(revcmp(kmer));
+                *counts.entry(kmer).or_default() += 1;
+            }
+        }
+        counts
+    }
+    fn encode(kmer: &[u8]) -> Kmer {
+        CanonicalKmers::new(kmer, kmer.len()).next().unwrap()
+    }
+    #[test]
+    fn canonical_kmers_test() {
+        let mut rng: Xoroshiro128Plus = SeedableRng::seed_from_u64(3289);
+        let seq: Vec<u8> = (0..500)
+            .map(|_| *b"ACGT".choose(&mut rng).unwrap())
+            .collect();
+        for k in [1, 10, 32, 33, 63] {
+            let kmers: Vec<_> = CanonicalKmers::new(&seq, k).collect();
+            assert_eq!(kmers.len(), seq.len() - k + 1);
+            for (kmer, window) in std::iter::zip(kmers, seq.windows(k)) {
+                assert_eq!(kmer, encode(window));
+                assert_eq!(kmer, encode(&revcmp(window)));
+                assert!(kmer < 1 << (2 * k));
+            }
+        }
+    }
+    #[test]
+    fn count_test() {
+        let mut rng: Xoroshiro128Plus = SeedableRng::seed_from_u64(3289);
+        let template: Vec<u8> = (0..2_000)
+            .map(|_| *b"ACGT".choose(&mut rng).unwrap())
+            .collect();
+        let seqs: Vec<Vec<u8>> = (0..20)
+            .map(|i| match i % 2 {
+                0 => template[i * 50..].to_vec(),
+                _ => revcmp(&template[..template.len() - i * 50]),
+            })
+            .collect();
+        for k in [3, 15, 40] {
+            let naive = naive_count(&seqs, k);
+            for batch_bases in [100, BATCH_BASES] {
+                let counts = KmerCounts::with_batch_size(&seqs, k, batch_bases);
+                assert_eq!(counts.len(), naive.len());
+                assert!(counts.kmers.windows(2).all(|w| w[0] < w[1]));
+                for (kmer, &count) in naive.iter() {
+                    assert_eq!(counts.get(encode(kmer)), count);
+                }
+            }
+        }
+        let mut counts = KmerCounts::new(&seqs, 15);
+        counts.retain(|count| 10 < count);
+        let set = KmerCounts::new(&seqs, 15).into_set(|count| 10 < count);
+        assert_eq!(counts.len(), set.len());
+        for (kmer, _) in counts.iter() {
+            assert!(set.contains(kmer));
+        }
+        let mut added = KmerCounts::empty(15);
+        for seqs in seqs.chunks(3) {
+            added.add(seqs);
+        }
+        let whole = KmerCounts::new(&seqs, 15);
+        assert!(added.iter().eq(whole.iter()));
+    }
+    #[test]
+    fn difference_test() {
+        let set = |seq: &[u8]| KmerCounts::new(&[seq], 3).into_set(|_| true);
+        let (xs, ys) = (set(b"AACGTTTGCA"), set(b"CGTTTGGGC"));
+        let diff = xs.difference(&ys);
+        assert_eq!(diff.len(), 1);
+        for window in b"AACGTTTGCA".windows(3) {
+            let kmer = encode(window);
+            assert_eq!(diff.contains(kmer), !ys.contains(kmer));
+        }
+        assert!(xs.difference(&xs).is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+//! Estimate the haploid coverage, the heterozygosity, and the repeat fraction from the k-mer spectrum.
+//!
+//! The histogram of the k-mer occurrences is modeled as a mixture of Poisson distributions
+//! with means `c * lambda` (c = 1, ..., max(8, 2 * ploidy)), where `lambda` is the k-mer coverage of a haplotype.
+//! The first `ploidy - 1` components are the heterozygous k-mers, shared by `c` of the haplotypes,
+//! the `ploidy`-th is the homozygous ones, and the rest are the repeats.
+//! The k-mers occurring less than the first trough of the histogram
+//! are regarded as erroneous and excluded from the fitting.
+use crate::kmer_count::KmerCounts;
+use definitions::*;
+
+// The minimum number of the components.
+const MAX_COPY: usize = 8;
+// Occurrences larger than this value are put into the last bin.
+const MAX_COUNT: usize = 10_000;
+// A component is regarded as a peak if it has more than this fraction of the other one.
+const MIN_PEAK_FRAC: f64 = 0.1;
+const MAX_ITER: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct SpectrumFit {
+    /// The mean occurrence of a k-mer on a haplotype.
+    pub kmer_coverage: f64,
+    /// The fraction of the (distinct) k-mers in each component.
+    pub weights: Vec<f64>,
+    /// The k-mers occurring less than this value are regarded as erroneous.
+    pub error_trough: usize,
+    /// The fraction of the k-mer occurrences regarded as erroneous.
+    pub error_fraction: f64,
+    pub heterozygosity: f64,
+    pub repeat_fraction: f64,
+    log_likelihood: f64,
+}
+
+/// Return the histogram of the occurrences. The last bin holds all the k-mers occurring `MAX_COUNT` times or more.
+pub fn histogram(counts: &KmerCounts) -> Vec<u64> {
+    let max = counts.counts().iter().max().map_or(0, |&x| x as usize);
+    let mut hist = vec![0; max.min(MAX_COUNT) + 1];
+    for &count in counts.counts() {
+        hist[(count as usize).min(MAX_COUNT)] += 1;
+    }
+    hist
+}
+
+/// Fit the mixture to the histogram of the `k`-mers of a `ploidy`-ploid genome.
+/// Return `None` if the histogram has no peak.
+pub fn fit_spectrum(hist: &[u64], k: usize, ploidy: usize) -> Option<SpectrumFit> {
+    if hist.len() < 4 || ploidy == 0 {
+        return None;
+    }
+    // The last bin is not used in the fitting.
+    let error_trough = (1..hist.len() - 1).find(|&x| hist[x] < hist[x + 1])?;
+    let peak = (error_trough..hist.len() - 1).max_by_key(|&x| (hist[x], std::cmp::Reverse(x)))?;
+    let ln_fact = log_factorials(hist.len());
+    let fit = |init: f64| em_fit(hist, error_trough, init, &ln_fact, k, ploidy);
+    // The highest peak is shared by `c` haplotypes. Starting from the homozygous one (c = ploidy),
+    // it is accepted if other single-copy peaks are found.
+    let homozygous = fit(peak as f64 / ploidy as f64);
+    for c in (1..=ploidy).rev() {
+        let fitted = match c == ploidy {
+            true => homozygous.clone(),
+            false => fit(peak as f64 / c as f64),
+        };
+        let weights = &fitted.weights;
+        let has_other_peak = (0..ploidy)
+            .filter(|&j| j != c - 1)
+            .any(|j| MIN_PEAK_FRAC * weights[c - 1] < weights[j]);
+        if has_other_peak {
+            return Some(fitted);
+        }
+    }
+    Some(homozygous)
+}
+
+fn log_factorials(len: usize) -> Vec<f64> {
+    let ln_fact = (1..len).scan(0f64, |acc, x| {
+        *acc += (x as f64).ln();
+        Some(*acc)
+    });
+    std::iter::once(0f64).chain(ln_fact).collect()
+}
+
+fn ln_poisson(x: usize, lambda: f64, ln_fact: &[f64]) -> f64 {
+    x as f64 * lambda.ln() - lambda - ln_fact[x]
+}
+
+// Log posteriors of the components at `x`, and their log sum.
+fn posteriors(x: usize, lambda: f64, weights: &[f64], ln_fact: &[f64]) -> (Vec<f64>, f64) {
+    let lks: Vec<_> = weights
+        .iter()
+        .enumerate()
+        .map(|(c, w)| w.ln() + ln_poisson(x, (c + 1) as f64 * lambda, ln_fact))
+        .collect();
+    let total = crate::misc::logsumexp(&lks);
+    let lks = lks.iter().map(|lk| lk - total).collect();
+    (lks, total)
+}
+
+fn em_fit(
+    hist: &[u64],
+    trough: usize,
+    init: f64,
+    ln_fact: &[f64],
+    k: usize,
+    ploidy: usize,
+) -> SpectrumFit {
+    let range = trough..hist.len() - 1;
+    let num_kmers: u64 = hist[range.clone()].iter().sum();
+    let max_copy = MAX_COPY.max(2 * ploidy);
+    let mut lambda = init.max(1f64);
+    let mut weights = vec![1f64 / max_copy as f64; max_copy];
+    let mut log_likelihood = f64::NEG_INFINITY;
+    for _ in 0..MAX_ITER {
+        let mut counts = vec![0f64; max_copy];
+        let (mut sum_x, mut sum_c, mut lk) = (0f64, 0f64, 0f64);
+        for x in range.clone().filter(|&x| 0 < hist[x]) {
+            let h = hist[x] as f64;
+            let (posts, total) = posteriors(x, lambda, &weights, ln_fact);
+            lk += h * total;
+            for (c, post) in posts.iter().enumerate() {
+                let r = h * post.exp();
+                counts[c] += r;
+                sum_x += r * x as f64;
+                sum_c += r * (c + 1) as f64;
+            }
+        }
+        lambda = sum_x / sum_c;
+        weights = counts
+            .iter()
+            .map(|&n| (n / num_kmers as f64).max(f64::MIN_POSITIVE))
+            .collect();
+        let improved = lk - log_likelihood;
+        log_likelihood = lk;
+        if improved.abs() < 1e-6 {
+            break;
+        }
+    }
+    // Occurrences of the erroneous, single-copy, and repetitive k-mers.
+    let (mut error, mut single, mut repeat) = (0f64, 0f64, 0f64);
+    for (x, &h) in hist.iter().enumerate().filter(|&(_, &h)| 0 < h) {
+        let occ = h as f64 * x as f64;
+        if x < trough {
+            error += occ;
+        } else if x == hist.len() - 1 {
+            repeat += occ;
+        } else {
+            let (posts, _) = posteriors(x, lambda, &weights, ln_fact);
+            let single_frac: f64 = posts.iter().take(ploidy).map(|p| p.exp()).sum();
+            single += occ * single_frac;
+            repeat += occ * (1f64 - single_frac);
+        }
+    }
+    let error_fraction = error / (error + single + repeat);
+    let repeat_fraction = repeat / (single + repeat);
+    // Each heterozygous site makes k distinct k-mers on each haplotype.
+    // A k-mer shared by c haplotypes accounts for c/ploidy of a position.
+    let positions: Vec<_> = (1..=ploidy)
+        .map(|c| weights[c - 1] * c as f64 / ploidy as f64)
+        .collect();
+    let het: f64 = positions.iter().take(ploidy - 1).sum();
+    let het_position = het / positions.iter().sum::<f64>();
+    let heterozygosity = 1f64 - (1f64 - het_position).powf((k as f64).recip());
+    SpectrumFit {
+        kmer_coverage: lambda,
+        weights,
+        error_trough: trough,
+        error_fraction,
+        heterozygosity,
+        repeat_fraction,
+        log_likelihood,
+    }
+}
+
+impl SpectrumFit {
+    /// The haploid coverage of reads with mean length `read_len`.
+    /// The k-mers containing errors and the k-mers not fully contained in the reads are compensated.
+    pub fn haploid_coverage(&self, k: usize, read_len: f64) -> f64 {
+        let contained = ((read_len - k as f64 + 1f64) / read_len).max(f64::MIN_POSITIVE);
+        self.kmer_coverage / (1f64 - self.error_fraction) / contained
+    }
+    /// The expected number of the k-mers occurring `x` times in each component.
+    fn expected(&self, x: usize, num_kmers: f64, ln_fact: &[f64]) -> Vec<f64> {
+        self.weights
+            .iter()
+            .enumerate()
+            .map(|(c, w)| {
+                let lambda = (c + 1) as f64 * self.kmer_coverage;
+                num_kmers * w * ln_poisson(x, lambda, ln_fact).exp()
+            })
+            .collect()
+    }
+}
+
+/// The observed and the fitted histogram, in TSV.
+pub fn spectrum_tsv(hist: &[u64], fit: Option<&SpectrumFit>) -> String {
+    let ln_fact = log_factorials(hist.len());
+    let mut header = vec!["count".to_string(), "observed".to_string()];
+    if let Some(fit) = fit {
+        header.push("fitted".to_string());
+        header.extend((1..=fit.weights.len()).map(|c| format!("copy{c}")));
+    }
+    let num_kmers = fit.map_or(0, |fit| hist[fit.error_trough..hist.len() - 1].iter().sum());
+    let mut lines = vec![header.join("\t")];
+    for (x, &h) in hist.iter().enumerate().skip(1) {
+        let mut line = vec![x.to_string(), h.to_string()];
+        if let Some(fit) = fit {
+            let expected = fit.expected(x, num_kmers as f64, &ln_fact);
+            line.push(format!("{:.2}", expected.iter().sum::<f64>()));
+            line.extend(expected.iter().map(|e| format!("{e:.2}")));
+        }
+        lines.push(line.join("\t"));
+    }
+    lines.join("\n")
+}
+
+/// Fit the mixture to the spectrum of the `k`-mers of the raw reads under `DataSet::ploidy`, and record the estimation.
+/// If the coverage is not given, it is set to the estimated haploid coverage.
+/// The histogram is written to `tsv`, if given.
+pub fn estimate_by_kmer_spectrum(ds: &mut DataSet, k: usize, tsv: Option<&str>) {
+    let seqs: Vec<_> = ds.raw_reads.iter().map(|r| r.seq()).collect();
+    let hist = histogram(&KmerCounts::new(&seqs, k));
+    let fit = fit_spectrum(&hist, k, ds.ploidy);
+    if let Some(path) = tsv {
+        if let Err(why) = std::fs::write(path, spectrum_tsv(&hist, fit.as_ref())) {
+            warn!("{why:?},{path}");
+        }
+    }
+    let fit = match fit {
+        Some(fit) => fit,
+        None => {
+            warn!("Failed to fit the k-mer spectrum (k={k}).");
+            return;
+        }
+    };
+    let total: usize = seqs.iter().map(|s| s.len()).sum();
+    let read_len = total as f64 / seqs.len().max(1) as f64;
+    let estimate = KmerSpectrumEstimate {
+        k,
+        haploid_coverage: fit.haploid_coverage(k, read_len),
+        heterozygosity: fit.heterozygosity,
+        repeat_fraction: fit.repeat_fraction,
+    };
+    debug!("SPECTRUM\tKmerCoverage\t{:.2}", fit.kmer_coverage);
+    debug!("SPECTRUM\tErrorFraction\t{:.3}", fit.error_fraction);
+    debug!("SPECTRUM\tLogLikelihood\t{:.1}", fit.log_likelihood);
+    debug!("SPECTRUM\tCoverage\t{:.2}", estimate.haploid_coverage);
+    debug!("SPECTRUM\tHeterozygosity\t{:.5}", estimate.heterozygosity);
+    debug!("SPECTRUM\tRepeatFraction\t{:.3}", estimate.repeat_fraction);
+    if !ds.coverage.is_protected() {
+        ds.coverage = Coverage::Estimated(estimate.haploid_coverage);
+    }
+    ds.kmer_spectrum = Some(estimate);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // Error k-mers and the k-mers of a diploid genome with `het` heterozygous and `hom` homozygous k-mers.
+    fn gen_hist(lambda: f64, het: f64, hom: f64) -> Vec<u64> {
+        let ln_fact = log_factorials(200);
+        let mut hist: Vec<_> = (0..200)
+            .map(|x| {
+                let het_count = het * ln_poisson(x, lambda, &ln_fact).exp();
+                let hom_count = hom * ln_poisson(x, 2f64 * lambda, &ln_fact).exp();
+                let rep_count = 0.02 * hom * ln_poisson(x, 5f64 * lambda, &ln_fact).exp();
+                (het_count + hom_count + rep_count).round() as u64
+            })
+            .collect();
+        hist[0] = 0;
+        hist[1] += 2_000_000;
+        hist[2] += 200_000;
+        hist[3] += 20_000;
+        hist
+    }
+    #[test]
+    fn heterozygous_test() {
+        let hist = gen_hist(15f64, 200_000f64, 1_000_000f64);
+        let fit = fit_spectrum(&hist, 21, 2).unwrap();
+        assert!((fit.kmer_coverage - 15f64).abs() < 0.5, "{:?}", fit);
+        let het_position = 0.1 / 1.1;
+        let het = 1f64 - (1f64 - het_position).powf(1f64 / 21f64);
+        assert!((fit.heterozygosity - het).abs() < het * 0.2, "{:?}", fit);
+        assert!(
+            0.01 < fit.repeat_fraction && fit.repeat_fraction < 0.1,
+            "{:?}",
+            fit
+        );
+    }
+    #[test]
+    fn homozygous_test() {
+        let hist = gen_hist(15f64, 0f64, 1_000_000f64);
+        let fit = fit_spectrum(&hist, 21, 2).unwrap();
+        assert!((fit.kmer_coverage - 15f64).abs() < 0.5, "{:?}", fit);
+        assert!(fit.heterozygosity < 0.001, "{:?}", fit);
+    }
+    #[test]
+    fn polyploid_test() {
+        // Tetraploid, with the k-mers shared by one, two, and four haplotypes.
+        let ln_fact = log_factorials(300);
+        let lambda = 10f64;
+        let mut hist: Vec<_> = (0..300)
+            .map(|x| {
+                let count: f64 = [(1f64, 300_000f64), (2f64, 100_000f64), (4f64, 1_000_000f64)]
+                    .iter()
+                    .map(|&(c, n)| n * ln_poisson(x, c * lambda, &ln_fact).exp())
+                    .sum();
+                count.round() as u64
+            })
+            .collect();
+        hist[0] = 0;
+        hist[1] += 2_000_000;
+        hist[2] += 200_000;
+        let fit = fit_spectrum(&hist, 21, 4).unwrap();
+        assert!((fit.kmer_coverage - lambda).abs() < 0.5, "{:?}", fit);
+        assert!(0.001 < fit.heterozygosity, "{:?}", fit);
+        assert!(fit.repeat_fraction < 0.01, "{:?}", fit);
+        // Haploid genomes have no heterozygous k-mers.
+        let hist = gen_hist(15f64, 0f64, 1_000_000f64);
+        let fit = fit_spectrum(&hist, 21, 1).unwrap();
+        assert!((fit.kmer_coverage - 30f64).abs() < 1f64, "{:?}", fit);
+        assert!(fit.heterozygosity.abs() < 0.0001, "{:?}", fit);
+    }
+    #[test]
+    fn highly_heterozygous_test() {
+        let hist = gen_hist(15f64, 2_000_000f64, 500_000f64);
+        let fit = fit_spectrum(&hist, 21, 2).unwrap();
+        assert!((fit.kmer_coverage - 15f64).abs() < 0.5, "{:?}", fit);
+    }
+}
@@ -1,6 +1,8 @@
 #[macro_use]
 extern crate log;
+pub mod add_reads;
 pub mod assemble;
+pub mod assembly_diff;
 pub mod consensus;
 pub mod copy_number_estimation;
 pub mod dense_encoding;
@@ -10,12 +12,20 @@ pub mod entry;
 pub mod estimate_error_rate;
 pub mod extract;
 pub mod find_union;
+pub mod hic;
+pub mod homopolymer;
+pub mod kmer_count;
+pub mod kmer_spectrum;
 pub mod likelihood_gains;
 pub mod local_clustering;
+pub mod locate_chunks;
 pub mod minimap2;
+pub mod minimizer_aligner;
 pub mod misc;
+pub mod model_library;
 pub mod model_tune;
 pub mod multiplicity_estimation;
+pub mod phasing;
 pub mod phmm_likelihood_correction;
 pub mod pick_component;
 pub mod polish_chunks;
@@ -26,19 +36,24 @@ pub mod repeat_masking;
 pub mod seq;
 pub mod squish_erroneous_clusters;
 pub mod stats;
+pub mod trio_binning;
 /// Re-imports.
+pub use add_reads::{AddReads, AddReadsConfig};
 pub use assemble::{Assemble, AssembleConfig};
 pub use dense_encoding::{DenseEncoding, DenseEncodingConfig};
 pub use determine_chunks::{DetermineUnit, DetermineUnitConfig};
 pub use encode::deletion_fill::{CorrectDeletion, CorrectDeletionConfig};
 pub use entry::Entry;
+pub use locate_chunks::{LocateChunks, LocateChunksConfig};
 pub use multiplicity_estimation::{MultiplicityEstimation, MultiplicityEstimationConfig};
+pub use phasing::{Phasing, PhasingConfig};
 pub use phmm_likelihood_correction::{AlignmentCorrection, CorrectionConfig};
 pub use pick_component::{ComponentPicking, ComponentPickingConfig};
 pub use purge_diverged::{PurgeDivConfig, PurgeDivergent};
 pub use remove_erroneous_nodes::RemoveErroneousNodes;
 pub use repeat_masking::{RepeatMask, RepeatMaskConfig};
 pub use squish_erroneous_clusters::{SquishConfig, SquishErroneousClusters};
+pub use trio_binning::{TrioBinning, TrioBinningConfig};
 
 /// Global alignment parameter.
 pub const ALN_PARAMETER: (i32, i32, i32, i32) = (2, -6, -5, -1);
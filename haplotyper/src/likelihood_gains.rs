@@ -56,6 +56,8 @@ pub struct Gains {
     subst: Vec<GainProfile>,
     deletions: Vec<GainProfile>,
     insertions: Vec<GainProfile>,
+    // The homopolymer models on the forward and the reverse strand, used on top of the pair-HMM.
+    homopolymers: [HomopolymerModel; 2],
 }
 
 impl std::fmt::Display for Gains {
@@ -83,6 +85,47 @@ impl Gains {
             DiffType::Ins => self.insertions[homop_len - 1].gain,
         }
     }
+    /// The homopolymer models to correct the likelihoods of the pair-HMM, see [`crate::homopolymer::modification_tables`].
+    pub fn homopolymers(&self) -> &[HomopolymerModel; 2] {
+        &self.homopolymers
+    }
+    /// Use the homopolymer models on the forward and the reverse strand on top of the pair-HMM.
+    /// - The expected gains of the deletions in homopolymers get the corrections of the models,
+    ///   for a read of the deleted haplotype without any error in the homopolymer.
+    ///   The insertions in the table are of a different base (see `gen_diff_haplotypes`), and are not corrected.
+    /// - The probabilities to see indels in homopolymers from the null model are raised up to the rates of the models.
+    ///
+    /// The table is extended to the longest homopolymer in the models, sharing the gains of the current longest one.
+    pub fn with_homopolymer_models(mut self, homopolymers: [HomopolymerModel; 2]) -> Self {
+        self.homopolymers = homopolymers;
+        let max_len = self.homopolymers.iter().map(|h| h.len()).max().unwrap_or(0);
+        if max_len == 0 {
+            return self;
+        }
+        while self.max_homopolymer_len < max_len {
+            self.subst.push(*self.subst.last().unwrap());
+            self.deletions.push(*self.deletions.last().unwrap());
+            self.insertions.push(*self.insertions.last().unwrap());
+            self.max_homopolymer_len += 1;
+        }
+        for len in 1..=self.max_homopolymer_len {
+            let rates = self.homopolymers.iter().filter_map(|h| h.rates(len));
+            let (ins, del) = rates.fold((0f64, 0f64), |(i, d), (x, y)| (i.max(x), d.max(y)));
+            let ins_prob = &mut self.insertions[len - 1].prob;
+            *ins_prob = ins_prob.max(ins);
+            let del_prob = &mut self.deletions[len - 1].prob;
+            *del_prob = del_prob.max(del);
+            let correction = self
+                .homopolymers
+                .iter()
+                .map(|h| h.correction(len, len - 1, -1))
+                .sum::<f64>()
+                / 2f64;
+            let del_gain = &mut self.deletions[len - 1].gain;
+            *del_gain = (*del_gain + correction).max(0f64);
+        }
+        self
+    }
     pub fn pvalues(&self, total: usize) -> Pvalues {
         let substs: Vec<_> = self
             .subst
@@ -156,6 +199,7 @@ impl Pvalues {
     }
 }
 
+use crate::homopolymer::HomopolymerModel;
 use kiley::hmm::PairHiddenMarkovModelOnStrands;
 pub fn estimate_gain(
     hmm: &PairHiddenMarkovModelOnStrands,
@@ -178,6 +222,7 @@ pub fn estimate_gain(
         subst,
         deletions,
         insertions,
+        homopolymers: Default::default(),
     }
 }
 
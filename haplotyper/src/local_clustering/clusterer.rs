@@ -0,0 +1,358 @@
+//! Strategies to cluster the reads on a chunk.
+//!
+//! All of them take the same input, i.e., the consensus, the reads with their alignments and strands,
+//! the pair-HMM, and a [`ClusteringConfig`] (copy number and coverage), and return
+//! the assignments, the posterior probabilities (in log), the score, and the number of the clusters.
+use super::pseudo_mcmc::{self, ClusteringConfig};
+use kiley::hmm::PairHiddenMarkovModelOnStrands;
+use rand::Rng;
+
+pub type ClusteringResult = (Vec<usize>, Vec<Vec<f64>>, f64, usize);
+
+// The copy number above which the MCMC is done recursively.
+pub const UPPER_COPY_NUM: usize = 8;
+// Exact enumeration is done only if the pileup is this small...
+const EXACT_MAX_COPY_NUM: usize = 3;
+const EXACT_MAX_READS: usize = 40;
+// ... and the number of the variants is up to this value. Otherwise, it falls back to the MCMC.
+const EXACT_MAX_VARIANTS: usize = 6;
+// K-means is used if the per-cluster coverage is higher than this value.
+const KMEANS_MIN_COV: f64 = 60f64;
+const KMEANS_TRIALS: usize = 5;
+
+/// The reads on a chunk, aligned to the consensus.
+#[derive(Debug, Clone, Copy)]
+pub struct Pileup<'a> {
+    pub cons: &'a [u8],
+    pub seqs: &'a [&'a [u8]],
+    pub ops: &'a [Vec<kiley::Op>],
+    pub strands: &'a [bool],
+}
+
+pub trait ChunkClusterer {
+    fn cluster<R: Rng>(
+        &self,
+        pileup: &Pileup,
+        rng: &mut R,
+        hmm: &PairHiddenMarkovModelOnStrands,
+        config: &ClusteringConfig,
+    ) -> ClusteringResult;
+}
+
+/// The clustering strategy of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusteringStrategy {
+    /// The pseudo-MCMC clustering, recursively if the copy number is large.
+    Mcmc,
+    /// Enumerate all the possible haplotypes. Falls back to `Mcmc` for large pileups.
+    Exact,
+    /// K-means on the likelihood gains of the variants.
+    Kmeans,
+    /// Choose one of the above for each chunk by its coverage and copy number.
+    Auto,
+}
+
+impl std::default::Default for ClusteringStrategy {
+    fn default() -> Self {
+        Self::Mcmc
+    }
+}
+
+impl std::str::FromStr for ClusteringStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mcmc" => Ok(Self::Mcmc),
+            "exact" => Ok(Self::Exact),
+            "kmeans" => Ok(Self::Kmeans),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!("Unknown clustering strategy:{s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for ClusteringStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Mcmc => "mcmc",
+            Self::Exact => "exact",
+            Self::Kmeans => "kmeans",
+            Self::Auto => "auto",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl ClusteringStrategy {
+    /// Resolve `Auto` into a concrete strategy for a chunk with `read_num` reads.
+    pub fn select(&self, read_num: usize, config: &ClusteringConfig) -> Self {
+        match self {
+            Self::Auto if config.copy_num >= UPPER_COPY_NUM => Self::Mcmc,
+            Self::Auto if config.copy_num <= EXACT_MAX_COPY_NUM && read_num <= EXACT_MAX_READS => {
+                Self::Exact
+            }
+            Self::Auto if KMEANS_MIN_COV <= config.local_coverage => Self::Kmeans,
+            Self::Auto => Self::Mcmc,
+            &x => x,
+        }
+    }
+}
+
+impl ChunkClusterer for ClusteringStrategy {
+    fn cluster<R: Rng>(
+        &self,
+        pileup: &Pileup,
+        rng: &mut R,
+        hmm: &PairHiddenMarkovModelOnStrands,
+        config: &ClusteringConfig,
+    ) -> ClusteringResult {
+        match self.select(pileup.seqs.len(), config) {
+            Self::Exact => ExactClusterer.cluster(pileup, rng, hmm, config),
+            Self::Kmeans => KmeansClusterer.cluster(pileup, rng, hmm, config),
+            Self::Mcmc | Self::Auto => McmcClusterer.cluster(pileup, rng, hmm, config),
+        }
+    }
+}
+
+/// The pseudo-MCMC clustering. If the copy number is more than [`UPPER_COPY_NUM`],
+/// the reads are split into a few clusters first, then each of them is clustered recursively.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct McmcClusterer;
+
+impl ChunkClusterer for McmcClusterer {
+    fn cluster<R: Rng>(
+        &self,
+        pileup: &Pileup,
+        rng: &mut R,
+        hmm: &PairHiddenMarkovModelOnStrands,
+        config: &ClusteringConfig,
+    ) -> ClusteringResult {
+        let Pileup {
+            cons,
+            seqs,
+            ops,
+            strands,
+        } = *pileup;
+        super::clustering_recursive(cons, seqs, ops, strands, rng, hmm, config)
+    }
+}
+
+/// Enumerate all the combinations of the variants for each cluster.
+/// The number of the clusters increases while the gain is larger than [`pseudo_mcmc::split_penalty`],
+/// as in the MCMC. As the enumeration already finds the optimum, the seeds of the reads cannot improve it;
+/// seeded pileups fall back to the MCMC so that the seeds are respected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExactClusterer;
+
+impl ChunkClusterer for ExactClusterer {
+    fn cluster<R: Rng>(
+        &self,
+        pileup: &Pileup,
+        rng: &mut R,
+        hmm: &PairHiddenMarkovModelOnStrands,
+        config: &ClusteringConfig,
+    ) -> ClusteringResult {
+        let Pileup {
+            cons,
+            seqs,
+            ops,
+            strands,
+        } = *pileup;
+        if config.copy_num < 2 {
+            return single_cluster(seqs.len());
+        } else if EXACT_MAX_COPY_NUM < config.copy_num {
+            return McmcClusterer.cluster(pileup, rng, hmm, config);
+        }
+        let (features, seeds) =
+            pseudo_mcmc::search_variants_and_seeds(cons, seqs, ops, strands, hmm, config);
+        let (variants, variant_type) = &features;
+        let dim = variant_type.len();
+        if dim == 0 || seqs.len() <= config.copy_num {
+            return single_cluster(seqs.len());
+        } else if EXACT_MAX_VARIANTS < dim || seeds.iter().any(|s| s.is_some()) {
+            return McmcClusterer.cluster(pileup, rng, hmm, config);
+        }
+        let (mut asn, mut lks, mut score) =
+            (vec![0; seqs.len()], vec![vec![0f64]; seqs.len()], 0f64);
+        let mut prev_columns = vec![false; dim];
+        for k in 2..=config.copy_num {
+            let (new_asn, new_lks, new_score, _) =
+                super::exact_clustering::cluster_filtered_variants_exact(&features, k);
+            let columns = used_columns(variants, k, &new_asn);
+            let columns: Vec<_> = (0..dim).map(|i| columns.iter().any(|c| c[i])).collect();
+            let penalty = pseudo_mcmc::split_penalty(config, variant_type, &prev_columns, &columns);
+            trace!("EXACT\t{k}\t{new_score:.3}\t{score:.3}\t{penalty:.3}");
+            if new_score - score < penalty {
+                break;
+            }
+            (asn, lks, score, prev_columns) = (new_asn, new_lks, new_score, columns);
+        }
+        let (asn, k) = compact(&asn, &mut lks);
+        to_posterior(&mut lks);
+        (asn, lks, score, k)
+    }
+}
+
+/// K-means (`misc::kmeans`) on the likelihood gains of the variants.
+/// The number of the clusters increases while the gain is larger than expected.
+/// If some reads are seeded, the seeds are tried as an initial assignment in addition to random ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KmeansClusterer;
+
+impl ChunkClusterer for KmeansClusterer {
+    fn cluster<R: Rng>(
+        &self,
+        pileup: &Pileup,
+        rng: &mut R,
+        hmm: &PairHiddenMarkovModelOnStrands,
+        config: &ClusteringConfig,
+    ) -> ClusteringResult {
+        let Pileup {
+            cons,
+            seqs,
+            ops,
+            strands,
+        } = *pileup;
+        if config.copy_num < 2 {
+            return single_cluster(seqs.len());
+        }
+        let ((variants, variant_type), seeds) =
+            pseudo_mcmc::search_variants_and_seeds(cons, seqs, ops, strands, hmm, config);
+        let is_seeded = seeds.iter().any(|s| s.is_some());
+        if variant_type.is_empty() || seqs.len() <= config.copy_num {
+            return single_cluster(seqs.len());
+        }
+        let expected_gain = variant_type
+            .iter()
+            .map(|&(homop, diff)| config.gains.expected(homop, diff))
+            .fold(f64::INFINITY, f64::min);
+        let penalty = expected_gain * config.local_coverage;
+        let (mut asn, mut score, mut k) = (vec![0; seqs.len()], gain_score(&variants, 1, &[]), 1);
+        for new_k in 2..=config.copy_num.min(seqs.len()) {
+            let seeded = is_seeded.then(|| {
+                let init = seeds
+                    .iter()
+                    .map(|s| match s {
+                        Some(s) => (*s).min(new_k - 1),
+                        None => rng.gen_range(0..new_k),
+                    })
+                    .collect();
+                crate::misc::kmeans_from(&variants, new_k, init)
+            });
+            let (_, new_asn) = (0..KMEANS_TRIALS)
+                .map(|_| crate::misc::kmeans(&variants, new_k, rng))
+                .chain(seeded)
+                .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap())
+                .unwrap();
+            let new_score = gain_score(&variants, new_k, &new_asn);
+            trace!("KMEANS\t{new_k}\t{new_score:.3}\t{score:.3}\t{penalty:.3}");
+            if new_score - score < penalty {
+                break;
+            }
+            (asn, score, k) = (new_asn, new_score, new_k);
+        }
+        let mut lks = read_gains(&variants, k, &asn);
+        let (asn, k) = compact(&asn, &mut lks);
+        to_posterior(&mut lks);
+        (asn, lks, score, k)
+    }
+}
+
+fn single_cluster(len: usize) -> ClusteringResult {
+    (vec![0; len], vec![vec![0f64]; len], 0f64, 1)
+}
+
+// The columns each cluster uses, i.e., the columns with positive total gain in the cluster.
+fn used_columns(variants: &[Vec<f64>], k: usize, asn: &[usize]) -> Vec<Vec<bool>> {
+    let dim = variants[0].len();
+    let mut sums = vec![vec![0f64; dim]; k];
+    for (xs, &asn) in variants.iter().zip(asn) {
+        sums[asn].iter_mut().zip(xs).for_each(|(s, x)| *s += x);
+    }
+    sums.iter()
+        .map(|sums| sums.iter().map(|&s| 0f64 < s).collect())
+        .collect()
+}
+
+// i->k->the likelihood gain of the i-th read if it were in the k-th cluster.
+fn read_gains(variants: &[Vec<f64>], k: usize, asn: &[usize]) -> Vec<Vec<f64>> {
+    let columns = used_columns(variants, k, asn);
+    variants
+        .iter()
+        .map(|xs| {
+            columns
+                .iter()
+                .map(|used| {
+                    std::iter::zip(xs, used)
+                        .filter_map(|(x, &u)| u.then_some(x))
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// The total gain of the clustering. If `asn` is empty, all the reads are in the same cluster.
+fn gain_score(variants: &[Vec<f64>], k: usize, asn: &[usize]) -> f64 {
+    let zeros = vec![0; variants.len()];
+    let asn = if asn.is_empty() { &zeros } else { asn };
+    let gains = read_gains(variants, k, asn);
+    std::iter::zip(&gains, asn).map(|(gain, &a)| gain[a]).sum()
+}
+
+// Remove empty clusters, re-index the assignments, and return the number of the clusters.
+fn compact(asn: &[usize], lks: &mut [Vec<f64>]) -> (Vec<usize>, usize) {
+    let k = lks.first().map(|xs| xs.len()).unwrap_or(0);
+    let mut is_used = vec![false; k];
+    asn.iter().for_each(|&a| is_used[a] = true);
+    let mut index = vec![0; k];
+    let mut used = 0;
+    for (i, &u) in is_used.iter().enumerate() {
+        index[i] = used;
+        used += u as usize;
+    }
+    for lk in lks.iter_mut() {
+        let mut i = 0;
+        lk.retain(|_| {
+            i += 1;
+            is_used[i - 1]
+        });
+    }
+    let asn = asn.iter().map(|&a| index[a]).collect();
+    (asn, used.max(1))
+}
+
+fn to_posterior(lks: &mut [Vec<f64>]) {
+    for xs in lks.iter_mut() {
+        let total = crate::misc::logsumexp(xs);
+        xs.iter_mut().for_each(|x| *x -= total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn compact_test() {
+        let asn = vec![0, 2, 2, 0];
+        let mut lks = vec![vec![1f64, 0f64, 2f64]; 4];
+        let (asn, k) = compact(&asn, &mut lks);
+        assert_eq!(k, 2);
+        assert_eq!(asn, vec![0, 1, 1, 0]);
+        assert!(lks.iter().all(|lk| lk == &vec![1f64, 2f64]));
+    }
+    #[test]
+    fn gain_score_test() {
+        // Two haplotypes, distinguished by the first two columns.
+        let variants = vec![
+            vec![1f64, -1f64, 0f64],
+            vec![1f64, -1f64, 0f64],
+            vec![-1f64, 1f64, 0f64],
+            vec![-1f64, 1f64, 0f64],
+        ];
+        let single = gain_score(&variants, 1, &[]);
+        let split = gain_score(&variants, 2, &[0, 0, 1, 1]);
+        assert!(single < split);
+        assert!((split - 4f64).abs() < 0.0001);
+    }
+}
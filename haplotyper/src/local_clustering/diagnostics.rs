@@ -0,0 +1,162 @@
+//! Per-chunk diagnostics of the local clustering.
+//!
+//! If enabled, each chunk is written to `{dir}/{chunk_id}.json`, containing the variants used
+//! for the clustering, the likelihood gains of each read on them, and the final assignments.
+//! `jtk inspect-chunk` prints it as a read x variant matrix.
+use serde::*;
+
+/// A variant column selected by `search_variants`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantColumn {
+    /// The position in the consensus (bp).
+    pub position: usize,
+    /// The index of the edit operation in the modification table.
+    pub edit: usize,
+    /// `S`, `I`, or `D`.
+    pub diff_type: String,
+    /// The likelihood gain of this column, used to pick variants.
+    pub gain: f64,
+}
+
+/// The result of the strand-bias check of a candidate column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrandCheck {
+    pub position: usize,
+    pub edit: usize,
+    /// If false, the column is removed as it is explained by the strand of the reads.
+    pub explainable: bool,
+}
+
+/// A step of the MCMC clustering, i.e., the trial with `k` clusters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreTrace {
+    pub k: usize,
+    pub score: f64,
+    pub expected_gain: f64,
+    pub improved_reads: usize,
+    pub accepted: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkDiagnostics {
+    pub chunk_id: u64,
+    pub copy_num: usize,
+    pub strategy: String,
+    pub consensus: String,
+    pub variants: Vec<VariantColumn>,
+    pub strand_checks: Vec<StrandCheck>,
+    /// The read IDs, in the same order as the rows of `profiles`.
+    pub read_ids: Vec<u64>,
+    pub strands: Vec<bool>,
+    /// read -> variant -> likelihood gain.
+    pub profiles: Vec<Vec<f64>>,
+    pub assignments: Vec<usize>,
+    /// read -> cluster -> posterior probability (in log).
+    pub posteriors: Vec<Vec<f64>>,
+    pub score_trace: Vec<ScoreTrace>,
+    pub score: f64,
+    pub cluster_num: usize,
+}
+
+// Gains smaller than this value are printed as `.`.
+const SIGN_THR: f64 = 0.5;
+
+impl ChunkDiagnostics {
+    pub fn new(chunk_id: u64, copy_num: usize, strategy: &str) -> Self {
+        Self {
+            chunk_id,
+            copy_num,
+            strategy: strategy.to_string(),
+            ..Default::default()
+        }
+    }
+    /// Format the reads x variants matrix. The reads are sorted by their assignments.
+    /// If `values` is false, each gain is shown as `+`, `-`, or `.`.
+    pub fn format_matrix(&self, values: bool) -> String {
+        let mut lines = vec![];
+        let header: Vec<_> = self
+            .variants
+            .iter()
+            .map(|v| format!("{}{}", v.position, v.diff_type))
+            .collect();
+        lines.push(format!(
+            "read\tstrand\tcluster\tpost\t{}",
+            header.join("\t")
+        ));
+        let mut order: Vec<_> = (0..self.assignments.len()).collect();
+        order.sort_by_key(|&i| (self.assignments[i], self.read_ids.get(i).copied()));
+        for i in order {
+            let id = self.read_ids.get(i).map(|x| x.to_string());
+            let id = id.as_deref().unwrap_or("-");
+            let strand = match self.strands.get(i) {
+                Some(true) => '+',
+                Some(false) => '-',
+                None => '?',
+            };
+            let asn = self.assignments[i];
+            let post = self.posteriors[i].get(asn).map_or(0f64, |x| x.exp());
+            let cells: Vec<_> = self.profiles.get(i).map_or(vec![], |prof| {
+                prof.iter()
+                    .map(|&x| match values {
+                        true => format!("{x:.1}"),
+                        false if SIGN_THR < x => "+".to_string(),
+                        false if x < -SIGN_THR => "-".to_string(),
+                        false => ".".to_string(),
+                    })
+                    .collect()
+            });
+            lines.push(format!(
+                "{id}\t{strand}\t{asn}\t{post:.2}\t{}",
+                cells.join("\t")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+impl std::fmt::Display for ChunkDiagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "CHUNK\t{}\t{}", self.chunk_id, self.strategy)?;
+        writeln!(f, "COPYNUM\t{}", self.copy_num)?;
+        writeln!(f, "CLUSTER\t{}\t{:.3}", self.cluster_num, self.score)?;
+        writeln!(f, "READS\t{}", self.assignments.len())?;
+        for v in self.variants.iter() {
+            let (pos, edit, diff, gain) = (v.position, v.edit, &v.diff_type, v.gain);
+            writeln!(f, "VARIANT\t{pos}\t{edit}\t{diff}\t{gain:.2}")?;
+        }
+        let removed = self.strand_checks.iter().filter(|s| !s.explainable).count();
+        let checked = self.strand_checks.len();
+        writeln!(f, "STRAND\t{checked}\t{removed}")?;
+        for t in self.score_trace.iter() {
+            let (k, score, expt, imp, acc) =
+                (t.k, t.score, t.expected_gain, t.improved_reads, t.accepted);
+            writeln!(f, "TRACE\t{k}\t{score:.3}\t{expt:.3}\t{imp}\t{acc}")?;
+        }
+        write!(f, "CONSENSUS\t{}", self.consensus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn format_matrix_test() {
+        let mut diag = ChunkDiagnostics::new(0, 2, "mcmc");
+        diag.variants = vec![VariantColumn {
+            position: 10,
+            edit: 3,
+            diff_type: "S".to_string(),
+            gain: 5f64,
+        }];
+        diag.read_ids = vec![4, 3];
+        diag.strands = vec![true, false];
+        diag.profiles = vec![vec![2f64], vec![-2f64]];
+        diag.assignments = vec![1, 0];
+        diag.posteriors = vec![vec![-10f64, 0f64], vec![0f64, -10f64]];
+        let matrix = diag.format_matrix(false);
+        let lines: Vec<_> = matrix.lines().collect();
+        assert_eq!(lines[0], "read\tstrand\tcluster\tpost\t10S");
+        assert_eq!(lines[1], "3\t-\t0\t1.00\t-");
+        assert_eq!(lines[2], "4\t+\t1\t1.00\t+");
+    }
+}
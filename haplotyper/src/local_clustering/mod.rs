@@ -9,26 +9,94 @@ mod config;
 pub use config::*;
 
 use crate::model_tune::ModelFit;
+pub mod clusterer;
+pub use clusterer::{ChunkClusterer, ClusteringStrategy};
+pub mod diagnostics;
 pub mod exact_clustering;
 pub mod normalize;
 pub mod pseudo_mcmc;
+pub mod variant_prior;
+use variant_prior::VariantPrior;
 
 pub trait LocalClustering {
     fn local_clustering(&mut self);
     fn local_clustering_selected(&mut self, selection: &HashSet<u64>);
+    /// Same as `local_clustering`, but with the given configuration.
+    fn local_clustering_with(&mut self, config: &LocalClusteringConfig);
+    /// Same as `local_clustering_selected`, but with the given configuration.
+    fn local_clustering_selected_with(
+        &mut self,
+        selection: &HashSet<u64>,
+        config: &LocalClusteringConfig,
+    );
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LocalClusteringConfig {
+    strategy: ClusteringStrategy,
+    // If some, per-chunk diagnostics are written into this directory.
+    diagnostics: Option<String>,
+    // Known variants (e.g., from a phased VCF) on the chunks.
+    variant_prior: Option<VariantPrior>,
+    // Read ID -> the parent, filled from `DataSet::parental_labels` if any.
+    read_parents: HashMap<u64, Parent>,
+    // Chunk ID -> read ID -> the initial cluster (e.g., by the global phasing). They have priority over `read_parents`.
+    read_seeds: HashMap<u64, HashMap<u64, usize>>,
+    // Read ID -> the weight, filled from `DataSet::read_error_rates`. Reads not listed weigh 1.
+    read_weights: HashMap<u64, f64>,
+    // Filled from `DataSet::ploidy`.
+    ploidy: usize,
+    // Filled from `DataSet::seed`.
+    seed: u64,
+}
+
+impl LocalClusteringConfig {
+    pub fn new(strategy: ClusteringStrategy, diagnostics: Option<&str>) -> Self {
+        Self {
+            strategy,
+            diagnostics: diagnostics.map(|x| x.to_string()),
+            variant_prior: None,
+            read_parents: HashMap::new(),
+            read_seeds: HashMap::new(),
+            read_weights: HashMap::new(),
+            ploidy: 2,
+            seed: 0,
+        }
+    }
+    /// Use the known variants as a prior of the clustering.
+    pub fn set_variant_prior(&mut self, variant_prior: VariantPrior) {
+        self.variant_prior = Some(variant_prior);
+    }
+    /// Seed the clustering of the chunks by the initial clusters of the reads (chunk ID -> read ID -> cluster),
+    /// such as [`crate::phasing::PhasingResult::read_seeds`].
+    pub fn set_read_seeds(&mut self, read_seeds: HashMap<u64, HashMap<u64, usize>>) {
+        self.read_seeds = read_seeds;
+    }
 }
 
 impl LocalClustering for DataSet {
     fn local_clustering(&mut self) {
         let selection: HashSet<_> = self.selected_chunks.iter().map(|x| x.id).collect();
-        local_clustering_selected(self, &selection);
+        local_clustering_selected(self, &selection, &LocalClusteringConfig::default());
     }
     fn local_clustering_selected(&mut self, selection: &HashSet<u64>) {
-        local_clustering_selected(self, selection)
+        local_clustering_selected(self, selection, &LocalClusteringConfig::default())
+    }
+    fn local_clustering_with(&mut self, config: &LocalClusteringConfig) {
+        let selection: HashSet<_> = self.selected_chunks.iter().map(|x| x.id).collect();
+        local_clustering_selected(self, &selection, config);
+    }
+    fn local_clustering_selected_with(
+        &mut self,
+        selection: &HashSet<u64>,
+        config: &LocalClusteringConfig,
+    ) {
+        local_clustering_selected(self, selection, config);
     }
 }
 
-type PileUp<'a> = (Vec<&'a mut Node>, &'a Chunk);
+// The nodes are paired with the ID of the read they are on.
+type PileUp<'a> = (Vec<(u64, &'a mut Node)>, &'a Chunk);
 fn pileup_nodes<'a>(ds: &'a mut DataSet, selection: &HashSet<u64>) -> HashMap<u64, PileUp<'a>> {
     let mut pileups: HashMap<u64, _> = ds
         .selected_chunks
@@ -36,14 +104,17 @@ fn pileup_nodes<'a>(ds: &'a mut DataSet, selection: &HashSet<u64>) -> HashMap<u6
         .filter(|c| selection.contains(&c.id))
         .map(|c| (c.id, (vec![], c)))
         .collect();
-    for node in ds.encoded_reads.iter_mut().flat_map(|r| r.nodes.iter_mut()) {
-        if let Some(bucket) = pileups.get_mut(&node.chunk) {
-            bucket.0.push(node);
+    for read in ds.encoded_reads.iter_mut() {
+        let id = read.id;
+        for node in read.nodes.iter_mut() {
+            if let Some(bucket) = pileups.get_mut(&node.chunk) {
+                bucket.0.push((id, node));
+            }
         }
     }
     pileups.iter_mut().for_each(|(_, nodes)| {
         let (nodes, ref_chunk) = nodes;
-        nodes.sort_by_cached_key(|node| {
+        nodes.sort_by_cached_key(|(_, node)| {
             let (_, aln, _) = node.recover(ref_chunk);
             aln.iter().filter(|&&x| x != b'|').count()
         });
@@ -52,21 +123,51 @@ fn pileup_nodes<'a>(ds: &'a mut DataSet, selection: &HashSet<u64>) -> HashMap<u6
 }
 
 /// Selection: HashSet of the chunk ID to be clustered on.
-fn local_clustering_selected(ds: &mut DataSet, selection: &HashSet<u64>) {
+fn local_clustering_selected(
+    ds: &mut DataSet,
+    selection: &HashSet<u64>,
+    config: &LocalClusteringConfig,
+) {
     crate::misc::update_coverage(ds);
     ds.update_models_on_both_strands();
     let hmm = ds.get_model_on_both_strands();
-    let gains = crate::likelihood_gains::estimate_gain_default(&hmm);
+    let homopolymers = crate::homopolymer::HomopolymerModel::on_strands(&ds.model_param);
+    let gains =
+        crate::likelihood_gains::estimate_gain_default(&hmm).with_homopolymer_models(homopolymers);
     let coverage = ds.coverage.unwrap();
     let read_type = ds.read_type;
+    if let Some(dir) = config.diagnostics.as_ref() {
+        if let Err(why) = std::fs::create_dir_all(dir) {
+            warn!("{why:?},{dir}");
+        }
+    }
+    let mut config = config.clone();
+    config.ploidy = ds.ploidy;
+    config.seed = ds.seed;
+    if !ds.parental_labels.is_empty() {
+        config.read_parents = crate::trio_binning::read_parents(ds);
+    }
+    config.read_weights = crate::estimate_error_rate::read_weights(ds);
+    let config = &config;
     let pileups = pileup_nodes(ds, selection);
     let consensus_and_clusternum: HashMap<_, _> = pileups
         .into_par_iter()
         .filter(|(_, (nodes, _))| !nodes.is_empty())
         .map(|(chunk_id, (mut nodes, ref_chunk))| {
-            let consensus_and_scores =
-                clustering_on_pileup(&mut nodes, ref_chunk, read_type, &hmm, &gains, coverage);
-            (chunk_id, consensus_and_scores)
+            let (cons, score, k, diagnostics) = clustering_on_pileup(
+                &mut nodes, ref_chunk, read_type, &hmm, &gains, coverage, config,
+            );
+            if let (Some(mut diagnostics), Some(dir)) = (diagnostics, config.diagnostics.as_ref()) {
+                diagnostics.read_ids = nodes.iter().map(|x| x.0).collect();
+                let path = format!("{dir}/{chunk_id}.json");
+                let dumped = std::fs::File::create(&path)
+                    .map(std::io::BufWriter::new)
+                    .map(|wtr| serde_json::ser::to_writer(wtr, &diagnostics));
+                if !matches!(dumped, Ok(Ok(_))) {
+                    warn!("Failed to write {path}");
+                }
+            }
+            (chunk_id, (cons, score, k))
         })
         .collect();
     debug!("LC\t{}", consensus_and_clusternum.len());
@@ -81,44 +182,107 @@ fn local_clustering_selected(ds: &mut DataSet, selection: &HashSet<u64>) {
     normalize::normalize_local_clustering(ds);
 }
 
-const UPPER_COPY_NUM: usize = 8;
+use clusterer::UPPER_COPY_NUM;
+// The nodes are paired with the ID of the read they are on.
 fn clustering_on_pileup(
-    nodes: &mut [&mut Node],
+    nodes: &mut [(u64, &mut Node)],
     ref_chunk: &Chunk,
     read_type: ReadType,
     hmm: &PairHiddenMarkovModelOnStrands,
     gains: &crate::likelihood_gains::Gains,
     coverage: f64,
-) -> (Vec<u8>, f64, usize) {
+    lc_config: &LocalClusteringConfig,
+) -> (Vec<u8>, f64, usize, Option<diagnostics::ChunkDiagnostics>) {
     use pseudo_mcmc::*;
     let refseq = ref_chunk.seq();
     let band_width = read_type.band_width(ref_chunk.seq().len());
-    let mut rng: Xoshiro256StarStar = SeedableRng::seed_from_u64(ref_chunk.id * 3490);
+    let seed = crate::misc::sub_seed(lc_config.seed, "local_clustering", ref_chunk.id);
+    let mut rng: Xoshiro256StarStar = SeedableRng::seed_from_u64(seed);
     let (seqs, mut ops): (Vec<_>, Vec<_>) = nodes
         .iter()
-        .map(|node| (node.seq(), crate::misc::ops_to_kiley(&node.cigar)))
+        .map(|(_, node)| (node.seq(), crate::misc::ops_to_kiley(&node.cigar)))
         .unzip();
     let start = std::time::Instant::now();
     let copy_num = ref_chunk.copy_num;
-    let strands: Vec<_> = nodes.iter().map(|n| n.is_forward).collect();
+    let strands: Vec<_> = nodes.iter().map(|(_, n)| n.is_forward).collect();
     let config = kiley::hmm::HMMPolishConfig::new(band_width / 2, seqs.len(), 3);
     let cons = hmm.polish_until_converge_antidiagonal(refseq, &seqs, &mut ops, &strands, &config);
     let polished = std::time::Instant::now();
-    let per_cluster_cov = match copy_num {
-        0 | 1 | 2 => seqs.len() as f64 / copy_num as f64,
-        _ => (seqs.len() as f64 / copy_num as f64).max(coverage),
+    // Up to the ploidy, the chunk is likely to be a single-copy region and the pileup tells the coverage.
+    let per_cluster_cov = match copy_num <= lc_config.ploidy.max(2) {
+        true => seqs.len() as f64 / copy_num as f64,
+        false => (seqs.len() as f64 / copy_num as f64).max(coverage),
+    };
+    let config = ClusteringConfig::new(band_width / 2, copy_num, coverage, per_cluster_cov, gains)
+        .with_ploidy(lc_config.ploidy);
+    let strategy = lc_config.strategy.select(seqs.len(), &config);
+    let diagnostics = lc_config.diagnostics.as_ref().map(|_| {
+        let diagnostics =
+            diagnostics::ChunkDiagnostics::new(ref_chunk.id, copy_num, &strategy.to_string());
+        std::cell::RefCell::new(diagnostics)
+    });
+    let config = match diagnostics.as_ref() {
+        Some(diagnostics) => config.with_diagnostics(diagnostics),
+        None => config,
     };
-    let config = ClusteringConfig::new(band_width / 2, copy_num, coverage, per_cluster_cov, gains);
-    let (asn, pss, score, k) =
-        clustering_recursive(&cons, &seqs, &ops, &strands, &mut rng, hmm, &config);
+    let known_variants = lc_config
+        .variant_prior
+        .as_ref()
+        .and_then(|prior| prior.sites.get(&ref_chunk.id))
+        .map(|sites| variant_prior::lift_over(refseq, &cons, sites))
+        .unwrap_or_default();
+    let config = config.with_known_variants(&known_variants);
+    // Given seeds are used as they are. Otherwise, maternal reads are seeded in the first cluster, paternal ones in the second.
+    let given_seeds = lc_config.read_seeds.get(&ref_chunk.id);
+    let read_seeds: Vec<_> = match given_seeds {
+        Some(seeds) => nodes.iter().map(|(id, _)| seeds.get(id).copied()).collect(),
+        None if 2 <= copy_num && !lc_config.read_parents.is_empty() => nodes
+            .iter()
+            .map(|(id, _)| match lc_config.read_parents.get(id) {
+                Some(Parent::Maternal) => Some(0),
+                Some(Parent::Paternal) => Some(1),
+                None => None,
+            })
+            .collect(),
+        None => vec![],
+    };
+    let config = config.with_read_seeds(&read_seeds);
+    let read_weights: Vec<_> = match lc_config.read_weights.is_empty() {
+        true => vec![],
+        false => nodes
+            .iter()
+            .map(|(id, _)| lc_config.read_weights.get(id).copied().unwrap_or(1f64))
+            .collect(),
+    };
+    let config = config.with_read_weights(&read_weights);
+    let pileup = clusterer::Pileup {
+        cons: &cons,
+        seqs: &seqs,
+        ops: &ops,
+        strands: &strands,
+    };
+    let (asn, pss, score, k) = strategy.cluster(&pileup, &mut rng, hmm, &config);
     update_by_clusterings(nodes, &asn, &ops, &pss);
+    if given_seeds.is_none() && !read_seeds.is_empty() {
+        log_parental_consistency(ref_chunk.id, &read_seeds, &asn, k);
+    }
     let end = std::time::Instant::now();
     let polished_time = (polished - start).as_millis();
     let elapsed = (end - start).as_millis();
     let (len, cov) = (cons.len(), nodes.len());
     let chunk_id = ref_chunk.id;
-    debug!("RECORD\t{chunk_id}\t{elapsed}\t{polished_time}\t{len}\t{score:.3}\t{cov}",);
-    (cons, score, k)
+    debug!("RECORD\t{chunk_id}\t{elapsed}\t{polished_time}\t{len}\t{score:.3}\t{cov}\t{strategy}",);
+    let diagnostics = diagnostics.map(|diagnostics| {
+        let mut diagnostics = diagnostics.into_inner();
+        diagnostics.consensus = String::from_utf8_lossy(&cons).to_string();
+        diagnostics.strands = strands;
+        diagnostics.assignments = asn;
+        diagnostics.posteriors = pss;
+        diagnostics.score = score;
+        diagnostics.cluster_num = k;
+        diagnostics
+    });
+    (cons, score, k, diagnostics)
 }
 
 type ClusteringDevResult = (Vec<usize>, Vec<Vec<f64>>, f64, usize);
@@ -140,7 +304,7 @@ fn clustering_recursive<R: rand::Rng>(
         rec_config.copy_num = BRANCH_NUM;
         let band_width = config.band_width;
         let (asn, pss, score, k) = clustering(cons, seqs, ops, strands, rng, hmm, &rec_config);
-        let copy_numbers = estim_copy_num(&asn, k, config.copy_num, config.coverage);
+        let copy_numbers = estim_copy_num(&asn, k, config.copy_num, config.coverage, config.ploidy);
         trace!("RECURSE\tCOPYNUM\t{:?}", copy_numbers);
         if k <= 1 {
             return (asn, pss, score, k);
@@ -151,10 +315,18 @@ fn clustering_recursive<R: rand::Rng>(
             .map(|(k, &cp)| {
                 let (seqs, mut ops, strands) = filter_sub_clusters(seqs, ops, strands, &asn, k);
                 let pconfig = kiley::hmm::HMMPolishConfig::new(band_width, seqs.len(), 0);
+                let parent_cons = cons;
                 let cons = hmm
                     .polish_until_converge_antidiagonal(cons, &seqs, &mut ops, &strands, &pconfig);
-                let mut config = *config;
+                // The known variants are on the parent consensus. Lift them onto the sub-cluster's one.
+                let known_variants =
+                    variant_prior::lift_over(parent_cons, &cons, config.known_variants);
+                let mut config = config.with_known_variants(&known_variants);
                 config.copy_num = cp;
+                // Only the top-level split is recorded.
+                config.diagnostics = None;
+                config.read_seeds = &[];
+                config.read_weights = &[];
                 clustering_recursive(&cons, &seqs, &ops, &strands, rng, hmm, &config)
             })
             .collect();
@@ -219,41 +391,85 @@ fn filter_sub_clusters<'a>(
     (sub_seqs, sub_ops, sub_strands)
 }
 
-fn estim_copy_num(asn: &[usize], k: usize, copy_num: usize, coverage: f64) -> Vec<usize> {
+// `coverage` is the haploid coverage, i.e., it is already divided by the ploidy.
+// As each copy of a repeat is usually present in all the haplotypes, the copy numbers
+// in multiples of the ploidy are preferred unless the other ones explain the counts better.
+fn estim_copy_num(
+    asn: &[usize],
+    k: usize,
+    copy_num: usize,
+    coverage: f64,
+    ploidy: usize,
+) -> Vec<usize> {
     assert!(k <= copy_num, "{},{}", k, copy_num);
     let mut counts = vec![0f64; k];
     for &x in asn.iter() {
         counts[x] += 1f64;
     }
-    let mut copy_numbers = vec![1; k];
-    for _ in k..copy_num {
+    let copy_numbers = distribute_copy_num(&counts, copy_num, coverage, 1);
+    let is_divisible =
+        1 < ploidy && copy_num.checked_rem(ploidy) == Some(0) && k * ploidy <= copy_num;
+    if is_divisible {
+        let by_ploidy = distribute_copy_num(&counts, copy_num, coverage, ploidy);
+        let lk = |cps: &[usize]| -> f64 {
+            std::iter::zip(cps, counts.iter())
+                .map(|(&cp, &count)| {
+                    let lambda = (cp as f64 * coverage).max(0.01);
+                    count * lambda.ln() - lambda
+                })
+                .sum()
+        };
+        if lk(&copy_numbers) <= lk(&by_ploidy) {
+            return by_ploidy;
+        }
+    }
+    copy_numbers
+}
+
+// Distribute `copy_num` copies to the clusters by `unit` copies, to the cluster deviating the most.
+fn distribute_copy_num(counts: &[f64], copy_num: usize, coverage: f64, unit: usize) -> Vec<usize> {
+    let mut copy_numbers = vec![unit; counts.len()];
+    for _ in (counts.len() * unit..copy_num).step_by(unit) {
         *copy_numbers
             .iter_mut()
             .zip(counts.iter())
             .map(|(cp, cov)| ((cov - coverage * *cp as f64).powi(2), cp))
             .max_by(|x, y| x.0.partial_cmp(&y.0).unwrap())
             .unwrap()
-            .1 += 1;
+            .1 += unit;
     }
     let sum: usize = copy_numbers.iter().sum();
     assert_eq!(sum, copy_num);
     copy_numbers
 }
 
+// Log the number of the maternal and paternal reads in each cluster.
+fn log_parental_consistency(chunk_id: u64, read_seeds: &[Option<usize>], asn: &[usize], k: usize) {
+    let mut counts = vec![[0; 2]; k];
+    for (seed, &asn) in std::iter::zip(read_seeds, asn) {
+        if let Some(parent) = seed {
+            counts[asn][*parent] += 1;
+        }
+    }
+    for (cl, [maternal, paternal]) in counts.iter().enumerate() {
+        debug!("TRIO\tCLUSTER\t{chunk_id}\t{cl}\t{maternal}\t{paternal}");
+    }
+}
+
 fn update_by_clusterings(
-    chunks: &mut [&mut Node],
+    chunks: &mut [(u64, &mut Node)],
     asn: &[usize],
     ops: &[Vec<kiley::Op>],
     pss: &[Vec<f64>],
 ) {
-    for (node, ps) in chunks.iter_mut().zip(pss) {
+    for ((_, node), ps) in chunks.iter_mut().zip(pss) {
         node.posterior.clear();
         node.posterior.extend(ps);
     }
-    for (node, &asn) in chunks.iter_mut().zip(asn) {
+    for ((_, node), &asn) in chunks.iter_mut().zip(asn) {
         node.cluster = asn as u64;
     }
-    for (node, ops) in chunks.iter_mut().zip(ops) {
+    for ((_, node), ops) in chunks.iter_mut().zip(ops) {
         node.cigar = crate::misc::kiley_op_to_ops(ops);
     }
 }
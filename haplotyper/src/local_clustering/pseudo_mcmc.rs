@@ -3,9 +3,11 @@
 const MASK_LENGTH: usize = 7;
 const MAX_HOMOP_LENGTH: usize = 2;
 const POS_THR: f64 = 0.00001;
+use super::variant_prior::KnownVariant;
 use crate::likelihood_gains::{Gains, Pvalues};
 use kiley::hmm::NUM_ROW;
 use rand::Rng;
+use std::collections::HashSet;
 
 type FeatureVector = (
     Vec<Vec<f64>>,
@@ -16,11 +18,21 @@ type ClusteringDevResult = (Vec<usize>, Vec<Vec<f64>>, f64, usize);
 #[derive(Debug, Clone, Copy)]
 pub struct ClusteringConfig<'a> {
     pub band_width: usize,
-    gains: &'a Gains,
+    pub gains: &'a Gains,
     // Coverage for haploid.
     pub coverage: f64,
     pub copy_num: usize,
     pub local_coverage: f64,
+    // The ploidy of the genome, used as the prior of the copy numbers of the sub-clusters.
+    pub ploidy: usize,
+    // If some, the intermediate results are recorded into it.
+    pub diagnostics: Option<&'a std::cell::RefCell<super::diagnostics::ChunkDiagnostics>>,
+    // Known variants on the template, preferred in the variant selection.
+    pub known_variants: &'a [KnownVariant],
+    // Initial assignments of the reads (e.g., by the parental origin). Empty if not available.
+    pub read_seeds: &'a [Option<usize>],
+    // Weights of the reads (e.g., lower for erroneous reads). Empty if all the reads weigh the same.
+    pub read_weights: &'a [f64],
 }
 
 impl<'a> ClusteringConfig<'a> {
@@ -37,10 +49,39 @@ impl<'a> ClusteringConfig<'a> {
             gains,
             copy_num,
             local_coverage,
+            ploidy: 2,
+            diagnostics: None,
+            known_variants: &[],
+            read_seeds: &[],
+            read_weights: &[],
         }
     }
+    pub fn with_ploidy(mut self, ploidy: usize) -> Self {
+        self.ploidy = ploidy;
+        self
+    }
+    pub fn with_read_seeds(mut self, read_seeds: &'a [Option<usize>]) -> Self {
+        self.read_seeds = read_seeds;
+        self
+    }
+    pub fn with_read_weights(mut self, read_weights: &'a [f64]) -> Self {
+        self.read_weights = read_weights;
+        self
+    }
+    pub fn with_known_variants(mut self, known_variants: &'a [KnownVariant]) -> Self {
+        self.known_variants = known_variants;
+        self
+    }
+    pub fn with_diagnostics(
+        mut self,
+        diagnostics: &'a std::cell::RefCell<super::diagnostics::ChunkDiagnostics>,
+    ) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
 }
 
+// The modification tables by the pair-HMM, with the homopolymer models of the gains on top of it.
 fn modification_table<T: std::borrow::Borrow<[u8]>>(
     template: &[u8],
     reads: &[T],
@@ -48,22 +89,10 @@ fn modification_table<T: std::borrow::Borrow<[u8]>>(
     strands: &[bool],
     band: usize,
     hmm: &kiley::hmm::PairHiddenMarkovModelOnStrands,
+    gains: &Gains,
 ) -> Vec<Vec<f64>> {
-    reads
-        .iter()
-        .zip(ops)
-        .zip(strands)
-        .map(|((seq, op), strand)| {
-            let hmm = match strand {
-                true => hmm.forward(),
-                false => hmm.reverse(),
-            };
-            let (mut table, lk) =
-                hmm.modification_table_antidiagonal(template, seq.borrow(), op, band);
-            table.iter_mut().for_each(|x| *x -= lk);
-            table
-        })
-        .collect()
+    let homopolymers = gains.homopolymers();
+    crate::homopolymer::modification_tables(hmm, homopolymers, template, reads, ops, strands, band)
 }
 
 fn filter_by<_T>(profiles: &[Vec<f64>], probes: &[(usize, _T)]) -> Vec<Vec<f64>> {
@@ -85,8 +114,9 @@ pub fn clustering<R: Rng, T: std::borrow::Borrow<[u8]>>(
     if config.copy_num < 2 {
         return (vec![0; reads.len()], vec![vec![0f64]; reads.len()], 0f64, 1);
     }
-    let feature_vectors = search_variants(template, reads, ops, strands, hmm, config);
-    let clustering_result = cluster_filtered_variants(&feature_vectors, config, rng);
+    let (feature_vectors, seeds) =
+        search_variants_and_seeds(template, reads, ops, strands, hmm, config);
+    let clustering_result = cluster_filtered_variants_seeded(&feature_vectors, &seeds, config, rng);
     let (mut assignments, mut likelihood_gains, max, max_k) = clustering_result;
     if log_enabled!(log::Level::Trace) {
         for (i, asn) in assignments.iter().enumerate() {
@@ -113,11 +143,68 @@ pub fn search_variants<T: std::borrow::Borrow<[u8]>>(
     hmm: &kiley::hmm::PairHiddenMarkovModelOnStrands,
     config: &ClusteringConfig,
 ) -> FeatureVector {
-    let profiles = modification_table(template, reads, ops, strands, config.band_width, hmm);
-    let profiles = compress_small_gains(profiles, template, config.gains);
+    search_variants_and_seeds(template, reads, ops, strands, hmm, config).0
+}
+
+/// Same as `search_variants`, but also returns the initial assignments of the reads,
+/// i.e., the given `read_seeds` or the ones suggested by the phased known variants.
+pub fn search_variants_and_seeds<T: std::borrow::Borrow<[u8]>>(
+    template: &[u8],
+    reads: &[T],
+    ops: &[Vec<kiley::Op>],
+    strands: &[bool],
+    hmm: &kiley::hmm::PairHiddenMarkovModelOnStrands,
+    config: &ClusteringConfig,
+) -> (FeatureVector, Vec<Option<usize>>) {
+    let band = config.band_width;
+    let profiles = modification_table(template, reads, ops, strands, band, hmm, config.gains);
+    let mut profiles = compress_small_gains(profiles, template, config.gains);
+    if config.read_weights.len() == reads.len() {
+        for (prof, &w) in std::iter::zip(profiles.iter_mut(), config.read_weights) {
+            prof.iter_mut().for_each(|x| *x *= w);
+        }
+    }
+    let seeds = {
+        let known: Vec<_> = config
+            .known_variants
+            .iter()
+            .filter_map(|v| known_column(v, template.len()).map(|col| (*v, col)))
+            .collect();
+        let (known, columns): (Vec<_>, Vec<_>) = known.into_iter().unzip();
+        let gains: Vec<Vec<f64>> = profiles
+            .iter()
+            .map(|prof| columns.iter().map(|&col| prof[col]).collect())
+            .collect();
+        let seeds = super::variant_prior::phase_seeds(&known, &gains);
+        // The given seeds have priority over the ones by the known variants.
+        match config.read_seeds.len() == reads.len() {
+            true => std::iter::zip(config.read_seeds, seeds)
+                .map(|(given, seed)| given.or(seed))
+                .collect(),
+            false => seeds,
+        }
+    };
     let probes = filter_profiles(template, &profiles, strands, config);
     let op_and_homop = operation_and_homopolymer_length(template, &probes);
     let variants = filter_by(&profiles, &probes);
+    if let Some(diagnostics) = config.diagnostics {
+        use super::diagnostics::VariantColumn;
+        let mut diagnostics = diagnostics.borrow_mut();
+        diagnostics.variants = probes
+            .iter()
+            .map(|&(pos, gain)| {
+                let (position, diff_type) = pos_to_bp_and_difftype(pos);
+                let (edit, diff_type) = (pos % NUM_ROW, diff_type.to_string());
+                VariantColumn {
+                    position,
+                    edit,
+                    diff_type,
+                    gain,
+                }
+            })
+            .collect();
+        diagnostics.profiles = variants.clone();
+    }
     if log_enabled!(log::Level::Trace) {
         for (i, (pos, lk)) in probes.iter().enumerate() {
             let sum: f64 = profiles.iter().map(|prof| prof[*pos].max(0f64)).sum();
@@ -133,7 +220,13 @@ pub fn search_variants<T: std::borrow::Borrow<[u8]>>(
             trace!("VARS\t{i}\t-1\t{}", strand as usize)
         }
     }
-    (variants, op_and_homop)
+    ((variants, op_and_homop), seeds)
+}
+
+// The column of the modification table corresponding to the known variant.
+fn known_column(variant: &KnownVariant, template_len: usize) -> Option<usize> {
+    let base = b"ACGT".iter().position(|&b| b == variant.alt)?;
+    (variant.position < template_len).then(|| variant.position * NUM_ROW + base)
 }
 
 const MIN_REQ_FRACTION: f64 = 0.5;
@@ -210,7 +303,17 @@ fn homopolymer_length(xs: &[u8]) -> Vec<usize> {
 }
 
 pub fn cluster_filtered_variants<R: Rng>(
+    features: &FeatureVector,
+    config: &ClusteringConfig,
+    rng: &mut R,
+) -> ClusteringDevResult {
+    cluster_filtered_variants_seeded(features, &[], config, rng)
+}
+
+// `seeds` are the initial assignments of the reads (None if unknown) tried in addition to random ones.
+fn cluster_filtered_variants_seeded<R: Rng>(
     (variants, variant_type): &FeatureVector,
+    seeds: &[Option<usize>],
     config: &ClusteringConfig,
     rng: &mut R,
 ) -> ClusteringDevResult {
@@ -223,7 +326,6 @@ pub fn cluster_filtered_variants<R: Rng>(
         return (asn, lk_gains, 0f64, 1);
     }
     let datasize = variants.len();
-    let per_cluster_cov = config.local_coverage;
     let (mut assignments, mut max, mut max_k, mut read_lk_gains) =
         (vec![0; datasize], 0f64, 1, vec![0f64; datasize]);
     let mut prev_used_columns = vec![false; variants[0].len()];
@@ -235,9 +337,9 @@ pub fn cluster_filtered_variants<R: Rng>(
     trace!("RANGE\t{:?}", range);
     for k in range {
         let (asn, score, new_lk_gains, used_columns) = match k == 2 {
-            false => mcmc_clustering(variants, k, coverage, rng),
+            false => mcmc_clustering(variants, k, coverage, seeds, rng),
             true => {
-                let mcmc_gains = mcmc_clustering(variants, k, coverage, rng);
+                let mcmc_gains = mcmc_clustering(variants, k, coverage, seeds, rng);
                 let highest_gain = use_highest_gain(variants);
                 if mcmc_gains.1 < highest_gain.1 {
                     highest_gain
@@ -249,11 +351,20 @@ pub fn cluster_filtered_variants<R: Rng>(
         trace!("LK\t{k}\t{score:.3}");
         let min_gain = min_gain(gains, variant_type, &used_columns);
         let improved_reads = count_improved_reads(&new_lk_gains, &read_lk_gains, min_gain);
-        let expected_gain_per_read =
-            expected_gains(gains, variant_type, &prev_used_columns, &used_columns);
-        let expected_gain = expected_gain_per_read * per_cluster_cov + 0.1;
+        let expected_gain = split_penalty(config, variant_type, &prev_used_columns, &used_columns);
         trace!("LK\t{k}\t{score:.3}\t{expected_gain:.3}\t{improved_reads}");
-        if expected_gain < score - max {
+        let accepted = expected_gain < score - max;
+        if let Some(diagnostics) = config.diagnostics {
+            let trace = super::diagnostics::ScoreTrace {
+                k,
+                score,
+                expected_gain,
+                improved_reads,
+                accepted,
+            };
+            diagnostics.borrow_mut().score_trace.push(trace);
+        }
+        if accepted {
             let mut counts = vec![0; k];
             for x in asn.iter() {
                 counts[*x] += 1;
@@ -272,6 +383,19 @@ pub fn cluster_filtered_variants<R: Rng>(
     (assignments, likelihood_gains, max, max_k)
 }
 
+/// The expected gain of a new cluster, given the columns used before and after the split.
+/// A clustering with more clusters is accepted only if its score is higher than this value.
+pub fn split_penalty(
+    config: &ClusteringConfig,
+    variant_type: &[(usize, DiffType)],
+    prev_columns: &[bool],
+    used_columns: &[bool],
+) -> f64 {
+    let expected_gain_per_read =
+        expected_gains(config.gains, variant_type, prev_columns, used_columns);
+    expected_gain_per_read * config.local_coverage + 0.1
+}
+
 fn min_gain(gains: &Gains, variant_type: &[(usize, DiffType)], used_columns: &[bool]) -> f64 {
     std::iter::zip(variant_type, used_columns)
         .filter_map(|(&(homop_len, diff_type), is_used)| match is_used {
@@ -419,6 +543,8 @@ fn get_read_lk_gains(
 // ROUND * cluster num variants would be selected.
 const ROUND: usize = 3;
 const PVALUE: f64 = 0.05;
+// Added to the score of the columns at the known variants, so that they are preferred.
+const KNOWN_SITE_BONUS: f64 = 5f64;
 // False positive rate to determine the strand bias. In other words,
 // The probability that the variant is regarded as biased even if it is not
 // is 0.05. It essentially sacrifice 5% variants under the name of the strand bias.
@@ -436,6 +562,11 @@ fn filter_profiles<T: std::borrow::Borrow<[f64]>>(
     let homopolymer_length = homopolymer_length(template);
     let total_improvement = column_sum(profiles);
     let temp_len = total_improvement.len() / NUM_ROW;
+    let known_columns: HashSet<_> = config
+        .known_variants
+        .iter()
+        .filter_map(|v| known_column(v, temp_len))
+        .collect();
     let probes: Vec<(usize, f64)> = total_improvement
         .iter()
         .enumerate()
@@ -444,21 +575,39 @@ fn filter_profiles<T: std::borrow::Borrow<[f64]>>(
             MASK_LENGTH <= pos && pos <= (temp_len - MASK_LENGTH)
         })
         .filter(|&(pos, _)| pos % NUM_ROW < 8 || pos % NUM_ROW == 8 + kiley::hmm::COPY_SIZE) // 8 -> 1 length copy = same as insertion
-        .filter(|&(pos, _)| is_in_short_homopolymer(pos, &homopolymer_length, template))
+        .filter(|&(pos, _)| {
+            known_columns.contains(&pos)
+                || is_in_short_homopolymer(pos, &homopolymer_length, template)
+        })
         .filter(|&(pos, &improve)| {
-            has_small_pvalue(pos, improve, &homopolymer_length, &pvalues, gains, temp_len)
+            known_columns.contains(&pos)
+                || has_small_pvalue(pos, improve, &homopolymer_length, &pvalues, gains, temp_len)
         })
         .filter(|&(pos, _)| {
             let lks = profiles.iter().map(|p| p.borrow()[pos]);
             let paired = lks.zip(strands.iter().copied());
-            is_explainable_by_strandedness(paired)
+            let explainable = is_explainable_by_strandedness(paired);
+            if let Some(diagnostics) = config.diagnostics {
+                let (position, edit) = (pos / NUM_ROW, pos % NUM_ROW);
+                let check = super::diagnostics::StrandCheck {
+                    position,
+                    edit,
+                    explainable,
+                };
+                diagnostics.borrow_mut().strand_checks.push(check);
+            }
+            explainable
         })
         .map(|(pos, &(maxgain, count))| {
             let max_lk = (1..cluster_num + 1)
                 .map(|k| poisson_lk(count, coverage * k as f64))
                 .max_by(|x, y| x.partial_cmp(y).unwrap())
                 .unwrap_or_else(|| panic!("{}", cluster_num));
-            let total_lk = max_lk + maxgain;
+            let bonus = match known_columns.contains(&pos) {
+                true => KNOWN_SITE_BONUS,
+                false => 0f64,
+            };
+            let total_lk = max_lk + maxgain + bonus;
             (pos, total_lk)
         })
         .filter(|&(_, gain)| 0f64 < gain)
@@ -649,11 +798,24 @@ fn mcmc_clustering<R: Rng>(
     data: &[Vec<f64>],
     k: usize,
     cov: f64,
+    seeds: &[Option<usize>],
     rng: &mut R,
 ) -> (Vec<usize>, f64, Vec<f64>, Vec<bool>) {
-    let (assignment, score) = (0..20)
-        .map(|_| {
-            let mut assignments = crate::misc::kmeans(data, k, rng).1;
+    let mut initial_assignments: Vec<_> = (0..20).map(|_| None).collect();
+    if seeds.iter().any(|x| x.is_some()) {
+        let seeded = seeds
+            .iter()
+            .map(|s| match s {
+                Some(s) => (*s).min(k - 1),
+                None => rng.gen_range(0..k),
+            })
+            .collect();
+        initial_assignments.push(Some(seeded));
+    }
+    let (assignment, score) = initial_assignments
+        .into_iter()
+        .map(|init| {
+            let mut assignments = init.unwrap_or_else(|| crate::misc::kmeans(data, k, rng).1);
             let lk = mcmc_with_filter(data, &mut assignments, k, cov, rng);
             (assignments, lk)
         })
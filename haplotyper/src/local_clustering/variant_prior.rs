@@ -0,0 +1,384 @@
+//! Known variants (e.g., a phased VCF from short reads or trios) used as a prior of the local clustering.
+//!
+//! The sites in the VCF are projected onto the chunks by aligning the chunks to the reference with minimap2.
+//! In the clustering, the projected sites are
+//! 1. preferred in the variant selection, even if they are not significant in the pileup, and
+//! 2. used to seed the initial assignments of the reads, if they are phased.
+//!
+//! Only bi-allelic SNVs are used.
+use definitions::DataSet;
+use serde::*;
+use std::collections::HashMap;
+
+/// A known variant on a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownVariant {
+    /// 0-based position in the chunk.
+    pub position: usize,
+    /// The alternative base, in the same strand as the chunk.
+    pub alt: u8,
+    /// The phase set and whether the alternative allele is on the first haplotype. None if unphased.
+    pub phase: Option<(u64, bool)>,
+}
+
+/// Chunk ID -> known variants on the chunk, sorted by the position.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VariantPrior {
+    pub sites: HashMap<u64, Vec<KnownVariant>>,
+}
+
+/// A SNV record in a VCF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VcfRecord {
+    pub chrom: String,
+    /// 0-based position.
+    pub position: usize,
+    pub ref_base: u8,
+    pub alt: u8,
+    pub phase: Option<(u64, bool)>,
+}
+
+impl VcfRecord {
+    /// Parse a line of VCF. Returns None if it is a header, not a SNV, or a homozygous site.
+    /// The genotype is taken from the first sample. If there are no samples, the site is unphased.
+    pub fn parse(line: &str) -> Option<Self> {
+        if line.starts_with('#') {
+            return None;
+        }
+        let fields: Vec<_> = line.split('\t').collect();
+        if fields.len() < 5 {
+            return None;
+        }
+        let chrom = fields[0].to_string();
+        let position = fields[1].parse::<usize>().ok()?.checked_sub(1)?;
+        let (ref_base, alt) = match (fields[3].as_bytes(), fields[4].as_bytes()) {
+            (&[r], &[a]) if b"ACGT".contains(&r.to_ascii_uppercase()) => {
+                (r.to_ascii_uppercase(), a.to_ascii_uppercase())
+            }
+            _ => return None,
+        };
+        if !b"ACGT".contains(&alt) || ref_base == alt {
+            return None;
+        }
+        let phase = match (fields.get(8), fields.get(9)) {
+            (Some(format), Some(sample)) => parse_genotype(format, sample)?,
+            _ => None,
+        };
+        Some(Self {
+            chrom,
+            position,
+            ref_base,
+            alt,
+            phase,
+        })
+    }
+}
+
+// Outer None -> homozygous or missing. Inner None -> unphased heterozygous site.
+fn parse_genotype(format: &str, sample: &str) -> Option<Option<(u64, bool)>> {
+    let keys: Vec<_> = format.split(':').collect();
+    let values: Vec<_> = sample.split(':').collect();
+    let get = |key: &str| {
+        keys.iter()
+            .position(|&k| k == key)
+            .and_then(|i| values.get(i))
+    };
+    let gt = get("GT")?;
+    let is_phased = gt.contains('|');
+    let alleles: Vec<_> = gt.split(|c| c == '|' || c == '/').collect();
+    let (hap1, hap2) = match alleles.as_slice() {
+        &[h1, h2] => (h1.parse::<u8>().ok()?, h2.parse::<u8>().ok()?),
+        _ => return None,
+    };
+    if hap1 == hap2 || 1 < hap1.max(hap2) {
+        return None;
+    }
+    if !is_phased {
+        return Some(None);
+    }
+    let phase_set = get("PS").and_then(|ps| ps.parse().ok()).unwrap_or(0);
+    Some(Some((phase_set, hap1 == 1)))
+}
+
+pub fn parse_vcf(path: &str) -> std::io::Result<Vec<VcfRecord>> {
+    use std::io::BufRead;
+    let rdr = std::fs::File::open(path).map(std::io::BufReader::new)?;
+    let mut records = vec![];
+    for line in rdr.lines() {
+        if let Some(record) = VcfRecord::parse(&line?) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+impl VariantPrior {
+    /// Project the sites in `vcf` onto the chunks of `ds`, by aligning the chunks to `reference` with minimap2.
+    pub fn from_vcf(
+        ds: &DataSet,
+        vcf: &str,
+        reference: &str,
+        threads: usize,
+    ) -> std::io::Result<Self> {
+        let records = parse_vcf(vcf)?;
+        debug!("PRIOR\tVCF\t{}", records.len());
+        let alignments = align_chunks(ds, reference, threads)?;
+        let alignments = String::from_utf8_lossy(&alignments);
+        let pafs: Vec<_> = alignments
+            .lines()
+            .filter_map(bio_utils::paf::PAF::new)
+            .collect();
+        let prior = Self::project(&records, &pafs);
+        let total: usize = prior.sites.values().map(|x| x.len()).sum();
+        debug!("PRIOR\tPROJECTED\t{}\t{total}", prior.sites.len());
+        Ok(prior)
+    }
+    /// Project the records by the alignments of the chunks (query) to the reference (target).
+    /// If a chunk has more than one alignments, the one with the most matches is used.
+    pub fn project(records: &[VcfRecord], alignments: &[bio_utils::paf::PAF]) -> Self {
+        let mut on_chrom: HashMap<_, Vec<_>> = HashMap::new();
+        for record in records.iter() {
+            on_chrom
+                .entry(record.chrom.as_str())
+                .or_default()
+                .push(record);
+        }
+        on_chrom
+            .values_mut()
+            .for_each(|rs| rs.sort_by_key(|r| r.position));
+        let mut best: HashMap<u64, &bio_utils::paf::PAF> = HashMap::new();
+        for aln in alignments.iter() {
+            let chunk_id: u64 = match aln.qname.parse() {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let slot = best.entry(chunk_id).or_insert(aln);
+            if slot.matchnum < aln.matchnum {
+                *slot = aln;
+            }
+        }
+        let mut sites = HashMap::new();
+        for (chunk_id, aln) in best {
+            let records = match on_chrom.get(aln.tname.as_str()) {
+                Some(records) => records,
+                None => continue,
+            };
+            let start = records.partition_point(|r| r.position < aln.tstart);
+            let end = records.partition_point(|r| r.position < aln.tend);
+            if start == end {
+                continue;
+            }
+            let ref_to_chunk = match aln.get_tag("cg") {
+                Some((_, cigar)) => ref_to_query(aln, cigar),
+                None => continue,
+            };
+            let mut variants: Vec<_> = records[start..end]
+                .iter()
+                .filter_map(|r| {
+                    let position = *ref_to_chunk.get(&r.position)?;
+                    let alt = match aln.relstrand {
+                        true => r.alt,
+                        false => bio_utils::revcmp(&[r.alt])[0],
+                    };
+                    let phase = r.phase;
+                    Some(KnownVariant {
+                        position,
+                        alt,
+                        phase,
+                    })
+                })
+                .collect();
+            variants.sort_by_key(|v| v.position);
+            if !variants.is_empty() {
+                sites.insert(chunk_id, variants);
+            }
+        }
+        Self { sites }
+    }
+}
+
+// Reference position -> position in the query, for the aligned (match/mismatch) columns.
+fn ref_to_query(aln: &bio_utils::paf::PAF, cigar: &str) -> HashMap<usize, usize> {
+    use bio_utils::sam::Op;
+    let mut map = HashMap::new();
+    // Position in the target, and the position in the (reverse complemented, if needed) query.
+    let (mut tpos, mut qpos) = match aln.relstrand {
+        true => (aln.tstart, aln.qstart),
+        false => (aln.tstart, aln.qlen - aln.qend),
+    };
+    for op in bio_utils::sam::parse_cigar_string(cigar) {
+        match op {
+            Op::Align(l) | Op::Match(l) | Op::Mismatch(l) => {
+                for i in 0..l {
+                    let q = match aln.relstrand {
+                        true => qpos + i,
+                        false => aln.qlen - 1 - (qpos + i),
+                    };
+                    map.insert(tpos + i, q);
+                }
+                tpos += l;
+                qpos += l;
+            }
+            Op::Insertion(l) => qpos += l,
+            Op::Deletion(l) => tpos += l,
+            _ => {}
+        }
+    }
+    map
+}
+
+/// Translate the positions of `variants` on the chunk onto `template`, the polished consensus of the chunk.
+pub fn lift_over(chunk: &[u8], template: &[u8], variants: &[KnownVariant]) -> Vec<KnownVariant> {
+    if variants.is_empty() || chunk.is_empty() || template.is_empty() {
+        return vec![];
+    }
+    let mode = edlib_sys::AlignMode::Global;
+    let task = edlib_sys::AlignTask::Alignment;
+    let alignment = edlib_sys::align(template, chunk, mode, task);
+    let ops = crate::misc::edlib_to_kiley(alignment.operations().unwrap());
+    let mut chunk_to_template = vec![None; chunk.len()];
+    let (mut cpos, mut tpos) = (0, 0);
+    for op in ops {
+        match op {
+            kiley::Op::Match | kiley::Op::Mismatch => {
+                chunk_to_template[cpos] = Some(tpos);
+                cpos += 1;
+                tpos += 1;
+            }
+            kiley::Op::Ins => tpos += 1,
+            kiley::Op::Del => cpos += 1,
+        }
+    }
+    variants
+        .iter()
+        .filter_map(|v| {
+            let position = (*chunk_to_template.get(v.position)?)?;
+            Some(KnownVariant { position, ..*v })
+        })
+        .collect()
+}
+
+/// Seed the assignments of the reads by the phased known variants.
+/// `gains` is the likelihood gain of each read at the variants, i.e., positive if the read supports the alternative allele.
+/// Only the phase set with the most variants is used. Returns None for the reads without any information.
+pub fn phase_seeds(variants: &[KnownVariant], gains: &[Vec<f64>]) -> Vec<Option<usize>> {
+    const MIN_GAIN: f64 = 0.1;
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for (ps, _) in variants.iter().filter_map(|v| v.phase) {
+        *counts.entry(ps).or_default() += 1;
+    }
+    let phase_set = counts
+        .into_iter()
+        .max_by_key(|&(ps, c)| (c, ps))
+        .map(|x| x.0);
+    gains
+        .iter()
+        .map(|gains| {
+            let vote: i64 = std::iter::zip(variants, gains)
+                .filter(|(_, g)| MIN_GAIN < g.abs() && phase_set.is_some())
+                .filter(|(v, _)| v.phase.map(|x| x.0) == phase_set)
+                .map(|(v, g)| {
+                    let on_first = v.phase.unwrap().1;
+                    match (on_first, g.is_sign_positive()) {
+                        (true, true) | (false, false) => 1,
+                        _ => -1,
+                    }
+                })
+                .sum();
+            match vote.cmp(&0) {
+                std::cmp::Ordering::Greater => Some(0),
+                std::cmp::Ordering::Less => Some(1),
+                std::cmp::Ordering::Equal => None,
+            }
+        })
+        .collect()
+}
+
+fn align_chunks(ds: &DataSet, reference: &str, threads: usize) -> std::io::Result<Vec<u8>> {
+    use std::io::{BufWriter, Write};
+    let mut dir = std::env::current_dir()?;
+    dir.push(format!("{}", rand::random::<u64>() % 100_000_000));
+    std::fs::create_dir(&dir)?;
+    let chunks = {
+        let mut path = dir.clone();
+        path.push("chunks.fa");
+        let mut wtr = std::fs::File::create(&path).map(BufWriter::new)?;
+        for chunk in ds.selected_chunks.iter() {
+            writeln!(wtr, ">{}\n{}", chunk.id, &chunk.seq)?;
+        }
+        wtr.flush()?;
+        path.into_os_string().into_string().unwrap()
+    };
+    let threads = format!("{threads}");
+    let args = vec![
+        "-t",
+        &threads,
+        "-c",
+        "--eqx",
+        "-x",
+        "asm5",
+        "--secondary=no",
+    ];
+    let paf = crate::minimap2::minimap2_args(reference, &chunks, &args);
+    std::fs::remove_dir_all(dir)?;
+    Ok(paf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_vcf_test() {
+        let line = "chr1\t101\t.\tA\tG\t50\tPASS\t.\tGT:PS\t0|1:100";
+        let record = VcfRecord::parse(line).unwrap();
+        assert_eq!(record.position, 100);
+        assert_eq!((record.ref_base, record.alt), (b'A', b'G'));
+        assert_eq!(record.phase, Some((100, false)));
+        let line = "chr1\t101\t.\tA\tG\t50\tPASS\t.\tGT\t1|0";
+        assert_eq!(VcfRecord::parse(line).unwrap().phase, Some((0, true)));
+        let line = "chr1\t101\t.\tA\tG\t50\tPASS\t.\tGT\t0/1";
+        assert_eq!(VcfRecord::parse(line).unwrap().phase, None);
+        let line = "chr1\t101\t.\tA\tG\t50\tPASS\t.";
+        assert_eq!(VcfRecord::parse(line).unwrap().phase, None);
+        assert!(VcfRecord::parse("chr1\t101\t.\tA\tG\t50\tPASS\t.\tGT\t1|1").is_none());
+        assert!(VcfRecord::parse("chr1\t101\t.\tAT\tG\t50\tPASS\t.\tGT\t0|1").is_none());
+        assert!(VcfRecord::parse("#CHROM\tPOS").is_none());
+    }
+    #[test]
+    fn lift_over_test() {
+        let chunk = b"ACGTACGTTTGCA";
+        // One insertion at the 2nd position.
+        let template = b"ACCGTACGTTTGCA";
+        let variant = KnownVariant {
+            position: 5,
+            alt: b'G',
+            phase: None,
+        };
+        let lifted = lift_over(chunk, template, &[variant]);
+        assert_eq!(lifted.len(), 1);
+        assert_eq!(lifted[0].position, 6);
+    }
+    #[test]
+    fn phase_seeds_test() {
+        let variants = vec![
+            KnownVariant {
+                position: 10,
+                alt: b'A',
+                phase: Some((1, true)),
+            },
+            KnownVariant {
+                position: 20,
+                alt: b'C',
+                phase: Some((1, false)),
+            },
+        ];
+        let gains = vec![
+            vec![2f64, -2f64],
+            vec![-2f64, 2f64],
+            vec![0f64, 0f64],
+            vec![2f64, 2f64],
+        ];
+        let seeds = phase_seeds(&variants, &gains);
+        assert_eq!(seeds, vec![Some(0), Some(1), None, None]);
+    }
+}
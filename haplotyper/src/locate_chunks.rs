@@ -0,0 +1,233 @@
+//! Locate the chunks on a reference sequence.
+//!
+//! Each chunk is aligned to the reference, and the best alignment covering most of the chunk
+//! is recorded on `Chunk::reference`. The positions are carried over to the contig summaries
+//! and the GFA, so that the contigs can be placed on the reference without external tools.
+use bio_utils::paf::PAF;
+use definitions::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct LocateChunksConfig {
+    threads: usize,
+    // Alignments with lower identity than this value are discarded.
+    min_identity: f64,
+    // Alignments covering less than this fraction of the chunk are discarded.
+    min_aligned_frac: f64,
+}
+
+impl LocateChunksConfig {
+    pub fn new(threads: usize, min_identity: f64) -> Self {
+        Self {
+            threads,
+            min_identity,
+            min_aligned_frac: 0.8,
+        }
+    }
+}
+
+pub trait LocateChunks {
+    /// Align the chunks to the `(name, sequence)`s of the reference, and record the positions.
+    /// Chunks without any proper alignment lose their positions. Return the number of the located chunks.
+    fn locate_chunks(
+        &mut self,
+        reference: &[(String, Vec<u8>)],
+        config: &LocateChunksConfig,
+    ) -> std::io::Result<usize>;
+}
+
+impl LocateChunks for DataSet {
+    fn locate_chunks(
+        &mut self,
+        reference: &[(String, Vec<u8>)],
+        config: &LocateChunksConfig,
+    ) -> std::io::Result<usize> {
+        let alignments = match self.aligner {
+            Aligner::Minimap2 => mm2_chunk_to_reference(self, reference, config)?,
+            Aligner::Builtin => builtin_chunk_to_reference(self, reference),
+        };
+        let mut best_hits: HashMap<u64, ReferencePosition> = HashMap::new();
+        for aln in alignments.iter() {
+            let chunk: u64 = match aln.qname.parse() {
+                Ok(chunk) => chunk,
+                Err(_) => continue,
+            };
+            if let Some(position) = to_position(aln, config) {
+                let is_better = best_hits
+                    .get(&chunk)
+                    .map_or(true, |prev| prev.identity < position.identity);
+                if is_better {
+                    best_hits.insert(chunk, position);
+                }
+            }
+        }
+        for chunk in self.selected_chunks.iter_mut() {
+            chunk.reference = best_hits.remove(&chunk.id);
+            if let Some(position) = chunk.reference.as_ref() {
+                trace!("LOCATE\t{}\t{position}\t{:.3}", chunk.id, position.identity);
+            }
+        }
+        let located = self
+            .selected_chunks
+            .iter()
+            .filter(|c| c.reference.is_some())
+            .count();
+        debug!("LOCATE\t{located}\t{}", self.selected_chunks.len());
+        Ok(located)
+    }
+}
+
+/// Read a FASTA file into `(name, sequence)`s.
+pub fn load_reference(path: &str) -> std::io::Result<Vec<(String, Vec<u8>)>> {
+    let reader = std::fs::File::open(path).map(std::io::BufReader::new)?;
+    let records = bio_utils::fasta::parse_into_vec_from(reader)?
+        .into_iter()
+        .map(|record| {
+            let (id, _, seq) = record.into();
+            (id, seq.into_bytes())
+        })
+        .collect();
+    Ok(records)
+}
+
+fn to_position(aln: &PAF, config: &LocateChunksConfig) -> Option<ReferencePosition> {
+    let aligned_frac = (aln.qend - aln.qstart) as f64 / aln.qlen as f64;
+    let identity = aln.matchnum as f64 / aln.blocklen.max(1) as f64;
+    (config.min_aligned_frac <= aligned_frac && config.min_identity <= identity).then(|| {
+        ReferencePosition {
+            contig: aln.tname.clone(),
+            start: aln.tstart,
+            end: aln.tend,
+            strand: aln.relstrand,
+            identity,
+        }
+    })
+}
+
+fn mm2_chunk_to_reference(
+    ds: &DataSet,
+    reference: &[(String, Vec<u8>)],
+    config: &LocateChunksConfig,
+) -> std::io::Result<Vec<PAF>> {
+    use rand::{thread_rng, Rng};
+    let id: u64 = thread_rng().gen::<u64>() % 100_000_000;
+    let mut c_dir = std::env::current_dir()?;
+    c_dir.push(format!("{}", id));
+    debug!("LOCATE\tCreating\t{:?}", c_dir);
+    std::fs::create_dir(&c_dir)?;
+    use std::io::{BufWriter, Write};
+    let (chunks, refs) = {
+        let mut chunks = c_dir.clone();
+        chunks.push("chunks.fa");
+        let mut wtr = std::fs::File::create(&chunks).map(BufWriter::new)?;
+        for chunk in ds.selected_chunks.iter() {
+            writeln!(wtr, ">{}\n{}", chunk.id, &chunk.seq)?;
+        }
+        wtr.flush()?;
+        let mut refs = c_dir.clone();
+        refs.push("reference.fa");
+        let mut wtr = std::fs::File::create(&refs).map(BufWriter::new)?;
+        for (name, seq) in reference.iter() {
+            writeln!(wtr, ">{name}\n{}", String::from_utf8_lossy(seq))?;
+        }
+        wtr.flush()?;
+        let chunks = chunks.into_os_string().into_string().unwrap();
+        let refs = refs.into_os_string().into_string().unwrap();
+        (chunks, refs)
+    };
+    let threads = format!("{}", config.threads);
+    let args = vec!["-t", &threads, "-x", "asm20", "-c", "--eqx"];
+    let mm2 = crate::minimap2::minimap2_args(&refs, &chunks, &args);
+    debug!("LOCATE\tRemoving\t{:?}", c_dir);
+    std::fs::remove_dir_all(c_dir)?;
+    let alignments = String::from_utf8_lossy(&mm2)
+        .lines()
+        .filter_map(PAF::new)
+        .collect();
+    Ok(alignments)
+}
+
+fn builtin_chunk_to_reference(ds: &DataSet, reference: &[(String, Vec<u8>)]) -> Vec<PAF> {
+    use crate::minimizer_aligner::*;
+    let targets: Vec<_> = reference
+        .iter()
+        .map(|(name, seq)| (name.as_str(), seq.as_slice()))
+        .collect();
+    let config = AlignerConfig::from_read_type(ReadType::CCS);
+    let index = MinimizerIndex::new(targets, &config);
+    let names: Vec<_> = ds
+        .selected_chunks
+        .iter()
+        .map(|c| c.id.to_string())
+        .collect();
+    let queries: Vec<_> = std::iter::zip(&names, &ds.selected_chunks)
+        .map(|(name, chunk)| (name.as_str(), chunk.seq()))
+        .collect();
+    index.map_all(&queries)
+}
+
+/// The reference position of a contig, spanning the positions of its chunks on the reference
+/// sequence where most of them are located. The strand is the majority of the chunks, and
+/// the identity is their mean.
+pub fn contig_position(
+    elements: impl IntoIterator<Item = (bool, ReferencePosition)>,
+) -> Option<ReferencePosition> {
+    let mut contigs: HashMap<_, Vec<_>> = HashMap::new();
+    for (strand, position) in elements {
+        contigs
+            .entry(position.contig.clone())
+            .or_default()
+            .push((strand, position));
+    }
+    let (contig, positions) = contigs
+        .into_iter()
+        .max_by(|(n1, p1), (n2, p2)| p1.len().cmp(&p2.len()).then(n2.cmp(n1)))?;
+    let start = positions.iter().map(|(_, p)| p.start).min()?;
+    let end = positions.iter().map(|(_, p)| p.end).max()?;
+    let forward = positions
+        .iter()
+        .filter(|(strand, p)| *strand == p.strand)
+        .count();
+    let strand = positions.len() <= 2 * forward;
+    let identity = positions.iter().map(|(_, p)| p.identity).sum::<f64>() / positions.len() as f64;
+    Some(ReferencePosition {
+        contig,
+        start,
+        end,
+        strand,
+        identity,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn pos(contig: &str, start: usize, strand: bool) -> ReferencePosition {
+        ReferencePosition {
+            contig: contig.to_string(),
+            start,
+            end: start + 2_000,
+            strand,
+            identity: 0.99,
+        }
+    }
+    #[test]
+    fn contig_position_test() {
+        let elements = vec![
+            (true, pos("chr1", 1_000, true)),
+            (true, pos("chr1", 5_000, true)),
+            (false, pos("chr1", 3_000, false)),
+            (true, pos("chr2", 0, true)),
+        ];
+        let position = contig_position(elements).unwrap();
+        assert_eq!(position.contig, "chr1");
+        assert_eq!((position.start, position.end), (1_000, 7_000));
+        assert!(position.strand);
+        let elements = vec![
+            (false, pos("chr1", 0, true)),
+            (false, pos("chr1", 10, true)),
+        ];
+        assert!(!contig_position(elements).unwrap().strand);
+        assert!(contig_position(vec![]).is_none());
+    }
+}
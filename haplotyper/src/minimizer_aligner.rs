@@ -0,0 +1,405 @@
+//! The built-in aligner, an alternative to invoking minimap2.
+//!
+//! The targets are indexed by their (w,k)-minimizers. The minimizer hits of a query are chained
+//! by dynamic programming, and each chain is extended to the ends and aligned by edlib.
+//! The alignments are reported as PAF records with the `cg:Z:` tag in the `=`/`X` form,
+//! just as `minimap2 -c --eqx -P` does. As everything is done in memory,
+//! neither the minimap2 binary nor write permission in the current directory is required.
+use bio_utils::paf::PAF;
+use definitions::ReadType;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct AlignerConfig {
+    k: usize,
+    w: usize,
+    // Minimizers occurring more than this value in the targets are ignored.
+    max_occ: usize,
+    // Chains with fewer anchors than this value are discarded.
+    min_anchors: usize,
+    // Chains with lower scores than this value are discarded.
+    min_chain_score: f64,
+    // Adjacent anchors in a chain should be closer than this value.
+    max_gap: usize,
+    // If true, a sequence is not aligned to the target with the same name (`-X` of minimap2).
+    skip_self: bool,
+}
+
+impl AlignerConfig {
+    pub fn new(k: usize, w: usize) -> Self {
+        assert!(0 < k && k < 32 && 0 < w);
+        Self {
+            k,
+            w,
+            max_occ: 500,
+            min_anchors: 3,
+            min_chain_score: 40f64,
+            max_gap: 5_000,
+            skip_self: false,
+        }
+    }
+    /// The same k-mer size as we pass to minimap2.
+    pub fn from_read_type(read_type: ReadType) -> Self {
+        let k = match read_type {
+            ReadType::CCS => 18,
+            ReadType::CLR => 15,
+            ReadType::ONT => 17,
+            ReadType::None => 15,
+        };
+        Self::new(k, 10)
+    }
+    pub fn set_skip_self(&mut self, skip_self: bool) {
+        self.skip_self = skip_self;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Minimizer {
+    hash: u64,
+    pos: usize,
+    is_forward: bool,
+}
+
+// Invertible hash function of minimap2.
+fn hash64(key: u64, mask: u64) -> u64 {
+    let mut key = (!key).wrapping_add(key << 21) & mask;
+    key ^= key >> 24;
+    key = (key.wrapping_add(key << 3)).wrapping_add(key << 8) & mask;
+    key ^= key >> 14;
+    key = (key.wrapping_add(key << 2)).wrapping_add(key << 4) & mask;
+    key ^= key >> 28;
+    key = key.wrapping_add(key << 31) & mask;
+    key
+}
+
+// The hash and the strand of the canonical k-mer starting at each position.
+// None if the k-mer contains a non-ACGT base or is its own reverse complement.
+fn canonical_kmers(seq: &[u8], k: usize) -> Vec<Option<(u64, bool)>> {
+    let mask = (1 << (2 * k)) - 1;
+    let shift = 2 * (k - 1);
+    let (mut forward, mut reverse, mut valid) = (0u64, 0u64, 0);
+    let mut kmers = Vec::with_capacity(seq.len().saturating_sub(k) + 1);
+    for (i, &base) in seq.iter().enumerate() {
+        let code = match base {
+            b'A' | b'a' => 0,
+            b'C' | b'c' => 1,
+            b'G' | b'g' => 2,
+            b'T' | b't' => 3,
+            _ => 4,
+        };
+        if code < 4 {
+            forward = ((forward << 2) | code) & mask;
+            reverse = (reverse >> 2) | ((3 ^ code) << shift);
+            valid += 1;
+        } else {
+            valid = 0;
+        }
+        if k <= i + 1 {
+            let kmer = (k <= valid && forward != reverse).then(|| match forward < reverse {
+                true => (hash64(forward, mask), true),
+                false => (hash64(reverse, mask), false),
+            });
+            kmers.push(kmer);
+        }
+    }
+    kmers
+}
+
+// The k-mers with the smallest hash in each window of `w` consecutive k-mers.
+fn minimizers(seq: &[u8], k: usize, w: usize) -> Vec<Minimizer> {
+    let kmers = canonical_kmers(seq, k);
+    let mut minimizers: Vec<Minimizer> = vec![];
+    for start in 0..kmers.len().saturating_sub(w - 1) {
+        let min = kmers[start..start + w]
+            .iter()
+            .enumerate()
+            .filter_map(|(i, kmer)| kmer.map(|(hash, is_forward)| (hash, start + i, is_forward)))
+            .min_by_key(|&(hash, pos, _)| (hash, pos));
+        if let Some((hash, pos, is_forward)) = min {
+            if minimizers.last().map(|m| m.pos) != Some(pos) {
+                minimizers.push(Minimizer {
+                    hash,
+                    pos,
+                    is_forward,
+                });
+            }
+        }
+    }
+    minimizers
+}
+
+/// The minimizer index of the target sequences.
+pub struct MinimizerIndex<'a> {
+    targets: Vec<(&'a str, &'a [u8])>,
+    // Hash -> (target index, position, strand).
+    index: HashMap<u64, Vec<(usize, usize, bool)>>,
+    config: AlignerConfig,
+}
+
+impl<'a> MinimizerIndex<'a> {
+    pub fn new(targets: Vec<(&'a str, &'a [u8])>, config: &AlignerConfig) -> Self {
+        let mut index: HashMap<_, Vec<_>> = HashMap::new();
+        for (t, (_, seq)) in targets.iter().enumerate() {
+            for m in minimizers(seq, config.k, config.w) {
+                index
+                    .entry(m.hash)
+                    .or_default()
+                    .push((t, m.pos, m.is_forward));
+            }
+        }
+        index.retain(|_, hits| hits.len() <= config.max_occ);
+        debug!("MINIMIZER\tINDEX\t{}\t{}", targets.len(), index.len());
+        Self {
+            targets,
+            index,
+            config: config.clone(),
+        }
+    }
+    /// Align each `(name, sequence)` to the targets, in parallel.
+    pub fn map_all(&self, queries: &[(&str, &[u8])]) -> Vec<PAF> {
+        queries
+            .par_iter()
+            .flat_map_iter(|&(name, seq)| self.map(name, seq))
+            .collect()
+    }
+    /// Align the query to the targets. All the chains, not only the best one, are reported.
+    pub fn map(&self, qname: &str, query: &[u8]) -> Vec<PAF> {
+        let k = self.config.k;
+        let qlen = query.len();
+        // (target, is_forward) -> (position on the query (reverse complemented if needed), position on the target)
+        let mut anchors: HashMap<(usize, bool), Vec<(usize, usize)>> = HashMap::new();
+        for m in minimizers(query, k, self.config.w) {
+            let hits = match self.index.get(&m.hash) {
+                Some(hits) => hits,
+                None => continue,
+            };
+            for &(t, tpos, t_forward) in hits {
+                if self.config.skip_self && self.targets[t].0 == qname {
+                    continue;
+                }
+                let is_forward = m.is_forward == t_forward;
+                let qpos = match is_forward {
+                    true => m.pos,
+                    false => qlen - m.pos - k,
+                };
+                anchors
+                    .entry((t, is_forward))
+                    .or_default()
+                    .push((qpos, tpos));
+            }
+        }
+        let mut anchors: Vec<_> = anchors.into_iter().collect();
+        anchors.sort_unstable_by_key(|x| x.0);
+        let revcmp = bio_utils::revcmp(query);
+        let mut alignments = vec![];
+        for ((t, is_forward), mut anchors) in anchors {
+            anchors.sort_unstable_by_key(|&(q, t)| (t, q));
+            anchors.dedup();
+            let (tname, target) = self.targets[t];
+            let oriented = match is_forward {
+                true => query,
+                false => revcmp.as_slice(),
+            };
+            for chain in chain_anchors(&anchors, &self.config) {
+                let (first, last) = (anchors[chain[0]], anchors[chain[chain.len() - 1]]);
+                let last = (last.0 + k, last.1 + k);
+                if let Some(aln) = align_chain(oriented, target, first, last) {
+                    let (qstart, qend) = match is_forward {
+                        true => (aln.qstart, aln.qend),
+                        false => (qlen - aln.qend, qlen - aln.qstart),
+                    };
+                    let strand = if is_forward { '+' } else { '-' };
+                    let line = format!(
+                        "{qname}\t{qlen}\t{qstart}\t{qend}\t{strand}\t{tname}\t{}\t{}\t{}\t{}\t{}\t255\ttp:A:P\tcg:Z:{}",
+                        target.len(),
+                        aln.tstart,
+                        aln.tend,
+                        aln.matches,
+                        aln.block_len,
+                        aln.cigar
+                    );
+                    alignments.extend(PAF::new(&line));
+                }
+            }
+        }
+        alignments
+    }
+}
+
+// Chain the anchors (the position on the query, the position on the target) sorted by the target position.
+// Return the indices of the anchors in each chain, in the ascending order.
+fn chain_anchors(anchors: &[(usize, usize)], config: &AlignerConfig) -> Vec<Vec<usize>> {
+    const LOOKBACK: usize = 50;
+    let k = config.k;
+    let mut scores = vec![k as f64; anchors.len()];
+    let mut parents = vec![None; anchors.len()];
+    for (i, &(qi, ti)) in anchors.iter().enumerate() {
+        for j in (i.saturating_sub(LOOKBACK)..i).rev() {
+            let (qj, tj) = anchors[j];
+            if qi <= qj || ti <= tj {
+                continue;
+            }
+            let (dq, dt) = (qi - qj, ti - tj);
+            if config.max_gap < dq.max(dt) {
+                continue;
+            }
+            let gap = dq.max(dt) - dq.min(dt);
+            let gap_cost = match gap {
+                0 => 0f64,
+                _ => 0.01 * (k * gap) as f64 + 0.5 * (gap as f64).log2(),
+            };
+            let score = scores[j] + dq.min(dt).min(k) as f64 - gap_cost;
+            if scores[i] < score {
+                scores[i] = score;
+                parents[i] = Some(j);
+            }
+        }
+    }
+    let mut ends: Vec<_> = (0..anchors.len()).collect();
+    ends.sort_by(|&i, &j| scores[j].partial_cmp(&scores[i]).unwrap().then(i.cmp(&j)));
+    let mut used = vec![false; anchors.len()];
+    let mut chains = vec![];
+    for end in ends {
+        if scores[end] < config.min_chain_score {
+            break;
+        }
+        let (mut chain, mut pointer, mut base_score) = (vec![], Some(end), 0f64);
+        while let Some(i) = pointer {
+            if used[i] {
+                base_score = scores[i];
+                break;
+            }
+            used[i] = true;
+            chain.push(i);
+            pointer = parents[i];
+        }
+        let score = scores[end] - base_score;
+        if config.min_anchors <= chain.len() && config.min_chain_score <= score {
+            chain.reverse();
+            chains.push(chain);
+        }
+    }
+    chains
+}
+
+#[derive(Debug, Clone)]
+struct ChainAlignment {
+    qstart: usize,
+    qend: usize,
+    tstart: usize,
+    tend: usize,
+    matches: usize,
+    block_len: usize,
+    cigar: String,
+}
+
+// Extend the chain spanning [first, last) along the diagonals until either sequence ends,
+// align the regions globally, and trim the indels at both ends.
+fn align_chain(
+    query: &[u8],
+    target: &[u8],
+    first: (usize, usize),
+    last: (usize, usize),
+) -> Option<ChainAlignment> {
+    let ext = first.0.min(first.1);
+    let (mut qstart, mut tstart) = (first.0 - ext, first.1 - ext);
+    let ext = (query.len() - last.0).min(target.len() - last.1);
+    let (mut qend, mut tend) = (last.0 + ext, last.1 + ext);
+    let mode = edlib_sys::AlignMode::Global;
+    let task = edlib_sys::AlignTask::Alignment;
+    let alignment = edlib_sys::align(&target[tstart..tend], &query[qstart..qend], mode, task);
+    // Edlib operations: match, insertion (a base only in the target), deletion (only in the query), and mismatch.
+    let mut ops: Vec<u8> = alignment
+        .operations()?
+        .iter()
+        .map(|&op| [b'=', b'D', b'I', b'X'][op as usize])
+        .collect();
+    let is_indel = |op: &&u8| **op == b'I' || **op == b'D';
+    let head_len = ops.iter().take_while(is_indel).count();
+    let tail_len = ops.iter().rev().take_while(is_indel).count();
+    if ops.len() <= head_len + tail_len {
+        return None;
+    }
+    for &op in ops.iter().take(head_len) {
+        match op {
+            b'I' => qstart += 1,
+            _ => tstart += 1,
+        }
+    }
+    for &op in ops.iter().rev().take(tail_len) {
+        match op {
+            b'I' => qend -= 1,
+            _ => tend -= 1,
+        }
+    }
+    ops.truncate(ops.len() - tail_len);
+    ops.drain(..head_len);
+    let matches = ops.iter().filter(|&&op| op == b'=').count();
+    let mut cigar = String::new();
+    let mut ops = ops.iter().peekable();
+    while let Some(&op) = ops.next() {
+        let mut len = 1;
+        while ops.peek() == Some(&&op) {
+            ops.next();
+            len += 1;
+        }
+        cigar += &format!("{len}{}", op as char);
+    }
+    let block_len = (qend - qstart).max(tend - tstart);
+    Some(ChainAlignment {
+        qstart,
+        qend,
+        tstart,
+        tend,
+        matches,
+        block_len,
+        cigar,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoroshiro128PlusPlus;
+    #[test]
+    fn minimizer_test() {
+        let seq = b"ACGTTGCATGCATCGATCGATCGTAGCTAGCTAGCTGATCGATGCTAGCTAGCTGACTGA";
+        let forward = minimizers(seq, 11, 5);
+        let reverse = minimizers(&bio_utils::revcmp(seq), 11, 5);
+        assert!(!forward.is_empty());
+        let mut fhash: Vec<_> = forward.iter().map(|m| m.hash).collect();
+        let mut rhash: Vec<_> = reverse.iter().map(|m| m.hash).collect();
+        fhash.sort_unstable();
+        rhash.sort_unstable();
+        assert_eq!(fhash, rhash);
+    }
+    #[test]
+    fn map_test() {
+        let mut rng: Xoroshiro128PlusPlus = SeedableRng::seed_from_u64(4234);
+        let chunk = kiley::gen_seq::generate_seq(&mut rng, 2_000);
+        let noisy = kiley::gen_seq::introduce_errors(&chunk, &mut rng, 10, 10, 10);
+        let flank1 = kiley::gen_seq::generate_seq(&mut rng, 1_000);
+        let flank2 = kiley::gen_seq::generate_seq(&mut rng, 1_000);
+        let read: Vec<_> = flank1
+            .iter()
+            .chain(noisy.iter())
+            .chain(flank2.iter())
+            .copied()
+            .collect();
+        let config = AlignerConfig::new(15, 10);
+        let index = MinimizerIndex::new(vec![("0", chunk.as_slice())], &config);
+        let alns = index.map("read", &read);
+        assert_eq!(alns.len(), 1);
+        let aln = &alns[0];
+        assert!(aln.relstrand);
+        assert!(aln.tstart < 25 && aln.tlen - aln.tend < 25);
+        assert!(aln.qstart.abs_diff(1_000) < 25);
+        let revcmp = bio_utils::revcmp(&read);
+        let alns = index.map("read", &revcmp);
+        assert_eq!(alns.len(), 1);
+        assert!(!alns[0].relstrand);
+        assert!(alns[0].qstart.abs_diff(1_000) < 25);
+    }
+}
@@ -235,10 +235,23 @@ pub fn kmeans<R: Rng, D: std::borrow::Borrow<[f64]>>(
     assert!(1 <= k);
     let dim = data[0].borrow().len();
     assert!(0 < dim);
-    let mut assignments = match rng.gen_bool(0.5) {
+    let assignments = match rng.gen_bool(0.5) {
         true => (0..data.len()).map(|_| rng.gen_range(0..k)).collect(),
         false => suggest_first(data, k, rng),
     };
+    kmeans_from(data, k, assignments)
+}
+
+/// Same as `kmeans`, but starting from the given `assignments` instead of random ones.
+pub fn kmeans_from<D: std::borrow::Borrow<[f64]>>(
+    data: &[D],
+    k: usize,
+    mut assignments: Vec<usize>,
+) -> (f64, Vec<usize>) {
+    assert!(1 <= k);
+    assert_eq!(data.len(), assignments.len());
+    let dim = data[0].borrow().len();
+    assert!(0 < dim);
     let (mut centers, mut counts) = (vec![vec![0f64; dim]; k], vec![0; k]);
     let mut dist = get_dist(data, &centers, &assignments);
     loop {
@@ -390,6 +403,33 @@ pub fn max_indel_node(node: &definitions::Node, mat_weight: u64, indel_weight: u
     max_indel(&node.cigar, mat_weight, indel_weight)
 }
 
+/// FNV-1a hash. Unlike `DefaultHasher`, its output is the same across releases and platforms.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Derive the seed of a sub-stream from the global seed.
+/// `stream` names the stage (e.g., "assemble") and `task` identifies the unit of work in the stage (e.g., a chunk ID).
+/// The value depends only on these arguments, not on the order or the thread the tasks are processed.
+pub fn sub_seed(seed: u64, stream: &str, task: u64) -> u64 {
+    let stream = fnv1a(stream.as_bytes());
+    [stream, task]
+        .iter()
+        .fold(seed, |acc, &x| splitmix64(acc ^ x))
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Relative difference between the coverages estimated from the chunks and the k-mer spectrum, tolerated silently.
+const MAX_COVERAGE_DISAGREEMENT: f64 = 0.25;
+/// Estimate the haploid coverage, regarding the median chunk as having `ds.ploidy` copies.
 pub fn update_coverage(ds: &mut DataSet) {
     if !ds.coverage.is_protected() {
         use std::collections::HashMap;
@@ -399,8 +439,15 @@ pub fn update_coverage(ds: &mut DataSet) {
         }
         let mut counts: Vec<_> = counts.values().copied().collect();
         counts.sort_unstable();
-        let cov = counts[counts.len() / 2] as f64 / 2f64;
+        let cov = counts[counts.len() / 2] as f64 / ds.ploidy.max(1) as f64;
         debug!("MULTP\tCOVERAGE\t{}\tHAPLOID", cov);
+        if let Some(spectrum) = ds.kmer_spectrum.as_ref() {
+            let diff = (cov - spectrum.haploid_coverage).abs() / cov.max(f64::MIN_POSITIVE);
+            if MAX_COVERAGE_DISAGREEMENT < diff {
+                let (k, kmer_cov) = (spectrum.k, spectrum.haploid_coverage);
+                warn!("The coverage from chunks ({cov:.1}) disagrees with the one from the {k}-mer spectrum ({kmer_cov:.1}).");
+            }
+        }
         ds.coverage.set(cov);
     }
 }
@@ -457,9 +504,25 @@ mod tests {
         assert_eq!(max_in, 203);
     }
     #[test]
+    fn sub_seed_test() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(sub_seed(4, "assemble", 10), sub_seed(4, "assemble", 10));
+        assert_ne!(sub_seed(4, "assemble", 10), sub_seed(4, "assemble", 11));
+        assert_ne!(sub_seed(4, "assemble", 10), sub_seed(4, "consensus", 10));
+        assert_ne!(sub_seed(4, "assemble", 10), sub_seed(5, "assemble", 10));
+    }
+    #[test]
     fn rand_index_test() {
         let pred = [0, 0, 0, 1, 1, 1];
         let answ = [0, 0, 1, 1, 2, 2];
         assert!((0.6666 - rand_index(&pred, &answ)).abs() < 0.0001);
     }
+    #[test]
+    fn kmeans_from_test() {
+        let data = vec![vec![0f64], vec![0.1], vec![10f64], vec![10.1]];
+        let (dist, asn) = kmeans_from(&data, 2, vec![0, 1, 1, 1]);
+        assert_eq!(asn, vec![0, 0, 1, 1]);
+        assert!(dist < 0.1);
+    }
 }
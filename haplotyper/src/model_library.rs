@@ -0,0 +1,129 @@
+//! Export, import, and compare the pair-HMM models.
+//! A trained model can be written by [`write_model`] and read for a new run of the same chemistry by [`read_model`],
+//! either as the starting point of the training or as a fixed model.
+use definitions::*;
+use std::io::{BufReader, BufWriter};
+
+/// Write the model into a JSON file.
+pub fn write_model(model: &HMMParamOnStrands, path: &str) -> std::io::Result<()> {
+    let wtr = std::fs::File::create(path).map(BufWriter::new)?;
+    serde_json::ser::to_writer_pretty(wtr, model).map_err(std::io::Error::from)
+}
+
+/// Read the model from a JSON file written by [`write_model`].
+pub fn read_model(path: &str) -> std::io::Result<HMMParamOnStrands> {
+    let rdr = std::fs::File::open(path).map(BufReader::new)?;
+    serde_json::de::from_reader(rdr).map_err(std::io::Error::from)
+}
+
+pub trait ImportModel {
+    /// Use the model in the following steps. If `fixed` is true, the model is never re-trained.
+    /// Otherwise, it is the starting point of the training.
+    fn import_model(&mut self, model: HMMParamOnStrands, fixed: bool);
+}
+
+impl ImportModel for DataSet {
+    fn import_model(&mut self, model: HMMParamOnStrands, fixed: bool) {
+        self.model_param = model;
+        self.model_fixed = fixed;
+    }
+}
+
+/// The parameters of two models, side by side.
+#[derive(Debug, Clone)]
+pub struct ModelComparison {
+    // (strand, kind, name, first, second)
+    rows: Vec<(&'static str, &'static str, String, f64, f64)>,
+}
+
+impl ModelComparison {
+    /// The largest absolute difference between the parameters.
+    pub fn max_diff(&self) -> f64 {
+        self.rows
+            .iter()
+            .map(|&(_, _, _, x, y)| (x - y).abs())
+            .fold(0f64, f64::max)
+    }
+}
+
+impl std::fmt::Display for ModelComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = vec![format!("SUMMARY\tMaxDiff\t{:.4}", self.max_diff())];
+        for (strand, kind, name, x, y) in self.rows.iter() {
+            let diff = y - x;
+            lines.push(format!(
+                "{kind}\t{strand}\t{name}\t{x:.4}\t{y:.4}\t{diff:.4}"
+            ));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Compare the transition and the emission tables of the two models.
+pub fn compare_models(first: &HMMParamOnStrands, second: &HMMParamOnStrands) -> ModelComparison {
+    let mut rows = vec![];
+    let strands = [
+        ("forward", &first.forward, &second.forward),
+        ("reverse", &first.reverse, &second.reverse),
+    ];
+    for (strand, x, y) in strands {
+        let transitions = [
+            ("mat_mat", x.mat_mat, y.mat_mat),
+            ("mat_ins", x.mat_ins, y.mat_ins),
+            ("mat_del", x.mat_del, y.mat_del),
+            ("ins_mat", x.ins_mat, y.ins_mat),
+            ("ins_ins", x.ins_ins, y.ins_ins),
+            ("ins_del", x.ins_del, y.ins_del),
+            ("del_mat", x.del_mat, y.del_mat),
+            ("del_ins", x.del_ins, y.del_ins),
+            ("del_del", x.del_del, y.del_del),
+        ];
+        for (name, x, y) in transitions {
+            rows.push((strand, "TRANS", name.to_string(), x, y));
+        }
+        for (i, (x, y)) in std::iter::zip(x.mat_emit, y.mat_emit).enumerate() {
+            let (rbase, qbase) = (b"ACGT"[i / 4] as char, b"ACGT"[i % 4] as char);
+            rows.push((strand, "MATEMIT", format!("{rbase}->{qbase}"), x, y));
+        }
+        for (i, (x, y)) in std::iter::zip(x.ins_emit, y.ins_emit).enumerate() {
+            rows.push((strand, "INSEMIT", format!("{i}"), x, y));
+        }
+        // Homopolymer-length-dependent rates, on the lengths both models have.
+        let (x, y) = (&x.homopolymer, &y.homopolymer);
+        for (i, (x, y)) in std::iter::zip(&x.ins, &y.ins).enumerate() {
+            rows.push((strand, "HOMOPINS", format!("{}", i + 1), *x, *y));
+        }
+        for (i, (x, y)) in std::iter::zip(&x.del, &y.del).enumerate() {
+            rows.push((strand, "HOMOPDEL", format!("{}", i + 1), *x, *y));
+        }
+    }
+    ModelComparison { rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn compare_models_test() {
+        let model = HMMParamOnStrands::default();
+        assert!(compare_models(&model, &model).max_diff() < 0.0001);
+        let mut other = model.clone();
+        other.reverse.mat_mat -= 0.1;
+        other.reverse.mat_del += 0.1;
+        assert!((compare_models(&model, &other).max_diff() - 0.1).abs() < 0.0001);
+    }
+    #[test]
+    fn write_read_model_test() {
+        let mut model = HMMParamOnStrands::default();
+        model.forward.mat_ins += 0.01;
+        model.forward.mat_mat -= 0.01;
+        let path = std::env::temp_dir().join(format!("jtk_model_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_model(&model, path).unwrap();
+        let read = read_model(path);
+        std::fs::remove_file(path).unwrap();
+        let read = read.unwrap();
+        assert!(compare_models(&model, &read).max_diff() < 0.000001);
+        assert!(read_model(path).is_err());
+    }
+}
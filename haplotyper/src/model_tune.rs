@@ -14,12 +14,22 @@ pub trait ModelFit {
 
 impl ModelFit for DataSet {
     fn fit_models_on_both_strands(&self) -> Option<PairHiddenMarkovModelOnStrands> {
-        estimate_model_parameters_on_both_strands(self)
+        match self.model_fixed {
+            true => Some(self.get_model_on_both_strands()),
+            false => estimate_model_parameters_on_both_strands(self),
+        }
     }
     fn update_models_on_both_strands(&mut self) {
+        if self.model_fixed {
+            return;
+        }
         let model = self.fit_models_on_both_strands().unwrap();
-        let forward = kiley_into_def(model.forward());
-        let reverse = kiley_into_def(model.reverse());
+        let mut forward = kiley_into_def(model.forward());
+        let mut reverse = kiley_into_def(model.reverse());
+        forward.homopolymer = estimate_homopolymer_indels_on(self, true);
+        reverse.homopolymer = estimate_homopolymer_indels_on(self, false);
+        debug!("HOMOP\tFORWARD\t{:?}", forward.homopolymer);
+        debug!("HOMOP\tREVERSE\t{:?}", reverse.homopolymer);
         self.model_param = HMMParamOnStrands { forward, reverse };
     }
     fn get_model(&self) -> PairHiddenMarkovModel {
@@ -32,6 +42,8 @@ impl ModelFit for DataSet {
     }
 }
 
+// The homopolymer-length-dependent rates are dropped, as the pair-HMM of kiley has no place for them.
+// They are applied on top of it by `crate::homopolymer::HomopolymerModel`.
 fn def_into_kiley(def: &definitions::HMMParam) -> kiley::hmm::PairHiddenMarkovModel {
     let &HMMParam {
         mat_mat,
@@ -45,6 +57,7 @@ fn def_into_kiley(def: &definitions::HMMParam) -> kiley::hmm::PairHiddenMarkovMo
         del_del,
         ref mat_emit,
         ref ins_emit,
+        ..
     } = def;
     PairHiddenMarkovModel {
         mat_mat,
@@ -87,9 +100,27 @@ fn kiley_into_def(model: &kiley::hmm::PairHiddenMarkovModel) -> definitions::HMM
         del_del,
         mat_emit: *mat_emit,
         ins_emit: *ins_emit,
+        homopolymer: HomopolymerIndel::default(),
     }
 }
 
+// Homopolymer-length-dependent indel rates of the reads on the given strand.
+fn estimate_homopolymer_indels_on(ds: &DataSet, is_forward: bool) -> HomopolymerIndel {
+    use crate::homopolymer::*;
+    let chunks: HashMap<_, _> = ds.selected_chunks.iter().map(|c| (c.id, c)).collect();
+    let changes = ds
+        .encoded_reads
+        .iter()
+        .flat_map(|r| r.nodes.iter())
+        .filter(|n| n.is_forward == is_forward)
+        .filter_map(|n| chunks.get(&n.chunk).map(|c| (n, c)))
+        .flat_map(|(node, chunk)| {
+            let ops = crate::misc::ops_to_kiley(&node.cigar);
+            run_length_changes(chunk.seq(), node.seq(), &ops)
+        });
+    estimate_homopolymer_indels(changes)
+}
+
 const TRAIN_UNIT_SIZE: usize = 5;
 const TRAIN_ROUND: usize = 10;
 fn estimate_model_parameters_on_both_strands(
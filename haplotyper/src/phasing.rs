@@ -0,0 +1,615 @@
+//! Global read-based phasing across chunks.
+//!
+//! Each chunk whose copy number is the ploidy and which is split into two or more clusters is a heterozygous "site",
+//! and each read is a row of a site x read matrix, whose entries are the cluster of the read on the site,
+//! weighted by the confidence of the assignment (`Node::posterior`). The sites linked by enough reads form a phase block,
+//! and the haplotypes of each block are the solution of the weighted minimum error correction (MEC) problem:
+//! 1. The dosage of each cluster, i.e., the number of the haplotypes carrying it, is estimated from its coverage.
+//! 2. The sites are added to the haplotypes one by one, in the breadth-first order from the first site,
+//!    so that the reads spanning the site and the phased ones agree with the haplotypes the most.
+//! 3. Then, the reads are assigned to the haplotypes and the haplotypes are updated by the weighted majority
+//!    of the assigned reads, under the dosages, until it converges.
+//!
+//! The confidence of a block is one minus the weighted MEC score divided by the total weight. The sites
+//! whose clusters often contradict the haplotypes are flagged for re-clustering, where the reads are
+//! seeded by the clusters on their haplotypes ([`PhasingResult::read_seeds`]).
+//! The phased nodes are kept in [`DataSet::phased_nodes`], and the assembly tags the contigs by them.
+use definitions::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PhasingConfig {
+    // Two sites are linked if this many reads span both of them.
+    min_span_reads: usize,
+    // Sites with consistency less than this value are flagged.
+    min_consistency: f64,
+}
+
+impl std::default::Default for PhasingConfig {
+    fn default() -> Self {
+        Self {
+            min_span_reads: 3,
+            min_consistency: 0.7,
+        }
+    }
+}
+
+impl PhasingConfig {
+    pub fn new(min_span_reads: usize, min_consistency: f64) -> Self {
+        Self {
+            min_span_reads,
+            min_consistency,
+        }
+    }
+}
+
+/// The phase of a site (a chunk with two or more clusters).
+#[derive(Debug, Clone)]
+pub struct SitePhase {
+    pub chunk: u64,
+    pub block: usize,
+    /// The cluster on each haplotype of the block. A cluster appears as many times as its dosage.
+    pub haplotypes: Vec<u64>,
+    /// The weighted fraction of the reads on this site agreeing with the haplotypes.
+    pub consistency: f64,
+    pub reads: usize,
+    /// If true, the clustering of this chunk contradicts the global solution.
+    pub is_flagged: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhaseBlock {
+    pub id: usize,
+    pub chunks: Vec<u64>,
+    pub reads: usize,
+    /// 1 - (weighted MEC score)/(total weight).
+    pub confidence: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PhasingResult {
+    pub blocks: Vec<PhaseBlock>,
+    pub sites: Vec<SitePhase>,
+    /// Read ID -> (block, haplotype(1-origin)).
+    pub reads: HashMap<u64, (usize, usize)>,
+}
+
+impl PhasingResult {
+    /// The chunks to be re-clustered.
+    pub fn flagged_chunks(&self) -> HashSet<u64> {
+        self.sites
+            .iter()
+            .filter(|s| s.is_flagged)
+            .map(|s| s.chunk)
+            .collect()
+    }
+    /// The phased nodes with the haplotypes carrying them, to be stored in [`DataSet::phased_nodes`].
+    pub fn phased_nodes(&self) -> Vec<PhasedNode> {
+        let mut nodes: HashMap<_, (usize, Vec<usize>)> = HashMap::new();
+        for site in self.sites.iter() {
+            for (hap, &cluster) in site.haplotypes.iter().enumerate() {
+                let key = (site.chunk, cluster);
+                let (_, haps) = nodes.entry(key).or_insert_with(|| (site.block, vec![]));
+                haps.push(hap + 1);
+            }
+        }
+        let mut nodes: Vec<_> = nodes
+            .into_iter()
+            .map(|((chunk, cluster), (block, haplotypes))| PhasedNode {
+                chunk,
+                cluster,
+                block,
+                haplotypes,
+            })
+            .collect();
+        nodes.sort_by_key(|n| (n.chunk, n.cluster));
+        nodes
+    }
+    /// Chunk ID -> read ID -> the cluster on the haplotype the read is phased to, for the flagged chunks.
+    /// They are the initial assignments to re-cluster the flagged chunks (See [`LocalClusteringConfig::set_read_seeds`]).
+    ///
+    /// [`LocalClusteringConfig::set_read_seeds`]: crate::local_clustering::LocalClusteringConfig::set_read_seeds
+    pub fn read_seeds(&self) -> HashMap<u64, HashMap<u64, usize>> {
+        self.sites
+            .iter()
+            .filter(|site| site.is_flagged)
+            .map(|site| {
+                let seeds = self
+                    .reads
+                    .iter()
+                    .filter(|&(_, &(block, _))| block == site.block)
+                    .map(|(&id, &(_, hap))| (id, site.haplotypes[hap - 1] as usize))
+                    .collect();
+                (site.chunk, seeds)
+            })
+            .collect()
+    }
+    /// Tab-separated phase blocks, with a header.
+    pub fn blocks_tsv(&self) -> String {
+        let mut lines = vec!["block\tsites\treads\tconfidence\tchunks".to_string()];
+        for block in self.blocks.iter() {
+            let chunks: Vec<_> = block.chunks.iter().map(|c| c.to_string()).collect();
+            lines.push(format!(
+                "{}\t{}\t{}\t{:.3}\t{}",
+                block.id,
+                block.chunks.len(),
+                block.reads,
+                block.confidence,
+                chunks.join(",")
+            ));
+        }
+        lines.join("\n")
+    }
+    /// Tab-separated phases of the sites, with a header.
+    /// The `haplotypes` column lists the cluster on each haplotype.
+    pub fn sites_tsv(&self) -> String {
+        let mut lines = vec!["chunk\tblock\thaplotypes\treads\tconsistency\tflagged".to_string()];
+        for site in self.sites.iter() {
+            let haplotypes: Vec<_> = site.haplotypes.iter().map(|c| c.to_string()).collect();
+            lines.push(format!(
+                "{}\t{}\t{}\t{}\t{:.3}\t{}",
+                site.chunk,
+                site.block,
+                haplotypes.join(","),
+                site.reads,
+                site.consistency,
+                site.is_flagged
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+pub trait Phasing {
+    /// Phase the clusters of the chunks by the reads, into `DataSet::ploidy` haplotypes.
+    fn phase(&self, config: &PhasingConfig) -> PhasingResult;
+}
+
+impl Phasing for DataSet {
+    fn phase(&self, config: &PhasingConfig) -> PhasingResult {
+        let ploidy = self.ploidy;
+        let mut counts: HashMap<_, usize> = HashMap::new();
+        for node in self.encoded_reads.iter().flat_map(|r| r.nodes.iter()) {
+            *counts.entry((node.chunk, node.cluster)).or_default() += 1;
+        }
+        let (sites, dosages): (Vec<u64>, Vec<_>) = self
+            .selected_chunks
+            .iter()
+            .filter(|c| c.copy_num == ploidy && 2 <= c.cluster_num && c.cluster_num <= ploidy)
+            .map(|c| {
+                let counts: Vec<_> = (0..c.cluster_num as u64)
+                    .map(|cl| counts.get(&(c.id, cl)).copied().unwrap_or(0))
+                    .collect();
+                (c.id, dosage(&counts, ploidy))
+            })
+            .unzip();
+        let site_index: HashMap<_, _> = sites.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+        let rows: Vec<_> = self
+            .encoded_reads
+            .iter()
+            .map(|read| {
+                let entries: Vec<_> = read
+                    .nodes
+                    .iter()
+                    .filter_map(|n| {
+                        let &s = site_index.get(&n.chunk)?;
+                        let is_valid = (n.cluster as usize) < dosages[s].len();
+                        is_valid.then(|| (s, n.cluster, weight(n)))
+                    })
+                    .collect();
+                (read.id, entries)
+            })
+            .filter(|(_, entries)| 1 < entries.len())
+            .collect();
+        debug!("PHASING\tSITES\t{}\t{}\t{ploidy}", sites.len(), rows.len());
+        let result = phase_matrix(&dosages, &rows, config);
+        let result = PhasingResult {
+            sites: result
+                .sites
+                .into_iter()
+                .map(|mut site| {
+                    site.chunk = sites[site.chunk as usize];
+                    site
+                })
+                .collect(),
+            blocks: result
+                .blocks
+                .into_iter()
+                .map(|mut block| {
+                    block.chunks = block.chunks.iter().map(|&s| sites[s as usize]).collect();
+                    block
+                })
+                .collect(),
+            reads: result.reads,
+        };
+        for block in result.blocks.iter() {
+            let (id, len, reads, conf) =
+                (block.id, block.chunks.len(), block.reads, block.confidence);
+            debug!("PHASING\tBLOCK\t{id}\t{len}\t{reads}\t{conf:.3}");
+        }
+        let flagged = result.sites.iter().filter(|s| s.is_flagged).count();
+        debug!("PHASING\tFLAGGED\t{flagged}\t{}", result.sites.len());
+        result
+    }
+}
+
+// The confidence of the assignment of a node, i.e., the difference between the largest and
+// the second largest posterior probabilities.
+fn weight(node: &Node) -> f64 {
+    if node.posterior.len() < 2 {
+        return 1f64;
+    }
+    let mut probs: Vec<_> = node.posterior.iter().map(|p| p.exp()).collect();
+    probs.sort_by(|x, y| y.partial_cmp(x).unwrap());
+    (probs[0] - probs[1]).max(MIN_WEIGHT)
+}
+
+// The number of the haplotypes carrying each cluster. Each cluster has at least one haplotype,
+// and the rest are distributed in proportion to the counts (D'Hondt method).
+fn dosage(counts: &[usize], ploidy: usize) -> Vec<usize> {
+    let mut dosage = vec![1; counts.len()];
+    for _ in counts.len()..ploidy {
+        let (max, _) = counts
+            .iter()
+            .zip(dosage.iter())
+            .enumerate()
+            .map(|(i, (&count, &d))| (i, count as f64 / (d + 1) as f64))
+            .fold((0, -1f64), |max, x| if max.1 < x.1 { x } else { max });
+        dosage[max] += 1;
+    }
+    dosage
+}
+
+// All the distinct assignments of the clusters to the haplotypes, with the given dosages.
+fn arrangements(dosage: &[usize]) -> Vec<Vec<u64>> {
+    fn fill(rest: &mut [usize], current: &mut Vec<u64>, len: usize, result: &mut Vec<Vec<u64>>) {
+        if current.len() == len {
+            result.push(current.clone());
+            return;
+        }
+        for cl in 0..rest.len() {
+            if 0 < rest[cl] {
+                rest[cl] -= 1;
+                current.push(cl as u64);
+                fill(rest, current, len, result);
+                current.pop();
+                rest[cl] += 1;
+            }
+        }
+    }
+    let len = dosage.iter().sum();
+    let mut result = vec![];
+    fill(&mut dosage.to_vec(), &mut vec![], len, &mut result);
+    result
+}
+
+const MIN_WEIGHT: f64 = 0.01;
+const MAX_EM_ROUND: usize = 20;
+
+// A row: read ID and (site, cluster, weight).
+type Row = (u64, Vec<(usize, u64, f64)>);
+
+// Phase the site x read matrix. `dosages[i]` is the dosage of the clusters on the i-th site.
+// The `chunk` of the sites and the blocks are the indices of the sites.
+fn phase_matrix(dosages: &[Vec<usize>], rows: &[Row], config: &PhasingConfig) -> PhasingResult {
+    let site_num = dosages.len();
+    // The number of the reads spanning each pair of the sites.
+    let mut links: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut rows_of_site = vec![vec![]; site_num];
+    for (r, (_, entries)) in rows.iter().enumerate() {
+        for (k, &(i, _, _)) in entries.iter().enumerate() {
+            rows_of_site[i].push(r);
+            for &(j, _, _) in entries.iter().skip(k + 1).filter(|x| x.0 != i) {
+                *links.entry((i.min(j), i.max(j))).or_default() += 1;
+            }
+        }
+    }
+    rows_of_site.iter_mut().for_each(|rs| rs.dedup());
+    let mut fu = crate::find_union::FindUnion::new(site_num);
+    let mut neighbors = vec![vec![]; site_num];
+    for (&(i, j), &count) in links.iter() {
+        if config.min_span_reads <= count {
+            fu.unite(i, j);
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+        }
+    }
+    neighbors.iter_mut().for_each(|ns| ns.sort_unstable());
+    let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..site_num {
+        components.entry(fu.find(i).unwrap()).or_default().push(i);
+    }
+    let mut components: Vec<_> = components.into_values().filter(|c| 1 < c.len()).collect();
+    components.sort();
+    let choices: Vec<_> = dosages.iter().map(|d| arrangements(d)).collect();
+    let mut block_of = vec![None; site_num];
+    let mut alleles: Vec<Vec<u64>> = vec![vec![]; site_num];
+    for (b, component) in components.iter().enumerate() {
+        component.iter().for_each(|&i| block_of[i] = Some(b));
+        // Breadth-first order from the first site.
+        let mut queue = VecDeque::from(vec![component[0]]);
+        let mut is_arrived: HashSet<_> = vec![component[0]].into_iter().collect();
+        while let Some(i) = queue.pop_front() {
+            let rows_of_site = &rows_of_site[i];
+            let scores =
+                hap_scores_by_affinity(i, &dosages[i], rows_of_site, rows, &alleles, &block_of);
+            alleles[i] = best_arrangement(&choices[i], &scores, None);
+            for &j in neighbors[i].iter() {
+                if is_arrived.insert(j) {
+                    queue.push_back(j);
+                }
+            }
+        }
+    }
+    // Refine by the weighted MEC.
+    let mut read_haps = vec![None; rows.len()];
+    for _ in 0..MAX_EM_ROUND {
+        for (hap, (_, entries)) in read_haps.iter_mut().zip(rows.iter()) {
+            *hap = assign_read(entries, &alleles, &block_of);
+        }
+        let mut changed = false;
+        for i in (0..site_num).filter(|&i| block_of[i].is_some()) {
+            let ploidy = alleles[i].len();
+            let mut scores = vec![vec![0f64; dosages[i].len()]; ploidy];
+            for &r in rows_of_site[i].iter() {
+                if let Some((_, hap)) = read_haps[r] {
+                    for &(_, cl, w) in rows[r].1.iter().filter(|e| e.0 == i) {
+                        scores[hap][cl as usize] += w;
+                    }
+                }
+            }
+            let updated = best_arrangement(&choices[i], &scores, Some(&alleles[i]));
+            if updated != alleles[i] {
+                alleles[i] = updated;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    // Summarize.
+    let mut site_stats = vec![(0f64, 0f64, 0); site_num];
+    let mut block_stats = vec![(0f64, 0f64, 0); components.len()];
+    let mut reads = HashMap::new();
+    for (hap, (id, entries)) in read_haps.iter().zip(rows.iter()) {
+        if let &Some((block, hap)) = hap {
+            reads.insert(*id, (block, hap + 1));
+            block_stats[block].2 += 1;
+            for &(i, cl, w) in entries.iter().filter(|e| block_of[e.0] == Some(block)) {
+                let err = if alleles[i][hap] == cl { 0f64 } else { w };
+                site_stats[i] = (
+                    site_stats[i].0 + err,
+                    site_stats[i].1 + w,
+                    site_stats[i].2 + 1,
+                );
+                block_stats[block].0 += err;
+                block_stats[block].1 += w;
+            }
+        }
+    }
+    let sites = (0..site_num)
+        .filter_map(|i| {
+            let block = block_of[i]?;
+            let (err, total, reads) = site_stats[i];
+            let consistency = 1f64 - err / total.max(MIN_WEIGHT);
+            Some(SitePhase {
+                chunk: i as u64,
+                block,
+                haplotypes: alleles[i].clone(),
+                consistency,
+                reads,
+                is_flagged: config.min_span_reads <= reads && consistency < config.min_consistency,
+            })
+        })
+        .collect();
+    let blocks = components
+        .iter()
+        .zip(block_stats)
+        .enumerate()
+        .map(|(id, (sites, (err, total, reads)))| PhaseBlock {
+            id,
+            chunks: sites.iter().map(|&i| i as u64).collect(),
+            reads,
+            confidence: 1f64 - err / total.max(MIN_WEIGHT),
+        })
+        .collect();
+    PhasingResult {
+        blocks,
+        sites,
+        reads,
+    }
+}
+
+// hap x cluster -> score of putting the cluster on the haplotype, by the reads spanning the i-th site and
+// the already phased sites in the same block. The sign of the score tells whether the reads agree with the haplotype.
+fn hap_scores_by_affinity(
+    i: usize,
+    dosage: &[usize],
+    row_indices: &[usize],
+    rows: &[Row],
+    alleles: &[Vec<u64>],
+    block_of: &[Option<usize>],
+) -> Vec<Vec<f64>> {
+    let (ploidy, cluster_num) = (dosage.iter().sum(), dosage.len());
+    let mut scores = vec![vec![0f64; cluster_num]; ploidy];
+    for &r in row_indices.iter() {
+        let entries = &rows[r].1;
+        let mut affinity = vec![0f64; ploidy];
+        for &(t, cl, w) in entries.iter() {
+            if t == i || alleles[t].is_empty() || block_of[t] != block_of[i] {
+                continue;
+            }
+            for (aff, &allele) in affinity.iter_mut().zip(alleles[t].iter()) {
+                *aff += if allele == cl { w } else { -w };
+            }
+        }
+        for &(_, cl, w) in entries.iter().filter(|e| e.0 == i) {
+            for (scores, aff) in scores.iter_mut().zip(affinity.iter()) {
+                scores[cl as usize] += w * aff;
+            }
+        }
+    }
+    scores
+}
+
+// The arrangement maximizing the total score. If `current` is given, it is kept unless others are strictly better.
+fn best_arrangement(
+    choices: &[Vec<u64>],
+    scores: &[Vec<f64>],
+    current: Option<&[u64]>,
+) -> Vec<u64> {
+    let score_of = |arrangement: &[u64]| -> f64 {
+        std::iter::zip(arrangement, scores)
+            .map(|(&cl, scores)| scores.get(cl as usize).copied().unwrap_or(0f64))
+            .sum()
+    };
+    let mut best = match current {
+        Some(current) => (current.to_vec(), score_of(current)),
+        None => (choices[0].clone(), score_of(&choices[0])),
+    };
+    for choice in choices.iter() {
+        let score = score_of(choice);
+        if best.1 < score {
+            best = (choice.clone(), score);
+        }
+    }
+    best.0
+}
+
+// Assign a read to the haplotype with the smallest MEC cost, in the block where the read has the most weight.
+// Returns (block, haplotype(0-origin)), or None if the best haplotype is not unique.
+fn assign_read(
+    entries: &[(usize, u64, f64)],
+    alleles: &[Vec<u64>],
+    block_of: &[Option<usize>],
+) -> Option<(usize, usize)> {
+    let mut weights: HashMap<usize, f64> = HashMap::new();
+    for &(i, _, w) in entries.iter() {
+        if let Some(b) = block_of[i] {
+            *weights.entry(b).or_default() += w;
+        }
+    }
+    let (block, _) = weights
+        .into_iter()
+        .max_by(|x, y| (x.1, y.0).partial_cmp(&(y.1, x.0)).unwrap())?;
+    let entries: Vec<_> = entries
+        .iter()
+        .filter(|e| block_of[e.0] == Some(block))
+        .collect();
+    let ploidy = alleles[entries[0].0].len();
+    let scores: Vec<f64> = (0..ploidy)
+        .map(|hap| {
+            entries
+                .iter()
+                .map(|&&(i, cl, w)| if alleles[i][hap] == cl { w } else { -w })
+                .sum()
+        })
+        .collect();
+    let (best, max) =
+        scores.iter().enumerate().fold(
+            (0, f64::MIN),
+            |max, (h, &s)| if max.1 < s { (h, s) } else { max },
+        );
+    let is_unique = scores.iter().filter(|&&s| s == max).count() == 1;
+    is_unique.then_some((block, best))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn phase_matrix_test() {
+        // Three sites. Site 1 is phased as (cluster 1 on the first haplotype) by the reads.
+        // The last read has an error on site 2.
+        let hap1 = vec![(0, 0, 1f64), (1, 1, 1f64), (2, 0, 1f64)];
+        let hap2 = vec![(0, 1, 1f64), (1, 0, 1f64), (2, 1, 1f64)];
+        let mut rows: Vec<Row> = (0..4).map(|i| (i, hap1.clone())).collect();
+        rows.extend((4..8).map(|i| (i, hap2.clone())));
+        rows.push((8, vec![(0, 0, 1f64), (1, 1, 1f64), (2, 1, 1f64)]));
+        let dosages = vec![vec![1, 1]; 3];
+        let result = phase_matrix(&dosages, &rows, &PhasingConfig::default());
+        assert_eq!(result.blocks.len(), 1);
+        assert_eq!(result.blocks[0].chunks, vec![0, 1, 2]);
+        assert!(0.9 < result.blocks[0].confidence && result.blocks[0].confidence < 1f64);
+        let site = |i: u64| result.sites.iter().find(|s| s.chunk == i).unwrap();
+        assert_ne!(site(0).haplotypes[0], site(1).haplotypes[0]);
+        assert_eq!(site(0).haplotypes[0], site(2).haplotypes[0]);
+        assert_eq!(result.reads[&0].1, result.reads[&8].1);
+        assert_ne!(result.reads[&0].1, result.reads[&4].1);
+        assert!(result.flagged_chunks().is_empty());
+    }
+    #[test]
+    fn flag_test() {
+        // Site 2 is clustered randomly.
+        let mut rows: Vec<Row> = vec![];
+        for i in 0..10 {
+            let hap = i % 2;
+            let noise = (i / 2) % 2;
+            rows.push((i, vec![(0, hap, 1f64), (1, hap, 1f64), (2, noise, 1f64)]));
+        }
+        let dosages = vec![vec![1, 1]; 3];
+        let result = phase_matrix(&dosages, &rows, &PhasingConfig::default());
+        assert_eq!(result.flagged_chunks(), vec![2].into_iter().collect());
+        // The reads on the flagged site are seeded by their haplotypes, not by their current clusters.
+        let seeds = result.read_seeds();
+        assert_eq!(seeds.len(), 1);
+        let seeds = &seeds[&2];
+        for (id, entries) in rows.iter() {
+            let same_hap = rows.iter().filter(|(_, e)| e[0].1 == entries[0].1);
+            for (other, _) in same_hap {
+                assert_eq!(seeds[id], seeds[other]);
+            }
+        }
+        assert_ne!(seeds[&0], seeds[&1]);
+        let changed = rows.iter().filter(|(id, e)| seeds[id] != e[2].1 as usize);
+        assert!(0 < changed.count());
+    }
+    #[test]
+    fn triploid_test() {
+        // Three haplotypes. Site 1 has a cluster shared by two haplotypes.
+        let haps = [[0, 0, 1], [1, 0, 0], [2, 1, 0]];
+        let dosages = vec![vec![1, 1, 1], vec![2, 1], vec![2, 1]];
+        let rows: Vec<Row> = (0..12)
+            .map(|i| {
+                let hap = haps[i % 3];
+                let entries = (0..3).map(|s| (s, hap[s], 1f64)).collect();
+                (i as u64, entries)
+            })
+            .collect();
+        let result = phase_matrix(&dosages, &rows, &PhasingConfig::default());
+        assert_eq!(result.blocks.len(), 1);
+        assert!((result.blocks[0].confidence - 1f64).abs() < 0.001);
+        for i in 0..12 {
+            assert_eq!(result.reads[&i].1, result.reads[&(i % 3)].1);
+        }
+        let read_haps: HashSet<_> = (0..3).map(|i| result.reads[&i].1).collect();
+        assert_eq!(read_haps.len(), 3);
+        let site = |i: u64| result.sites.iter().find(|s| s.chunk == i).unwrap();
+        for (read, hap) in haps.iter().enumerate() {
+            let h = result.reads[&(read as u64)].1 - 1;
+            for (s, &cl) in hap.iter().enumerate() {
+                assert_eq!(site(s as u64).haplotypes[h], cl);
+            }
+        }
+        // The cluster shared by two haplotypes is carried by both of them.
+        let nodes = result.phased_nodes();
+        assert_eq!(nodes.len(), 7);
+        assert!(nodes
+            .windows(2)
+            .all(|w| (w[0].chunk, w[0].cluster) < (w[1].chunk, w[1].cluster)));
+        let shared = nodes
+            .iter()
+            .find(|n| n.chunk == 1 && n.cluster == 0)
+            .unwrap();
+        assert_eq!(shared.haplotypes.len(), 2);
+    }
+    #[test]
+    fn dosage_test() {
+        assert_eq!(dosage(&[10, 10], 2), vec![1, 1]);
+        assert_eq!(dosage(&[10, 20], 3), vec![1, 2]);
+        assert_eq!(dosage(&[10, 10, 30], 5), vec![1, 1, 3]);
+        assert_eq!(arrangements(&[2, 1]).len(), 3);
+        assert_eq!(arrangements(&[1, 1, 1]).len(), 6);
+    }
+}
@@ -204,7 +204,15 @@ fn correct_chunk(
         .iter()
         .find(|u| u.id == chunk_id)
         .unwrap();
-    let (assignments, k) = clustering(&reads, cluster_and_copynum, copy_numbers, chunk, config);
+    let seed = crate::misc::sub_seed(ds.seed, "phmm_likelihood_correction", chunk_id);
+    let (assignments, k) = clustering(
+        &reads,
+        cluster_and_copynum,
+        copy_numbers,
+        chunk,
+        config,
+        seed,
+    );
     let (adj_rand_index, raw) = adj_rand_on_biased(&reads, &assignments);
     debug!("ARI\t{}\t{k}\t{adj_rand_index:.3}\t{raw:.3}", chunk.id);
     assert_eq!(assignments.len(), reads.len());
@@ -265,6 +273,7 @@ fn clustering(
     copy_numbers: &[Vec<f64>],
     chunk: &Chunk,
     _: &CorrectionConfig,
+    seed: u64,
 ) -> (Vec<usize>, usize) {
     let id = chunk.id;
     let contexts: Vec<_> = reads.iter().map(to_context).collect();
@@ -297,7 +306,7 @@ fn clustering(
     normalize_columns(&mut eigens);
     use rand::SeedableRng;
     use rand_xoshiro::Xoroshiro128PlusPlus;
-    let mut rng = Xoroshiro128PlusPlus::seed_from_u64(id * k as u64);
+    let mut rng = Xoroshiro128PlusPlus::seed_from_u64(seed);
     let cluster_num = k.min(pick_k);
     let asn = (0..20)
         .map(|_| crate::misc::kmeans(&eigens, cluster_num, &mut rng))
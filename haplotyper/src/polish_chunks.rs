@@ -22,6 +22,8 @@ impl PolishChunkConfig {
         self.read_type
     }
 }
+// Nodes on the reads weighing less than this are not used in the consensus, if possible.
+const MIN_POLISH_WEIGHT: f64 = 0.5;
 /// Polishing chunks or Taking consensus.
 /// Note that after calling this function,
 /// all the encoded reads would be removed.
@@ -34,15 +36,17 @@ pub trait PolishChunk {
 
 impl PolishChunk for DataSet {
     fn polish_chunk(&mut self, c: &PolishChunkConfig) {
+        let weights = crate::estimate_error_rate::read_weights(self);
         let mut pileups: HashMap<_, Vec<_>> = self
             .selected_chunks
             .iter()
             .map(|chunk| (chunk.id, vec![]))
             .collect();
         for read in self.encoded_reads.iter_mut() {
+            let weight = weights.get(&read.id).copied().unwrap_or(1f64);
             for node in read.nodes.iter_mut() {
                 if let Some(res) = pileups.get_mut(&node.chunk) {
-                    res.push(node);
+                    res.push((weight, node));
                 }
             }
         }
@@ -50,23 +54,37 @@ impl PolishChunk for DataSet {
         let mut polished_nodes: HashMap<_, _> = pileups
             .into_par_iter()
             .filter(|x| c.filter_size < x.1.len())
-            .map(|(id, mut pileup)| {
+            .map(|(id, pileup)| {
                 let chunk = chunk_seqs.get(&id).unwrap();
                 let radius = c.read_type().band_width(chunk.seq().len());
-                pileup.sort_by_cached_key(|node| {
+                // Nodes on erroneous reads are left out of the consensus if enough nodes remain.
+                let (mut pileup, mut erroneous): (Vec<_>, Vec<_>) = pileup
+                    .into_iter()
+                    .partition(|&(weight, _)| MIN_POLISH_WEIGHT <= weight);
+                if pileup.len() <= c.filter_size {
+                    pileup.append(&mut erroneous);
+                }
+                pileup.sort_by_cached_key(|(_, node)| {
                     let (_, aln, _) = node.recover(chunk);
                     aln.iter().filter(|&&x| x != b'|').count()
                 });
                 let (seqs, mut ops): (Vec<_>, Vec<_>) = pileup
                     .iter()
-                    .map(|n| (n.seq(), crate::misc::ops_to_kiley(&n.cigar)))
+                    .map(|(_, n)| (n.seq(), crate::misc::ops_to_kiley(&n.cigar)))
                     .unzip();
                 use kiley::bialignment::guided::polish_until_converge_with;
                 let cons = polish_until_converge_with(chunk.seq(), &seqs, &mut ops, radius);
                 pileup
                     .iter_mut()
                     .zip(ops)
-                    .for_each(|(n, ops)| n.cigar = crate::misc::kiley_op_to_ops(&ops));
+                    .for_each(|((_, n), ops)| n.cigar = crate::misc::kiley_op_to_ops(&ops));
+                erroneous.iter_mut().for_each(|(_, node)| {
+                    let mode = edlib_sys::AlignMode::Global;
+                    let task = edlib_sys::AlignTask::Alignment;
+                    let aln = edlib_sys::align(node.seq(), &cons, mode, task);
+                    let ops = crate::misc::edlib_to_kiley(aln.operations().unwrap());
+                    node.cigar = crate::misc::kiley_op_to_ops(&ops);
+                });
                 (id, cons)
             })
             .collect();
@@ -1,15 +1,56 @@
+use crate::kmer_count::{CanonicalKmers, KmerCounts, KmerSet};
 use rayon::prelude::*;
-use std::collections::{HashMap, HashSet};
 #[derive(Debug, Clone)]
 pub struct RepeatMaskConfig {
     k: usize,
     freq: f64,
     min: u32,
+    // Tandem repeats with period up to this value are masked. If zero, no tandem repeat is masked.
+    max_period: usize,
+    // Tandem repeats with fewer copies than this value are not masked.
+    min_copies: f64,
+    // Windows with lower normalized entropy than this value are masked. If zero, nothing is masked.
+    min_entropy: f64,
+    // If some, the masked intervals are written to this path in BED format.
+    bed: Option<String>,
+    // K-mer size for the spectrum fitting. If zero, the spectrum is not fitted.
+    spectrum_k: usize,
+    // If some, the k-mer histogram and the fitted mixture are written to this path.
+    spectrum_tsv: Option<String>,
 }
 
 impl RepeatMaskConfig {
     pub fn new(k: usize, freq: f64, min: u32) -> Self {
-        Self { k, freq, min }
+        Self {
+            k,
+            freq,
+            min,
+            max_period: 0,
+            min_copies: 3f64,
+            min_entropy: 0f64,
+            bed: None,
+            spectrum_k: 0,
+            spectrum_tsv: None,
+        }
+    }
+    /// Also mask the tandem repeats with period up to `max_period` and at least `min_copies` copies.
+    pub fn set_tandem_repeat(&mut self, max_period: usize, min_copies: f64) {
+        self.max_period = max_period;
+        self.min_copies = min_copies;
+    }
+    /// Also mask the windows whose 3-mer entropy, normalized into [0,1], is less than `min_entropy`.
+    pub fn set_low_complexity(&mut self, min_entropy: f64) {
+        self.min_entropy = min_entropy;
+    }
+    /// Write the masked intervals of each read to `path` in BED format.
+    pub fn set_bed_output(&mut self, path: &str) {
+        self.bed = Some(path.to_string());
+    }
+    /// Also fit the spectrum of the `k`-mers to estimate the haploid coverage and the heterozygosity.
+    /// The histogram is written to `tsv`, if given.
+    pub fn set_kmer_spectrum(&mut self, k: usize, tsv: Option<&str>) {
+        self.spectrum_k = k;
+        self.spectrum_tsv = tsv.map(|x| x.to_string());
     }
 }
 
@@ -20,115 +61,48 @@ pub trait RepeatMask {
 
 #[derive(Debug, Clone)]
 pub struct RepeatAnnot {
-    k: usize,
-    kmers: HashSet<u64>,
-}
-
-#[derive(Debug, Clone)]
-pub struct KMers<'a> {
-    input: &'a [u8],
-    idx: usize,
-    forward: u64,
-    reverse: u64,
-    next: Option<u64>,
-    k: usize,
-}
-
-impl<'a> KMers<'a> {
-    fn new(input: &'a [u8], k: usize) -> Self {
-        assert!(k <= 32);
-        let forward: u64 = input
-            .iter()
-            .take(k)
-            .enumerate()
-            .map(|(i, &b)| BASE2BIT[b as usize] << (2 * i))
-            .sum();
-        let reverse = input
-            .iter()
-            .take(k)
-            .rev()
-            .enumerate()
-            .map(|(i, &b)| BASE2BITCMP[b as usize] << (2 * i))
-            .sum();
-        let next = (k <= input.len()).then_some(forward.min(reverse));
-        Self {
-            input,
-            idx: k,
-            forward,
-            reverse,
-            next,
-            k,
-        }
-    }
-}
-
-impl<'a> std::iter::Iterator for KMers<'a> {
-    type Item = u64;
-    fn next(&mut self) -> Option<Self::Item> {
-        let ret_value = self.next;
-        // Set up next value, if possible.
-        if let Some(&base) = self.input.get(self.idx) {
-            self.idx += 1;
-            self.forward = (self.forward >> 2) | (BASE2BIT[base as usize] << (2 * (self.k - 1)));
-            self.reverse = {
-                let mask = (1 << (2 * self.k)) - 1;
-                (mask & (self.reverse << 2)) | BASE2BITCMP[base as usize]
-            };
-            self.next = Some(self.forward.min(self.reverse));
-        } else {
-            self.next = None;
-        }
-        ret_value
-    }
+    kmers: KmerSet,
 }
 
 impl RepeatAnnot {
     /// Return the fraction of the repetitive kmer.
     /// Here, the definition of the repetitiveness is both global (is it lowercase?) and local (is it occurred more than twice in this reads?)
     pub fn repetitiveness(&self, seq: &[u8]) -> f64 {
-        let mut counts: HashMap<_, u32> = HashMap::new();
-        // for kmer in seq
-        //     .windows(self.k)
-        //     .map(to_idx)
-        //     .filter(|x| self.kmers.contains(x))
-        // {
-        //     *counts.entry(kmer).or_default() += 1;
-        // }
-        for kmer in KMers::new(seq, self.k).filter(|x| self.kmers.contains(x)) {
-            *counts.entry(kmer).or_default() += 1;
+        let k = self.kmers.k();
+        let mut rep_kmers: Vec<_> = CanonicalKmers::new(seq, k)
+            .filter(|&x| self.kmers.contains(x))
+            .collect();
+        rep_kmers.sort_unstable();
+        // The number of the occurrences of the k-mers occurring more than once.
+        let mut num_rep_kmers = 0;
+        let mut idx = 0;
+        while idx < rep_kmers.len() {
+            let count = rep_kmers[idx..]
+                .iter()
+                .take_while(|&&x| x == rep_kmers[idx])
+                .count();
+            if 1 < count {
+                num_rep_kmers += count;
+            }
+            idx += count;
         }
-        let rep_kmers: u32 = counts.values().filter(|&&count| 1 < count).sum();
-        let kmers = seq.len().saturating_sub(self.k) + 1;
-        rep_kmers as f64 / kmers as f64
+        let kmers = seq.len().saturating_sub(k) + 1;
+        num_rep_kmers as f64 / kmers as f64
     }
 }
 
 impl RepeatMask for definitions::DataSet {
     fn get_repetitive_kmer(&self) -> RepeatAnnot {
         let k = self.masked_kmers.k;
-        let kmers: HashSet<_> = self
+        // The k-mers consisting only of lowercase bases are the k-mers of the lowercase runs.
+        let lowercase_runs: Vec<_> = self
             .raw_reads
-            .par_iter()
-            .flat_map(|r| {
-                r.seq()
-                    .windows(k)
-                    .filter(|kmer| kmer.iter().all(u8::is_ascii_lowercase))
-                    .map(to_idx)
-                    .collect::<Vec<_>>()
-            })
+            .iter()
+            .flat_map(|r| r.seq().split(|b| !b.is_ascii_lowercase()))
+            .filter(|run| k <= run.len())
             .collect();
-        // let kmers: HashSet<_> = self
-        //     .raw_reads
-        //     .par_iter()
-        //     .flat_map(|r| {
-        //         r.seq()
-        //             .windows(k)
-        //             .filter(|kmer| kmer.iter().all(u8::is_ascii_lowercase))
-        //             .map(to_idx)
-        //             .collect::<Vec<_>>()
-        //     })
-        //     .collect();
-        RepeatAnnot { k, kmers }
+        let kmers = KmerCounts::new(&lowercase_runs, k).into_set(|_| true);
+        RepeatAnnot { kmers }
     }
     fn mask_repeat(&mut self, config: &RepeatMaskConfig) {
         self.masked_kmers.k = config.k;
@@ -138,13 +112,36 @@ impl RepeatMask for definitions::DataSet {
                 .iter_mut()
                 .for_each(u8::make_ascii_uppercase)
         });
+        if 0 < config.spectrum_k {
+            let tsv = config.spectrum_tsv.as_deref();
+            crate::kmer_spectrum::estimate_by_kmer_spectrum(self, config.spectrum_k, tsv);
+        }
         let (mask, _thr) = create_mask(&self.raw_reads, config);
         debug!("MASKREPEAT\tMaskLen\t{}\t{}", config.k, mask.len());
+        let intervals: Vec<_> = self
+            .raw_reads
+            .par_iter()
+            .map(|read| masked_intervals(read.seq(), &mask, config))
+            .collect();
         let num_lower_base: usize = self
             .raw_reads
             .par_iter_mut()
-            .map(|read| mask_repeats(read.seq.seq_mut(), &mask, config.k))
+            .zip(intervals.par_iter())
+            .map(|(read, intervals)| mask_intervals(read.seq.seq_mut(), intervals))
             .sum();
+        let count = |kind: fn(&MaskKind) -> bool| {
+            let intervals = intervals.iter().flatten().filter(|x| kind(&x.kind));
+            intervals.map(|x| x.end - x.start).sum::<usize>()
+        };
+        let tandem = count(|k| matches!(k, MaskKind::TandemRepeat { .. }));
+        let low_complexity = count(|k| matches!(k, MaskKind::LowComplexity));
+        debug!("MASKREPEAT\tTandemRepeat\t{tandem}");
+        debug!("MASKREPEAT\tLowComplexity\t{low_complexity}");
+        if let Some(path) = config.bed.as_ref() {
+            if let Err(why) = write_bed(path, &self.raw_reads, &intervals) {
+                warn!("{why:?},{path}");
+            }
+        }
         let num_bases = self.raw_reads.iter().map(|r| r.seq.len()).sum::<usize>();
         // let num_lower_base = self
         //     .raw_reads
@@ -157,40 +154,6 @@ impl RepeatMask for definitions::DataSet {
 }
 
 use definitions::*;
-pub fn kmer_counting(reads: &[RawRead], k: usize) -> HashMap<u64, u32> {
-    assert!(k <= 32);
-    reads
-        .par_iter()
-        .fold(HashMap::new, |mut counts, read| {
-            for kmer in KMers::new(read.seq(), k) {
-                *counts.entry(kmer).or_default() += 1;
-            }
-            counts
-        })
-        .reduce(HashMap::new, |mut x, y| {
-            for (key, val) in y {
-                *x.entry(key).or_default() += val;
-            }
-            x
-        })
-    // const BUCKET_SIZE: usize = 1000;
-    // let mut counts: HashMap<u64, u32> = HashMap::new();
-    // for bucket in reads.chunks(BUCKET_SIZE) {
-    //     let kmers: Vec<_> = bucket
-    //         .into_par_iter()
-    //         .fold(Vec::new, |mut x, read| {
-    //             x.extend(read.seq().windows(k).map(to_idx));
-    //             x
-    //         })
-    //         .flatten()
-    //         .collect();
-    //     for kmer in kmers {
-    //         *counts.entry(kmer).or_default() += 1;
-    //     }
-    // }
-    // counts
-}
-
 pub fn to_idx(w: &[u8]) -> u64 {
     let forward: u64 = w
         .iter()
@@ -250,76 +213,214 @@ const fn base2bit() -> [u64; 256] {
     slots
 }
 
-fn create_mask(reads: &[RawRead], config: &RepeatMaskConfig) -> (HashSet<u64>, u32) {
-    let mut kmercount = kmer_counting(reads, config.k);
-    let (mut num_singleton, total) = (0, kmercount.len());
-    kmercount.retain(|_, &mut count| {
-        num_singleton += (count == 1) as usize;
-        count != 1
-    });
+fn create_mask(reads: &[RawRead], config: &RepeatMaskConfig) -> (KmerSet, u32) {
+    let seqs: Vec<_> = reads.iter().map(|r| r.seq()).collect();
+    let mut kmercount = KmerCounts::new(&seqs, config.k);
+    let total = kmercount.len();
+    kmercount.retain(|count| count != 1);
+    let num_singleton = total - kmercount.len();
     debug!("MASKREPEAT\tUNIQUE\t{num_singleton}\t{total}");
     let percentile = (kmercount.len() as f64 * (1f64 - config.freq)).floor() as usize;
-    let below_min = kmercount.values().filter(|&&x| x <= config.min).count();
+    let below_min = kmercount
+        .counts()
+        .iter()
+        .filter(|&&x| x <= config.min)
+        .count();
     if percentile < below_min {
         warn!("MASKREPEAT\tTakesAll\t{}\t{}", config.min, config.freq);
-        let kmers: HashSet<_> = kmercount.keys().copied().collect();
-        (kmers, 0)
+        (kmercount.into_set(|_| true), 0)
     } else {
         let percentile = percentile - below_min;
         let mut counts: Vec<_> = kmercount
-            .values()
+            .counts()
+            .iter()
             .filter(|&&count| config.min < count)
             .copied()
             .collect();
         counts.sort_unstable();
         let thr = counts[percentile];
         debug!("MASKREPEAT\tMaskMoreThan\t{thr}\t{}", config.k);
-        let kmers: HashSet<_> = kmercount
-            .iter()
-            .filter_map(|(&key, &val)| if val > thr { Some(key) } else { None })
-            .collect();
-        (kmers, thr)
+        (kmercount.into_set(|count| count > thr), thr)
     }
 }
 
-fn mask_repeats(seq: &mut [u8], mask: &HashSet<u64>, k: usize) -> usize {
-    let (mut start, mut end) = (0, 0);
-    let mut mask_ranges = vec![];
-    for (idx, kmer) in KMers::new(seq, k).enumerate() {
-        if mask.contains(&kmer) {
-            if end < idx {
-                mask_ranges.push((start, end));
-                start = idx;
-                end = idx + k;
-            } else {
-                end = idx + k;
+/// An interval `[start, end)` of a read to be masked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaskedInterval {
+    pub start: usize,
+    pub end: usize,
+    pub kind: MaskKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaskKind {
+    /// Consists of the frequent k-mers.
+    Kmer,
+    TandemRepeat {
+        period: usize,
+        copies: f64,
+    },
+    LowComplexity,
+}
+
+impl std::fmt::Display for MaskKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MaskKind::Kmer => write!(f, "kmer"),
+            MaskKind::TandemRepeat { period, copies } => write!(f, "TR:{period}x{copies:.1}"),
+            MaskKind::LowComplexity => write!(f, "LC"),
+        }
+    }
+}
+
+fn masked_intervals(seq: &[u8], mask: &KmerSet, config: &RepeatMaskConfig) -> Vec<MaskedInterval> {
+    let mut intervals: Vec<_> = kmer_mask_ranges(seq, mask, config.k)
+        .into_iter()
+        .map(|(start, end)| MaskedInterval {
+            start,
+            end,
+            kind: MaskKind::Kmer,
+        })
+        .collect();
+    if 0 < config.max_period {
+        let repeats = tandem_repeats(seq, config.max_period, config.min_copies);
+        intervals.extend(repeats);
+    }
+    if 0f64 < config.min_entropy {
+        let low_complexity = low_complexity_ranges(seq, config.min_entropy);
+        intervals.extend(
+            low_complexity
+                .into_iter()
+                .map(|(start, end)| MaskedInterval {
+                    start,
+                    end,
+                    kind: MaskKind::LowComplexity,
+                }),
+        );
+    }
+    intervals.sort_by_key(|x| (x.start, x.end));
+    intervals
+}
+
+// Lowercase the intervals and return the number of the bases masked.
+fn mask_intervals(seq: &mut [u8], intervals: &[MaskedInterval]) -> usize {
+    for interval in intervals.iter() {
+        seq[interval.start..interval.end]
+            .iter_mut()
+            .for_each(u8::make_ascii_lowercase);
+    }
+    seq.iter().filter(|x| x.is_ascii_lowercase()).count()
+}
+
+fn write_bed(
+    path: &str,
+    reads: &[RawRead],
+    intervals: &[Vec<MaskedInterval>],
+) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut wtr = std::fs::File::create(path).map(std::io::BufWriter::new)?;
+    for (read, intervals) in std::iter::zip(reads, intervals) {
+        for interval in intervals.iter() {
+            let (start, end) = (interval.start, interval.end);
+            writeln!(wtr, "{}\t{start}\t{end}\t{}", read.name, interval.kind)?;
+        }
+    }
+    Ok(())
+}
+
+fn kmer_mask_ranges(seq: &[u8], mask: &KmerSet, k: usize) -> Vec<(usize, usize)> {
+    let mut mask_ranges: Vec<(usize, usize)> = vec![];
+    for (idx, kmer) in CanonicalKmers::new(seq, k).enumerate() {
+        if mask.contains(kmer) {
+            match mask_ranges.last_mut() {
+                Some((_, end)) if idx <= *end => *end = idx + k,
+                _ => mask_ranges.push((idx, idx + k)),
+            }
+        }
+    }
+    mask_ranges
+}
+
+// Scores to extend a tandem repeat, comparing each base to the base one period downstream.
+const TR_MATCH: i64 = 1;
+const TR_MISMATCH: i64 = -2;
+const TR_XDROP: i64 = 10;
+// Tandem repeats shorter than this value are not reported.
+const TR_MIN_LEN: usize = 20;
+
+/// Find the tandem repeats with period up to `max_period` and at least `min_copies` copies.
+/// The repeats are allowed to have a few errors. If repeats overlap more than half,
+/// the one with the shortest period is reported.
+pub fn tandem_repeats(seq: &[u8], max_period: usize, min_copies: f64) -> Vec<MaskedInterval> {
+    let mut repeats: Vec<MaskedInterval> = vec![];
+    for period in 1..=max_period {
+        for (start, end) in tandem_repeats_of_period(seq, period) {
+            let copies = (end - start) as f64 / period as f64;
+            if end - start < TR_MIN_LEN || copies < min_copies {
+                continue;
+            }
+            let overlap: usize = repeats
+                .iter()
+                .map(|r| r.end.min(end).saturating_sub(r.start.max(start)))
+                .sum();
+            if 2 * overlap < end - start {
+                let kind = MaskKind::TandemRepeat { period, copies };
+                repeats.push(MaskedInterval { start, end, kind });
             }
         }
     }
-    mask_ranges.push((start, end));
-    let num_lower = mask_ranges.iter().map(|(s, e)| e - s).sum();
-    for (start, end) in mask_ranges {
-        seq.iter_mut()
-            .take(end)
-            .skip(start)
-            .for_each(|s| s.make_ascii_lowercase());
+    repeats
+}
+
+fn tandem_repeats_of_period(seq: &[u8], period: usize) -> Vec<(usize, usize)> {
+    let len = seq.len().saturating_sub(period);
+    let is_match = |i: usize| seq[i].eq_ignore_ascii_case(&seq[i + period]);
+    let mut repeats = vec![];
+    let mut start = 0;
+    while start < len {
+        let (mut score, mut max, mut end) = (0, 0, start);
+        let mut i = start;
+        while i < len && 0 <= score && max - score < TR_XDROP {
+            score += if is_match(i) { TR_MATCH } else { TR_MISMATCH };
+            i += 1;
+            if max < score {
+                max = score;
+                end = i;
+            }
+        }
+        if end == start {
+            start += 1;
+        } else {
+            repeats.push((start, end + period));
+            start = end;
+        }
     }
-    // if seq.len() <= k {
-    //     return 0;
-    // }
-    // let mut num_lower = 0;
-    // let mut farthest = 0;
-    // for idx in 0..seq.len() - k + 1 {
-    //     let kmer_position = to_idx(&seq[idx..idx + k]);
-    //     if mask.contains(&kmer_position) {
-    //         for seq in seq.iter_mut().take(idx + k).skip(farthest.max(idx)) {
-    //             seq.make_ascii_lowercase();
-    //             num_lower += 1;
-    //         }
-    //         farthest = idx + k - 1;
-    //     }
-    // }
-    num_lower
+    repeats
+}
+
+// The size of the windows and the k-mers to compute the entropy of the sequence.
+const LC_WINDOW: usize = 64;
+const LC_K: usize = 3;
+/// Return the merged windows whose entropy of the canonical 3-mers, divided by the maximum possible value,
+/// is less than `min_entropy`.
+pub fn low_complexity_ranges(seq: &[u8], min_entropy: f64) -> Vec<(usize, usize)> {
+    // There are 32 canonical 3-mers.
+    let max_entropy = ((LC_WINDOW - LC_K + 1).min(32) as f64).ln();
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    if seq.len() < LC_WINDOW {
+        return ranges;
+    }
+    for start in (0..seq.len() - LC_WINDOW + 1).step_by(LC_WINDOW / 2) {
+        let end = start + LC_WINDOW;
+        let entropy = crate::misc::entropy(&seq[start..end], LC_K) / max_entropy;
+        if entropy < min_entropy {
+            match ranges.last_mut() {
+                Some((_, prev_end)) if start <= *prev_end => *prev_end = end,
+                _ => ranges.push((start, end)),
+            }
+        }
+    }
+    ranges
 }
 
 #[cfg(test)]
@@ -328,43 +429,41 @@ pub mod test {
     use rand::seq::SliceRandom;
     use rand::SeedableRng;
     use rand_xoshiro::Xoroshiro128Plus;
+    fn random_seq<R: rand::Rng>(rng: &mut R, len: usize) -> Vec<u8> {
+        (0..len).map(|_| *b"ACGT".choose(rng).unwrap()).collect()
+    }
     #[test]
-    fn kmers() {
-        let kmers = b"AAAA";
-        let mut kmers_iter = KMers::new(kmers, 1);
-        assert_eq!(kmers_iter.clone().count(), 4);
-        assert!(kmers_iter.all(|x| x == 0));
-        assert!(KMers::new(kmers, 2).all(|x| x == 0));
-        let kmers = b"TTTT";
-        let mut kmers_iter = KMers::new(kmers, 1);
-        assert_eq!(kmers_iter.clone().count(), 4);
-        assert!(kmers_iter.all(|x| x == 0));
-        assert!(KMers::new(kmers, 2).all(|x| x == 0));
-
-        let kmers = b"CAGTGCAT";
-        let k = 2;
-        let kmers_iter = KMers::new(kmers, k);
-        println!("INIT\t{}\t{}", kmers_iter.forward, kmers_iter.reverse);
-        for (i, kmer) in kmers_iter.enumerate() {
-            assert!(kmer < (1 << (2 * k)));
-            let input = &kmers[i..i + k];
-            let seq = String::from_utf8_lossy(input);
-            assert_eq!(kmer, to_idx(input), "{},{:0b},{}", i, kmer, seq);
-        }
-
-        let mut rng: Xoroshiro128Plus = SeedableRng::seed_from_u64(832904);
-        let len = 1000;
-        let kmers: Vec<u8> = (0..len)
-            .map(|_| *b"ACGT".choose(&mut rng).unwrap())
-            .collect();
-        let k = 10;
-        let kmers_iter = KMers::new(&kmers, k);
-        assert_eq!(kmers_iter.clone().count(), len - k + 1);
-        for (i, kmer) in kmers_iter.enumerate() {
-            assert!(kmer < (1 << (2 * k)));
-            let input = &kmers[i..i + k];
-            let seq = String::from_utf8_lossy(input);
-            assert_eq!(kmer, to_idx(input), "{},{:0b},{}", i, kmer, seq);
+    fn tandem_repeats_test() {
+        let mut rng: Xoroshiro128Plus = SeedableRng::seed_from_u64(4829);
+        let mut seq = random_seq(&mut rng, 500);
+        let unit = b"ACGTTGA";
+        seq.extend(std::iter::repeat(unit).take(10).flatten());
+        // An error in the repeat.
+        seq[503] = b'C';
+        seq.extend(random_seq(&mut rng, 500));
+        let repeats = tandem_repeats(&seq, 10, 3f64);
+        assert_eq!(repeats.len(), 1, "{repeats:?}");
+        let repeat = &repeats[0];
+        assert!(repeat.start.abs_diff(500) < 5 && repeat.end.abs_diff(570) < 5);
+        match repeat.kind {
+            MaskKind::TandemRepeat { period, copies } => {
+                assert_eq!(period, unit.len());
+                assert!((copies - 10f64).abs() < 1f64);
+            }
+            _ => panic!(),
         }
+        let seq = random_seq(&mut rng, 2000);
+        assert!(tandem_repeats(&seq, 10, 3f64).is_empty());
+    }
+    #[test]
+    fn low_complexity_test() {
+        let mut rng: Xoroshiro128Plus = SeedableRng::seed_from_u64(4829);
+        let mut seq = random_seq(&mut rng, 512);
+        seq.extend(std::iter::repeat(b"AAAAAAAC").take(32).flatten());
+        seq.extend(random_seq(&mut rng, 512));
+        let ranges = low_complexity_ranges(&seq, 0.6);
+        assert_eq!(ranges.len(), 1, "{ranges:?}");
+        let (start, end) = ranges[0];
+        assert!(480 <= start && end <= 800, "{},{}", start, end);
     }
 }
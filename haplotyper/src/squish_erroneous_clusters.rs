@@ -95,7 +95,7 @@ fn classify_chunks(ds: &DataSet, config: &SquishConfig) -> HashMap<u64, RelClass
         .collect();
     chunk_pairs.retain(|_, val| config.count_thr < *val);
     chunk_pairs.retain(|(u1, u2), _| 1 < chunks[u1] && 1 < chunks[u2]);
-    let adj_rand_indices: Vec<_> = chunk_pairs
+    let mut adj_rand_indices: Vec<_> = chunk_pairs
         .par_iter()
         .map(|(&(u1, u2), _)| {
             let (cl1, cl2) = (chunks[&u1], chunks[&u2]);
@@ -103,13 +103,15 @@ fn classify_chunks(ds: &DataSet, config: &SquishConfig) -> HashMap<u64, RelClass
             (u1, u2, (rel, count))
         })
         .collect();
+    // Fix the order, as the node indices in the classification depend on it.
+    adj_rand_indices.sort_unstable_by_key(|&(u1, u2, _)| (u1, u2));
     let mut touch_chunks: HashMap<_, Vec<_>> = HashMap::new();
     for (&(u1, u2), _) in chunk_pairs.iter() {
         touch_chunks.entry(u1).or_default().push(u2);
     }
     let stiff_chunks = match adj_rand_indices.is_empty() {
         true => HashSet::new(),
-        false => classify(&adj_rand_indices, config),
+        false => classify(&adj_rand_indices, config, ds.seed),
     };
     // let stiff_chunks = classify_chunks_by_graph(ds, config);
     // ds.selected_chunks
@@ -251,7 +253,11 @@ fn check_correl(
     (rel_value, c1.len())
 }
 
-fn classify(adj_rand_indices: &[(u64, u64, (f64, usize))], config: &SquishConfig) -> HashSet<u64> {
+fn classify(
+    adj_rand_indices: &[(u64, u64, (f64, usize))],
+    config: &SquishConfig,
+    seed: u64,
+) -> HashSet<u64> {
     let mut nodes: HashMap<_, usize> = HashMap::new();
     for &(from, to, _) in adj_rand_indices.iter() {
         let len = nodes.len();
@@ -267,7 +273,8 @@ fn classify(adj_rand_indices: &[(u64, u64, (f64, usize))], config: &SquishConfig
         graph[to].push((from, ari, count));
     }
     let param = ClassifyParam::new(config.ari_thr, config.mismatch_score, config.match_score);
-    let assignments = classify_nodes(&graph, nodes.len(), &param);
+    let seed = crate::misc::sub_seed(seed, "squish", 0);
+    let assignments = classify_nodes(&graph, nodes.len(), &param, seed);
     nodes
         .iter()
         .filter_map(|(&uid, &node)| assignments[node].then_some(uid))
@@ -278,9 +285,9 @@ type RelGraph = Vec<Vec<(usize, f64, usize)>>;
 
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
-fn classify_nodes(graph: &RelGraph, nodes: usize, param: &ClassifyParam) -> Vec<bool> {
+fn classify_nodes(graph: &RelGraph, nodes: usize, param: &ClassifyParam, seed: u64) -> Vec<bool> {
     let mut assignments = vec![true; nodes];
-    let mut rng: Xoshiro256PlusPlus = SeedableRng::seed_from_u64(3093240);
+    let mut rng: Xoshiro256PlusPlus = SeedableRng::seed_from_u64(seed);
     for _t in 0..10 {
         wipe_through(graph, &mut assignments, param);
         mcmc(graph, &mut assignments, param, &mut rng);
@@ -4,6 +4,9 @@ use rayon::prelude::*;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+// Removed chunks with metrics within this fraction of the thresholds are counted as marginal.
+const MARGINAL_FRAC: f64 = 0.1;
+
 pub trait Stats {
     fn error_rate(&self) -> ErrorRate;
     fn stats<W: std::io::Write>(&self, wtr: W) -> std::io::Result<()>;
@@ -52,11 +55,12 @@ impl Stats for definitions::DataSet {
                 .iter()
                 .map(|r| r.seq1().len() + r.seq2().len());
             let sum = lens.clone().sum::<usize>();
-            let len = self.raw_reads.len() * 2;
+            let len = self.hic_pairs.len() * 2;
             let ave = sum / len;
-            writeln!(wtr, "HICREADS\tNumOfReads\t{sum}")?;
-            writeln!(wtr, "HICREADS\tTotalLength\t{len}")?;
+            writeln!(wtr, "HICREADS\tNumOfReads\t{len}")?;
+            writeln!(wtr, "HICREADS\tTotalLength\t{sum}")?;
             writeln!(wtr, "HICREADS\tMeanLength\t{ave}")?;
+            writeln!(wtr, "HICREADS\tNumOfEdges\t{}", self.hic_edges.len())?;
         }
         // Selected chunks
         if !self.selected_chunks.is_empty() {
@@ -68,6 +72,70 @@ impl Stats for definitions::DataSet {
             writeln!(wtr, "CHUNKS\tNumOfUnits\t{len}")?;
             writeln!(wtr, "CHUNKS\tMeanLength\t{ave}")?;
         }
+        // Chunks removed during the selection.
+        if !self.removed_chunks.is_empty() {
+            let mut reasons: HashMap<_, Vec<_>> = HashMap::new();
+            for removed in self.removed_chunks.iter() {
+                reasons.entry(removed.reason).or_default().push(removed);
+            }
+            let mut reasons: Vec<_> = reasons.into_iter().collect();
+            reasons.sort_by_key(|x| x.0);
+            for (reason, removed) in reasons.iter() {
+                let len = removed.len();
+                let sum = removed.iter().map(|r| r.seq().len()).sum::<usize>();
+                let mut values: Vec<_> = removed.iter().map(|r| r.value).collect();
+                values.sort_by(|x, y| x.partial_cmp(y).unwrap());
+                let median = values[len / 2];
+                // Removed by a narrow margin. These might be real unique sequences.
+                let marginal = removed
+                    .iter()
+                    .filter(|r| (r.value - r.threshold).abs() <= MARGINAL_FRAC * r.threshold)
+                    .count();
+                writeln!(wtr, "REMOVED\t{reason}\tNumOfUnits\t{len}")?;
+                writeln!(wtr, "REMOVED\t{reason}\tTotalLength\t{sum}")?;
+                writeln!(wtr, "REMOVED\t{reason}\tMedianValue\t{median:.3}")?;
+                writeln!(wtr, "REMOVED\t{reason}\tMarginal\t{marginal}")?;
+            }
+            // The chunks losing some occurrences by the large deletions are still selected.
+            let removed_len = self
+                .removed_chunks
+                .iter()
+                .filter(|r| !r.reason.keeps_chunk())
+                .map(|r| r.seq().len())
+                .sum::<usize>();
+            let selected_len = self
+                .selected_chunks
+                .iter()
+                .map(|c| c.seq().len())
+                .sum::<usize>();
+            let removed_rate = removed_len as f64 / (removed_len + selected_len) as f64;
+            writeln!(wtr, "REMOVED\tTotalLength\t{removed_len}")?;
+            writeln!(wtr, "REMOVED\tRemovedRate\t{removed_rate:.4}")?;
+        }
+        // Tiling of the reference by the chunks.
+        let positions: Vec<_> = self
+            .selected_chunks
+            .iter()
+            .filter_map(|c| c.reference.as_ref())
+            .collect();
+        if !positions.is_empty() {
+            // Spacers shorter than a chunk can not lack any chunk, and are not gaps.
+            let mut lens: Vec<_> = positions.iter().map(|p| p.end - p.start).collect();
+            let min_gap = *lens.select_nth_unstable(positions.len() / 2).1;
+            let (covered, spanned, gaps) = reference_tiling(&positions, min_gap);
+            let contigs: HashSet<_> = positions.iter().map(|p| &p.contig).collect();
+            let gap_len = gaps.iter().sum::<usize>();
+            let max_gap = gaps.iter().max().unwrap_or(&0);
+            writeln!(wtr, "REFERENCE\tNumOfContigs\t{}", contigs.len())?;
+            writeln!(wtr, "REFERENCE\tLocatedUnits\t{}", positions.len())?;
+            writeln!(wtr, "REFERENCE\tCoveredLength\t{covered}")?;
+            writeln!(wtr, "REFERENCE\tSpannedLength\t{spanned}")?;
+            let cover_rate = covered as f64 / spanned as f64;
+            writeln!(wtr, "REFERENCE\tCoverRate\t{cover_rate:.4}")?;
+            writeln!(wtr, "REFERENCE\tNumOfGaps\t{}", gaps.len())?;
+            writeln!(wtr, "REFERENCE\tGapLength\t{gap_len}")?;
+            writeln!(wtr, "REFERENCE\tMaxGap\t{max_gap}")?;
+        }
         // Encoded Reads
         if !self.encoded_reads.is_empty() {
             let reads: HashSet<_> = self
@@ -180,6 +248,39 @@ impl Stats for definitions::DataSet {
     }
 }
 
+// Return the length of the reference covered by the chunks, the length between the first and the last
+// chunk of each reference sequence, and the lengths of the uncovered regions longer than `min_gap` in between.
+fn reference_tiling(
+    positions: &[&ReferencePosition],
+    min_gap: usize,
+) -> (usize, usize, Vec<usize>) {
+    let mut intervals: HashMap<_, Vec<_>> = HashMap::new();
+    for pos in positions.iter() {
+        intervals
+            .entry(&pos.contig)
+            .or_default()
+            .push((pos.start, pos.end));
+    }
+    let (mut covered, mut spanned, mut gaps) = (0, 0, vec![]);
+    for intervals in intervals.values_mut() {
+        intervals.sort_unstable();
+        let (mut start, mut end) = intervals[0];
+        spanned += intervals.iter().map(|x| x.1).max().unwrap() - start;
+        for &(s, e) in intervals.iter().skip(1) {
+            if end < s {
+                covered += end - start;
+                if min_gap < s - end {
+                    gaps.push(s - end);
+                }
+                start = s;
+            }
+            end = end.max(e);
+        }
+        covered += end - start;
+    }
+    (covered, spanned, gaps)
+}
+
 //Return median and median absolute deviation.
 fn summarize<I: std::iter::Iterator<Item = f64>>(error_rates: I) -> (f64, f64) {
     let mut errors: Vec<_> = error_rates.collect();
@@ -210,3 +311,33 @@ fn up_to(lens: &[usize], frac: f64) -> usize {
     }
     panic!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn reference_tiling_test() {
+        let pos = |contig: &str, start, end| ReferencePosition {
+            contig: contig.to_string(),
+            start,
+            end,
+            strand: true,
+            identity: 1f64,
+        };
+        // Small margins between the chunks, a 5kbp gap, and an overlap.
+        let positions = [
+            pos("chr1", 0, 2_000),
+            pos("chr1", 2_050, 4_000),
+            pos("chr1", 9_000, 11_000),
+            pos("chr1", 10_900, 13_000),
+            pos("chr2", 100, 2_100),
+        ];
+        let positions: Vec<_> = positions.iter().collect();
+        let (covered, spanned, gaps) = reference_tiling(&positions, 2_000);
+        assert_eq!(covered, 2_000 + 1_950 + 4_000 + 2_000);
+        assert_eq!(spanned, 13_000 + 2_000);
+        assert_eq!(gaps, vec![5_000]);
+        let (_, _, gaps) = reference_tiling(&positions, 0);
+        assert_eq!(gaps.len(), 2);
+    }
+}
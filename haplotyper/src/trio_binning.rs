@@ -0,0 +1,259 @@
+//! Trio-binning by parental k-mers.
+//!
+//! The k-mers of the parental short reads are counted into sorted arrays by [`crate::kmer_count`].
+//! The solid k-mers found only in the mother (father) are the maternal (paternal) hap-mers, and each read
+//! is labelled by the hap-mers it contains. The labels are stored in [`DataSet::parental_labels`], and
+//! - seed the local clustering, so that the reads from the same parent tend to be in the same cluster,
+//! - prevent the repeat resolution from joining maternal and paternal nodes, and
+//! - tag the contigs with their parental origin (`HP:Z:maternal` or `HP:Z:paternal`).
+use crate::kmer_count::{CanonicalKmers, KmerCounts, KmerSet};
+use definitions::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+// The number of the sequences counted at once.
+const BATCH_SIZE: usize = 100_000;
+
+#[derive(Debug, Clone)]
+pub struct TrioBinningConfig {
+    k: usize,
+    // K-mers occurring less than this value in a parent are regarded as sequencing errors.
+    min_count: u32,
+    // A read needs at least this many hap-mers to be labelled.
+    min_hapmers: u32,
+    // ... and the (normalized) count of the major parent should be this times larger than the other.
+    min_ratio: f64,
+}
+
+impl std::default::Default for TrioBinningConfig {
+    fn default() -> Self {
+        Self {
+            k: 21,
+            min_count: 3,
+            min_hapmers: 5,
+            min_ratio: 2f64,
+        }
+    }
+}
+
+impl TrioBinningConfig {
+    pub fn new(k: usize, min_count: u32, min_hapmers: u32, min_ratio: f64) -> Self {
+        Self {
+            k,
+            min_count,
+            min_hapmers,
+            min_ratio,
+        }
+    }
+}
+
+/// The haplotype-specific k-mers of the parents.
+#[derive(Debug, Clone)]
+pub struct ParentalKmers {
+    k: usize,
+    maternal: KmerSet,
+    paternal: KmerSet,
+}
+
+impl ParentalKmers {
+    /// Count the k-mers in the FASTA/FASTQ files of each parent, and keep the solid k-mers specific to each of them.
+    pub fn from_files(
+        maternal: &[String],
+        paternal: &[String],
+        config: &TrioBinningConfig,
+    ) -> std::io::Result<Self> {
+        let maternal = solid_kmers(maternal, config)?;
+        let paternal = solid_kmers(paternal, config)?;
+        Ok(Self::from_solid_kmers(config.k, maternal, paternal))
+    }
+    /// Hap-mers are the solid k-mers found only in one of the parents.
+    pub fn from_solid_kmers(k: usize, maternal: KmerSet, paternal: KmerSet) -> Self {
+        let only_maternal = maternal.difference(&paternal);
+        let only_paternal = paternal.difference(&maternal);
+        debug!(
+            "TRIO\tHAPMERS\t{}\t{}",
+            only_maternal.len(),
+            only_paternal.len()
+        );
+        Self {
+            k,
+            maternal: only_maternal,
+            paternal: only_paternal,
+        }
+    }
+    /// The number of the maternal and paternal hap-mers in `seq`.
+    pub fn count(&self, seq: &[u8]) -> (u32, u32) {
+        CanonicalKmers::new(seq, self.k).fold((0, 0), |(mat, pat), kmer| {
+            let mat = mat + self.maternal.contains(kmer) as u32;
+            let pat = pat + self.paternal.contains(kmer) as u32;
+            (mat, pat)
+        })
+    }
+    /// Label `seq` by its hap-mers. None if it does not have enough hap-mers or they are ambiguous.
+    pub fn classify(&self, seq: &[u8], config: &TrioBinningConfig) -> Option<ParentalLabel> {
+        let (maternal, paternal) = self.count(seq);
+        // Normalize by the number of the hap-mers, as in TrioCanu.
+        let mat_score = maternal as f64 / self.maternal.len().max(1) as f64;
+        let pat_score = paternal as f64 / self.paternal.len().max(1) as f64;
+        let parent = if config.min_hapmers <= maternal && pat_score * config.min_ratio < mat_score {
+            Parent::Maternal
+        } else if config.min_hapmers <= paternal && mat_score * config.min_ratio < pat_score {
+            Parent::Paternal
+        } else {
+            return None;
+        };
+        Some(ParentalLabel {
+            id: 0,
+            parent,
+            maternal,
+            paternal,
+        })
+    }
+}
+
+pub trait TrioBinning {
+    /// Label the reads by the parental hap-mers.
+    fn trio_binning(&mut self, kmers: &ParentalKmers, config: &TrioBinningConfig);
+}
+
+impl TrioBinning for DataSet {
+    fn trio_binning(&mut self, kmers: &ParentalKmers, config: &TrioBinningConfig) {
+        self.parental_labels = self
+            .raw_reads
+            .par_iter()
+            .filter_map(|read| {
+                let seq = read.seq().to_ascii_uppercase();
+                let mut label = kmers.classify(&seq, config)?;
+                label.id = read.id;
+                Some(label)
+            })
+            .collect();
+        self.parental_labels.sort_by_key(|l| l.id);
+        let maternal = self
+            .parental_labels
+            .iter()
+            .filter(|l| l.parent == Parent::Maternal)
+            .count();
+        let paternal = self.parental_labels.len() - maternal;
+        let total = self.raw_reads.len();
+        debug!("TRIO\tLABELS\t{maternal}\t{paternal}\t{total}");
+    }
+}
+
+/// Read ID -> the parent.
+pub fn read_parents(ds: &DataSet) -> HashMap<u64, Parent> {
+    ds.parental_labels
+        .iter()
+        .map(|l| (l.id, l.parent))
+        .collect()
+}
+
+// The number of the labelled reads needed to determine the parent of a node/contig.
+const MIN_LABELLED: usize = 3;
+// The fraction of the major parent needed to determine the parent of a node/contig.
+const MIN_MAJORITY: f64 = 0.8;
+
+/// The parent of a set of reads (e.g., the reads on a node), if most of the labelled ones agree.
+pub fn majority<I: std::iter::IntoIterator<Item = Parent>>(parents: I) -> Option<Parent> {
+    let (maternal, paternal) = parents.into_iter().fold((0, 0), |(m, p), x| match x {
+        Parent::Maternal => (m + 1, p),
+        Parent::Paternal => (m, p + 1),
+    });
+    majority_of_counts(maternal, paternal)
+}
+
+/// Same as [`majority`], but from the number of the maternal and paternal reads.
+pub fn majority_of_counts(maternal: usize, paternal: usize) -> Option<Parent> {
+    let total = maternal + paternal;
+    if total < MIN_LABELLED {
+        None
+    } else if MIN_MAJORITY * total as f64 <= maternal as f64 {
+        Some(Parent::Maternal)
+    } else if MIN_MAJORITY * total as f64 <= paternal as f64 {
+        Some(Parent::Paternal)
+    } else {
+        None
+    }
+}
+
+fn solid_kmers(files: &[String], config: &TrioBinningConfig) -> std::io::Result<KmerSet> {
+    let mut counts = KmerCounts::empty(config.k);
+    for file in files {
+        debug!("TRIO\tCOUNT\t{file}");
+        for_each_batch(file, |batch| counts.add(batch))?;
+    }
+    let solid = counts.into_set(|count| config.min_count <= count);
+    debug!("TRIO\tSOLID\t{}", solid.len());
+    Ok(solid)
+}
+
+// Read a FASTA or FASTQ file and call `f` on every `BATCH_SIZE` sequences (uppercased).
+fn for_each_batch<F: FnMut(&[Vec<u8>])>(file: &str, mut f: F) -> std::io::Result<()> {
+    use std::io::BufRead;
+    let rdr = std::fs::File::open(file).map(std::io::BufReader::new)?;
+    let mut lines = rdr.lines();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut seq: Vec<u8> = vec![];
+    while let Some(line) = lines.next() {
+        let line = line?;
+        if line.starts_with('@') {
+            // FASTQ: header, sequence, separator, and quality.
+            if let Some(line) = lines.next() {
+                batch.push(line?.to_ascii_uppercase().into_bytes());
+            }
+            lines.next().transpose()?;
+            lines.next().transpose()?;
+        } else if line.starts_with('>') {
+            if !seq.is_empty() {
+                batch.push(std::mem::take(&mut seq));
+            }
+        } else {
+            seq.extend(line.trim().to_ascii_uppercase().bytes());
+        }
+        if BATCH_SIZE <= batch.len() {
+            f(&batch);
+            batch.clear();
+        }
+    }
+    if !seq.is_empty() {
+        batch.push(seq);
+    }
+    if !batch.is_empty() {
+        f(&batch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn classify_test() {
+        let k = 5;
+        let maternal_seq = b"ACGTTGCAAGT";
+        let paternal_seq = b"TTTGGGCCCAT";
+        let shared: &[u8] = b"CCCCCAAAAA";
+        let kmers = |seq: &[u8]| KmerCounts::new(&[seq, shared], k).into_set(|_| true);
+        let (maternal, paternal) = (kmers(maternal_seq), kmers(paternal_seq));
+        let hapmers = ParentalKmers::from_solid_kmers(k, maternal, paternal);
+        let config = TrioBinningConfig::new(k, 1, 3, 2f64);
+        let label = hapmers.classify(maternal_seq, &config).unwrap();
+        assert_eq!(label.parent, Parent::Maternal);
+        assert_eq!(label.paternal, 0);
+        let rev = bio_utils::revcmp(paternal_seq);
+        let label = hapmers.classify(&rev, &config).unwrap();
+        assert_eq!(label.parent, Parent::Paternal);
+        assert!(hapmers.classify(b"CCCCCAAAAA", &config).is_none());
+    }
+    #[test]
+    fn majority_test() {
+        use Parent::*;
+        assert_eq!(majority([Maternal, Maternal]), None);
+        assert_eq!(majority([Maternal; 5]), Some(Maternal));
+        assert_eq!(majority([Maternal, Paternal, Paternal, Paternal]), None);
+        assert_eq!(
+            majority([Maternal, Paternal, Paternal, Paternal, Paternal]),
+            Some(Paternal)
+        );
+    }
+}